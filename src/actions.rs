@@ -0,0 +1,289 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{error::SfResult, SfApiError, SfClient, SfResponse};
+
+#[derive(Debug, Serialize)]
+struct ActionInputs<I> {
+    inputs: Vec<I>,
+}
+
+// Action errors report a status code rather than the error code of other resources
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionError {
+    pub status_code: String,
+    pub message: String,
+    #[serde(default)]
+    pub fields: Vec<String>,
+}
+
+/// The result of a single invocation of an action
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionResult<O> {
+    pub action_name: String,
+    #[serde(default)]
+    pub errors: Option<Vec<ActionError>>,
+    pub is_success: bool,
+    #[serde(default = "Option::default")]
+    pub output_values: Option<O>,
+}
+
+/// A quick action that is available for an object, including global actions
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuickAction {
+    pub name: String,
+    pub label: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    #[serde(default)]
+    pub action_enum_or_id: Option<String>,
+    /// Resources of the action keyed by name, such as `describe` and `defaultValues`
+    #[serde(default)]
+    pub urls: HashMap<String, String>,
+}
+
+/// A record for a quick action to create or update, and the record that it is performed on
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuickActionRequest<T> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_id: Option<String>,
+    pub record: T,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuickActionResult {
+    pub id: Option<String>,
+    #[serde(default)]
+    pub context_id: Option<String>,
+    #[serde(default)]
+    pub created: bool,
+    pub success: bool,
+    #[serde(default)]
+    pub errors: Vec<SfApiError>,
+    #[serde(default)]
+    pub feed_item_ids: Option<Vec<String>>,
+}
+
+impl SfClient {
+    /// Lists the quick actions that are available for an object
+    pub async fn quick_actions(&self, object: &str) -> SfResult<SfResponse<Vec<QuickAction>>> {
+        self.get(&self.object_path(&format!("{}/quickActions", object)))
+            .await
+    }
+
+    /// Performs a quick action of an object, such as `Account.CreateContact`
+    pub async fn invoke_quick_action<T>(
+        &self,
+        object: &str,
+        action: &str,
+        body: &QuickActionRequest<T>,
+    ) -> SfResult<SfResponse<QuickActionResult>>
+    where
+        T: Serialize,
+    {
+        self.post(
+            &self.object_path(&format!("{}/quickActions/{}", object, action)),
+            body,
+            &[StatusCode::OK, StatusCode::CREATED],
+        )
+        .await
+    }
+
+    /// Invokes an autolaunched flow once for each of the inputs
+    pub async fn invoke_flow<I, O>(
+        &self,
+        name: &str,
+        inputs: Vec<I>,
+    ) -> SfResult<SfResponse<Vec<ActionResult<O>>>>
+    where
+        I: Serialize,
+        O: DeserializeOwned + 'static,
+    {
+        self.invoke_action(&format!("actions/custom/flow/{}", name), inputs)
+            .await
+    }
+
+    /// Invokes an `@InvocableMethod` Apex action once for each of the inputs
+    pub async fn invoke_apex<I, O>(
+        &self,
+        name: &str,
+        inputs: Vec<I>,
+    ) -> SfResult<SfResponse<Vec<ActionResult<O>>>>
+    where
+        I: Serialize,
+        O: DeserializeOwned + 'static,
+    {
+        self.invoke_action(&format!("actions/custom/apex/{}", name), inputs)
+            .await
+    }
+
+    async fn invoke_action<I, O>(
+        &self,
+        path: &str,
+        inputs: Vec<I>,
+    ) -> SfResult<SfResponse<Vec<ActionResult<O>>>>
+    where
+        I: Serialize,
+        O: DeserializeOwned + 'static,
+    {
+        self.post(path, ActionInputs { inputs }, &[StatusCode::OK])
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use wiremock::{
+        matchers::{body_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{authenticator::jwt::tests::add_token_mock, tests::get_client, SfResponse};
+
+    use super::QuickActionRequest;
+
+    #[derive(Serialize)]
+    struct Input {
+        #[serde(rename = "accountId")]
+        account_id: String,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Output {
+        score: u32,
+    }
+
+    #[tokio::test]
+    async fn test_invoke_flow() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/actions/custom/flow/Score_Account"))
+            .and(body_json(serde_json::json!({
+                "inputs": [{ "accountId": "001" }, { "accountId": "002" }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {
+                    "actionName": "Score_Account",
+                    "errors": null,
+                    "isSuccess": true,
+                    "outputValues": { "score": 42 }
+                },
+                {
+                    "actionName": "Score_Account",
+                    "errors": [{ "statusCode": "FLOW_ERROR", "message": "Account not found", "fields": [] }],
+                    "isSuccess": false,
+                    "outputValues": null
+                }
+            ])))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        let results = client
+            .invoke_flow::<_, Output>(
+                "Score_Account",
+                vec![
+                    Input {
+                        account_id: "001".to_string(),
+                    },
+                    Input {
+                        account_id: "002".to_string(),
+                    },
+                ],
+            )
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        assert!(results[0].is_success);
+        assert_eq!(Some(Output { score: 42 }), results[0].output_values);
+        assert!(!results[1].is_success);
+        assert_eq!(None, results[1].output_values);
+        assert_eq!(
+            "FLOW_ERROR",
+            results[1].errors.as_ref().unwrap()[0].status_code
+        );
+    }
+
+    #[tokio::test]
+    async fn test_quick_actions() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Account/quickActions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([{
+                "actionEnumOrId": "CreateContact",
+                "label": "New Contact",
+                "name": "Account.CreateContact",
+                "type": "Create",
+                "urls": {
+                    "defaultValues": "/services/data/v12345.0/sobjects/Account/quickActions/Account.CreateContact/defaultValues",
+                    "describe": "/services/data/v12345.0/sobjects/Account/quickActions/Account.CreateContact/describe"
+                }
+            }])))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(
+                "/services/data/v12345.0/sobjects/Account/quickActions/Account.CreateContact",
+            ))
+            .and(body_json(serde_json::json!({
+                "contextId": "001000000000001",
+                "record": { "LastName": "Smith" }
+            })))
+            .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({
+                "contextId": "001000000000001",
+                "created": true,
+                "errors": [],
+                "feedItemIds": null,
+                "id": "003000000000001",
+                "success": true
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        let actions = client
+            .quick_actions("Account")
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+        assert_eq!("Account.CreateContact", actions[0].name);
+        assert!(actions[0].urls.contains_key("describe"));
+
+        let result = client
+            .invoke_quick_action(
+                "Account",
+                &actions[0].name,
+                &QuickActionRequest {
+                    context_id: Some("001000000000001".to_string()),
+                    record: serde_json::json!({ "LastName": "Smith" }),
+                },
+            )
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+        assert!(result.success);
+        assert!(result.created);
+        assert_eq!(Some("003000000000001".to_string()), result.id);
+    }
+}
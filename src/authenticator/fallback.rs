@@ -0,0 +1,162 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use async_trait::async_trait;
+
+use crate::error::{Error, SfResult};
+
+use super::{Authenticator, SfAccessToken, SfUserInfo};
+
+/// Tries each of a list of authenticators in order until one succeeds, such as to fail over to a
+/// second connected app when the key of the first has been revoked. If every authenticator fails
+/// the error of the last one is returned
+pub struct FallbackAuthenticator {
+    authenticators: Vec<Box<dyn Authenticator + Send + Sync>>,
+}
+
+impl FallbackAuthenticator {
+    pub fn new(authenticators: Vec<Box<dyn Authenticator + Send + Sync>>) -> Self {
+        Self { authenticators }
+    }
+
+    fn no_authenticators() -> Error {
+        Error::InvalidInput("FallbackAuthenticator requires at least one authenticator".to_string())
+    }
+}
+
+#[async_trait]
+impl Authenticator for FallbackAuthenticator {
+    async fn get_token(&self) -> SfResult<SfAccessToken> {
+        let mut last_err = Self::no_authenticators();
+
+        for (index, authenticator) in self.authenticators.iter().enumerate() {
+            match authenticator.get_token().await {
+                Ok(token) => return Ok(token),
+                Err(err) => {
+                    tracing::warn!(index, ?err, "Authenticator failed to get a token");
+                    last_err = err;
+                }
+            }
+        }
+
+        Err(last_err)
+    }
+
+    fn set_http_client(&mut self, client: reqwest::Client) {
+        for authenticator in &mut self.authenticators {
+            authenticator.set_http_client(client.clone());
+        }
+    }
+
+    async fn user_info(&self) -> SfResult<SfUserInfo> {
+        let mut last_err = Self::no_authenticators();
+
+        for (index, authenticator) in self.authenticators.iter().enumerate() {
+            match authenticator.user_info().await {
+                Ok(user_info) => return Ok(user_info),
+                Err(err) => {
+                    tracing::warn!(index, ?err, "Authenticator failed to get user info");
+                    last_err = err;
+                }
+            }
+        }
+
+        Err(last_err)
+    }
+
+    async fn user_info_with_token(&self, token: &SfAccessToken) -> SfResult<SfUserInfo> {
+        let mut last_err = Self::no_authenticators();
+
+        for (index, authenticator) in self.authenticators.iter().enumerate() {
+            match authenticator.user_info_with_token(token).await {
+                Ok(user_info) => return Ok(user_info),
+                Err(err) => {
+                    tracing::warn!(index, ?err, "Authenticator failed to get user info");
+                    last_err = err;
+                }
+            }
+        }
+
+        Err(last_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        authenticator::{
+            jwt::{tests::private_key, JwtAuthenticator, LoginClaims},
+            session::SessionAuthenticator,
+            Authenticator, AuthorizationServer,
+        },
+        error::Error,
+    };
+
+    use super::FallbackAuthenticator;
+
+    fn failing_authenticator(server: &MockServer) -> JwtAuthenticator {
+        let claims = LoginClaims::new(
+            "sf-client-id".to_string(),
+            AuthorizationServer::Test,
+            "test@company".to_string(),
+        );
+
+        JwtAuthenticator::new(&server.uri(), claims, private_key()).unwrap()
+    }
+
+    async fn mount_login_failure(server: &MockServer) {
+        Mock::given(method("POST"))
+            .and(path("/services/oauth2/token"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "error": "invalid_client_id",
+                "error_description": "client identifier invalid"
+            })))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_fails_over() {
+        let server = MockServer::start().await;
+        mount_login_failure(&server).await;
+
+        let authenticator = FallbackAuthenticator::new(vec![
+            Box::new(failing_authenticator(&server)),
+            Box::new(SessionAuthenticator::new(
+                "session_token".to_string(),
+                server.uri(),
+            )),
+        ]);
+
+        let token = authenticator.get_token().await.unwrap();
+        assert_eq!("session_token", token.access_token);
+    }
+
+    #[tokio::test]
+    async fn test_returns_last_error() {
+        let server = MockServer::start().await;
+        mount_login_failure(&server).await;
+
+        let authenticator = FallbackAuthenticator::new(vec![
+            Box::new(failing_authenticator(&server)),
+            Box::new(failing_authenticator(&server)),
+        ]);
+
+        assert!(matches!(
+            authenticator.get_token().await,
+            Err(Error::LoginFailure(_))
+        ));
+        assert!(matches!(
+            FallbackAuthenticator::new(vec![]).get_token().await,
+            Err(Error::InvalidInput(_))
+        ));
+    }
+}
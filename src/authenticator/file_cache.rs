@@ -0,0 +1,350 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    env::var,
+    fmt,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+use tokio::sync::Mutex;
+
+use crate::{
+    error::{Error, SfResult},
+    util::Redacted,
+};
+
+use super::{Authenticator, SfAccessToken, SfUserInfo};
+
+// Salesforce does not report when a token expires. This matches the shortest session timeout that
+// an org can be configured with
+const DEFAULT_TTL_MINUTES: i64 = 15;
+
+// How often a cache file that is locked by another process is checked
+const LOCK_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
+#[derive(Deserialize, Serialize)]
+struct CachedToken {
+    token: SfAccessToken,
+    expires_at: DateTime<Utc>,
+}
+
+impl fmt::Debug for CachedToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachedToken")
+            .field("token", &Redacted)
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+/// Persists the tokens of an inner authenticator to a file so that they can be reused across
+/// runs of a process, such as repeated invocations of a CLI. Tokens are reused until their ttl
+/// has passed, after which a new token is requested from the inner authenticator.
+///
+/// The cache file is locked while it is read and refreshed, so concurrent tasks and processes
+/// that share a cache wait for each other rather than each requesting a token. The file is only
+/// readable by its owner, as it holds the token
+pub struct FileCacheAuthenticator<A> {
+    inner: A,
+    path: PathBuf,
+    ttl: Duration,
+    // Tasks of this process wait here, leaving the file lock to other processes
+    lock: Mutex<()>,
+}
+
+impl<A> FileCacheAuthenticator<A>
+where
+    A: Authenticator,
+{
+    pub fn new(inner: A, path: PathBuf) -> Self {
+        Self {
+            inner,
+            path,
+            ttl: Duration::minutes(DEFAULT_TTL_MINUTES),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Creates an authenticator that caches tokens at the path in `SALESFORCE_TOKEN_CACHE`
+    #[allow(clippy::result_large_err)]
+    pub fn from_env(inner: A) -> SfResult<Self> {
+        Ok(Self::new(inner, var("SALESFORCE_TOKEN_CACHE")?.into()))
+    }
+
+    /// How long a token is reused for. This should not exceed the session timeout of the org
+    pub fn ttl(&mut self, ttl: Duration) -> &mut Self {
+        self.ttl = ttl;
+        self
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn open(&self) -> SfResult<File> {
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create(true).truncate(false);
+
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        options.open(&self.path).map_err(Error::TokenCache)
+    }
+
+    // Waits for the lock without blocking the runtime while another process holds it. The lock
+    // is released when the file is closed
+    async fn lock(file: &File) -> SfResult<()> {
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(()),
+                Err(err) if err.raw_os_error() == fs2::lock_contended_error().raw_os_error() => {
+                    tokio::time::sleep(LOCK_POLL_INTERVAL).await
+                }
+                Err(err) => return Err(Error::TokenCache(err)),
+            }
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn read_cached(file: &mut File) -> SfResult<Option<SfAccessToken>> {
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)
+            .map_err(Error::TokenCache)?;
+
+        // A cache that can not be parsed is treated as empty and overwritten
+        Ok(serde_json::from_str::<CachedToken>(&contents)
+            .ok()
+            .filter(|cached| cached.expires_at > Utc::now())
+            .map(|cached| cached.token))
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn write_cached(&self, file: &mut File, token: &SfAccessToken) -> SfResult<()> {
+        let cached = CachedToken {
+            token: token.clone(),
+            expires_at: Utc::now() + self.ttl,
+        };
+        let contents = serde_json::to_vec(&cached).map_err(|err| Error::TokenCache(err.into()))?;
+
+        // The mode given when opening only applies to new files, so a cache that was created
+        // with looser permissions is restricted before the token is written to it
+        #[cfg(unix)]
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))
+            .map_err(Error::TokenCache)?;
+
+        file.set_len(0).map_err(Error::TokenCache)?;
+        file.seek(SeekFrom::Start(0)).map_err(Error::TokenCache)?;
+        file.write_all(&contents).map_err(Error::TokenCache)
+    }
+}
+
+#[async_trait]
+impl<A> Authenticator for FileCacheAuthenticator<A>
+where
+    A: Authenticator + Send + Sync,
+{
+    async fn get_token(&self) -> SfResult<SfAccessToken> {
+        let _guard = self.lock.lock().await;
+
+        // The cache is read once locked, as it may have been refreshed while waiting
+        let mut file = self.open()?;
+        Self::lock(&file).await?;
+
+        if let Some(token) = Self::read_cached(&mut file)? {
+            tracing::debug!(path = ?self.path, "Using cached token");
+            return Ok(token);
+        }
+
+        let token = self.inner.get_token().await?;
+        self.write_cached(&mut file, &token)?;
+
+        Ok(token)
+    }
+
+    // The cached token is used so that requesting user info does not request another token
+    async fn user_info(&self) -> SfResult<SfUserInfo> {
+        let token = self.get_token().await?;
+        self.inner.user_info_with_token(&token).await
+    }
+
+    async fn user_info_with_token(&self, token: &SfAccessToken) -> SfResult<SfUserInfo> {
+        self.inner.user_info_with_token(token).await
+    }
+
+    fn set_http_client(&mut self, client: reqwest::Client) {
+        self.inner.set_http_client(client);
+    }
+
+    fn auth_url(&self) -> Option<&str> {
+        self.inner.auth_url()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use fs2::FileExt;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::authenticator::{
+        jwt::{tests::private_key, JwtAuthenticator, LoginClaims},
+        Authenticator, AuthorizationServer, SfAccessToken,
+    };
+
+    use super::FileCacheAuthenticator;
+
+    fn token(access_token: &str, server: &MockServer) -> SfAccessToken {
+        SfAccessToken {
+            access_token: access_token.to_string(),
+            scope: "scope".to_string(),
+            instance_url: server.uri(),
+            id: "id".to_string(),
+            token_type: "token_type".to_string(),
+        }
+    }
+
+    fn jwt_authenticator(server: &MockServer) -> JwtAuthenticator {
+        let claims = LoginClaims::new(
+            "sf-client-id".to_string(),
+            AuthorizationServer::Test,
+            "test@company".to_string(),
+        );
+
+        JwtAuthenticator::new(&server.uri(), claims, private_key()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_reuses_cached_token() {
+        let server = MockServer::start().await;
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("token.json");
+
+        Mock::given(method("POST"))
+            .and(path("/services/oauth2/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(token("first", &server)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let authenticator = FileCacheAuthenticator::new(jwt_authenticator(&server), cache.clone());
+        assert_eq!(
+            "first",
+            authenticator.get_token().await.unwrap().access_token
+        );
+
+        // A separate authenticator sharing the cache, as in a second invocation of a CLI
+        let authenticator = FileCacheAuthenticator::new(jwt_authenticator(&server), cache);
+        assert_eq!(
+            "first",
+            authenticator.get_token().await.unwrap().access_token
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refreshes_expired_token() {
+        let server = MockServer::start().await;
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("token.json");
+
+        std::fs::write(&cache, "not a token").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            std::fs::set_permissions(&cache, std::fs::Permissions::from_mode(0o644)).unwrap();
+        }
+
+        Mock::given(method("POST"))
+            .and(path("/services/oauth2/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(token("fresh", &server)))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let mut authenticator =
+            FileCacheAuthenticator::new(jwt_authenticator(&server), cache.clone());
+        authenticator.ttl(Duration::zero());
+
+        assert_eq!(
+            "fresh",
+            authenticator.get_token().await.unwrap().access_token
+        );
+        assert_eq!(
+            "fresh",
+            authenticator.get_token().await.unwrap().access_token
+        );
+
+        // An existing cache that others could read is restricted once it is written
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = std::fs::metadata(&cache).unwrap().permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests_share_token() {
+        let server = MockServer::start().await;
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("token.json");
+
+        Mock::given(method("POST"))
+            .and(path("/services/oauth2/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(token("shared", &server)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let authenticator = FileCacheAuthenticator::new(jwt_authenticator(&server), cache.clone());
+        let (first, second) = tokio::join!(authenticator.get_token(), authenticator.get_token());
+
+        assert_eq!("shared", first.unwrap().access_token);
+        assert_eq!("shared", second.unwrap().access_token);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = std::fs::metadata(&cache).unwrap().permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_waits_for_locked_cache() {
+        let server = MockServer::start().await;
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("token.json");
+
+        Mock::given(method("POST"))
+            .and(path("/services/oauth2/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(token("fresh", &server)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // Another process holding the lock, which must not block the single threaded runtime
+        let held = std::fs::File::create(&cache).unwrap();
+        held.lock_exclusive().unwrap();
+
+        let authenticator = FileCacheAuthenticator::new(jwt_authenticator(&server), cache);
+        let release = async {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            held.unlock().unwrap();
+        };
+        let (token, _) = tokio::join!(authenticator.get_token(), release);
+
+        assert_eq!("fresh", token.unwrap().access_token);
+    }
+}
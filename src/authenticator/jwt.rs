@@ -7,23 +7,29 @@
 use async_trait::async_trait;
 use chrono::{Duration, Utc};
 use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
-use reqwest::{Client as HttpClient, StatusCode};
-use serde::Serialize;
-use std::{env::var, fs::File, io::Read, ops::Add, path::Path};
+use reqwest::{Client as HttpClient, StatusCode, Url};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, env::var, fmt, fs::File, io::Read, ops::Add, path::Path};
 
 use crate::{
-    error::{SfLoginError, SfResult},
+    error::{Error, SfLoginError, SfResult},
+    util::{retry_after, Redacted},
     SfResponse,
 };
 
-use super::{Authenticator, AuthorizationServer, SfAccessToken, SfUserInfo};
+use super::{
+    revoke_token, Authenticator, AuthorizationServer, OAuthPaths, SfAccessToken, SfUserInfo,
+};
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, PartialEq)]
 pub struct LoginClaims {
     iss: String,
     aud: String,
     sub: String,
     exp: i64,
+    #[serde(flatten)]
+    extra: HashMap<String, Value>,
 }
 
 impl LoginClaims {
@@ -33,9 +39,17 @@ impl LoginClaims {
             aud: format!("{}", aud),
             sub,
             exp: Utc::now().add(Duration::seconds(60)).timestamp(),
+            extra: HashMap::new(),
         }
     }
 
+    /// Adds a custom claim to the assertion, for connected apps that validate additional claims
+    pub fn claim(mut self, name: String, value: Value) -> Self {
+        self.extra.insert(name, value);
+        self
+    }
+
+    #[allow(clippy::result_large_err)]
     pub fn from_env(aud: AuthorizationServer) -> SfResult<Self> {
         Ok(Self::new(
             var("SALESFORCE_CLIENT_ID")?,
@@ -46,30 +60,60 @@ impl LoginClaims {
 }
 
 #[derive(Debug, Serialize)]
-struct LoginForm {
+struct LoginForm<'a> {
     grant_type: String,
     assertion: String,
     format: LoginResponseFormat,
+    #[serde(flatten)]
+    extra: &'a HashMap<String, String>,
 }
 
-#[derive(Debug, Serialize)]
-enum LoginResponseFormat {
+/// The format that the token endpoint responds with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub enum LoginResponseFormat {
+    #[default]
     #[serde(rename = "json")]
     Json,
-    #[allow(dead_code)]
     #[serde(rename = "urlencoded")]
     UrlEncoded,
-    #[allow(dead_code)]
     #[serde(rename = "xml")]
     Xml,
 }
 
-impl LoginForm {
-    pub fn new(claims: &LoginClaims, key: &[u8]) -> SfResult<Self> {
+impl LoginResponseFormat {
+    #[allow(clippy::result_large_err)]
+    fn parse<T>(&self, body: &str) -> SfResult<T>
+    where
+        T: DeserializeOwned,
+    {
+        let malformed = |error: String| Error::MalformedResponse {
+            error,
+            body: body.to_string(),
+        };
+
+        match self {
+            Self::Json => serde_json::from_str(body).map_err(|err| malformed(err.to_string())),
+            Self::UrlEncoded => {
+                serde_urlencoded::from_str(body).map_err(|err| malformed(err.to_string()))
+            }
+            Self::Xml => quick_xml::de::from_str(body).map_err(|err| malformed(err.to_string())),
+        }
+    }
+}
+
+impl<'a> LoginForm<'a> {
+    #[allow(clippy::result_large_err)]
+    pub fn new(
+        claims: &LoginClaims,
+        key: &[u8],
+        format: LoginResponseFormat,
+        extra: &'a HashMap<String, String>,
+    ) -> SfResult<Self> {
         Ok(Self {
             grant_type: "urn:ietf:params:oauth:grant-type:jwt-bearer".to_string(),
-            assertion: Self::create_assertion(claims, &key)?,
-            format: LoginResponseFormat::Json,
+            assertion: Self::create_assertion(claims, key)?,
+            format,
+            extra,
         })
     }
 
@@ -77,8 +121,9 @@ impl LoginForm {
         Header::new(Algorithm::RS256)
     }
 
+    #[allow(clippy::result_large_err)]
     fn create_assertion(claims: &LoginClaims, key: &[u8]) -> SfResult<String> {
-        let enc_key = EncodingKey::from_rsa_pem(&key)?;
+        let enc_key = EncodingKey::from_rsa_pem(key)?;
         Ok(encode(&Self::header(), claims, &enc_key)?)
     }
 }
@@ -88,28 +133,53 @@ pub struct JwtAuthenticator {
     instance: String,
     key: Vec<u8>,
     claims: LoginClaims,
+    format: LoginResponseFormat,
+    form_params: HashMap<String, String>,
+    paths: OAuthPaths,
+}
+
+impl fmt::Debug for JwtAuthenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtAuthenticator")
+            .field("instance", &self.instance)
+            .field("key", &Redacted)
+            .field("claims", &self.claims)
+            .field("format", &self.format)
+            .finish_non_exhaustive()
+    }
 }
 
 impl JwtAuthenticator {
-    pub fn new(instance_domain: &str, claims: LoginClaims, key: Vec<u8>) -> Self {
-        Self {
+    /// Creates an authenticator for the given instance domain, which may either be a bare domain
+    /// or a full url. Returns [`Error::InvalidInput`] if the domain is not valid
+    #[allow(clippy::result_large_err)]
+    pub fn new(instance_domain: &str, claims: LoginClaims, key: Vec<u8>) -> SfResult<Self> {
+        let instance = if instance_domain.starts_with("http") {
+            instance_domain.trim_end_matches('/').to_string()
+        } else {
+            format!("https://{}", instance_domain.trim_end_matches('/'))
+        };
+
+        validate_instance(&instance)?;
+
+        Ok(Self {
             inner: HttpClient::new(),
-            instance: if instance_domain.starts_with("http") {
-                instance_domain.trim_end_matches("/").to_string()
-            } else {
-                format!("https://{}", instance_domain.trim_end_matches("/"))
-            },
+            instance,
             key,
             claims,
-        }
+            format: LoginResponseFormat::default(),
+            form_params: HashMap::new(),
+            paths: OAuthPaths::default(),
+        })
     }
 
+    #[allow(clippy::result_large_err)]
     pub fn from_env(claims: LoginClaims) -> SfResult<Self> {
-        Ok(Self::new(
+        Self::new(
             &var("SALESFORCE_DOMAIN")?,
             claims,
             var("SALESFORCE_KEY")?.into(),
-        ))
+        )
     }
 
     pub fn key(&mut self, key: Vec<u8>) -> &mut Self {
@@ -117,6 +187,33 @@ impl JwtAuthenticator {
         self
     }
 
+    /// Requests tokens in the given format. Tokens are requested as JSON by default
+    pub fn response_format(&mut self, format: LoginResponseFormat) -> &mut Self {
+        self.format = format;
+        self
+    }
+
+    /// Adds a parameter to the token request, such as a `scope` required by the token policy of
+    /// the connected app
+    pub fn form_param(&mut self, name: String, value: String) -> &mut Self {
+        self.form_params.insert(name, value);
+        self
+    }
+
+    /// Overrides the paths of the OAuth endpoints, which default to those under `/services/oauth2`
+    pub fn oauth_paths(&mut self, paths: OAuthPaths) -> &mut Self {
+        self.paths = paths;
+        self
+    }
+
+    /// Revokes a token requested by this authenticator. Clients holding the token will fail to
+    /// authenticate until another token is requested
+    pub async fn revoke(&self, token: &SfAccessToken) -> SfResult<()> {
+        let url = OAuthPaths::url(&self.instance, &self.paths.revoke);
+        revoke_token(&self.inner, url, token).await
+    }
+
+    #[allow(clippy::result_large_err)]
     pub fn load_rsa_pem<T>(&mut self, path: T) -> SfResult<&mut Self>
     where
         T: AsRef<Path>,
@@ -130,41 +227,87 @@ impl JwtAuthenticator {
     }
 }
 
+#[allow(clippy::result_large_err)]
+fn validate_instance(instance: &str) -> SfResult<()> {
+    let invalid = || Error::InvalidInput(format!("{} is not a valid instance url", instance));
+    let url = Url::parse(instance).map_err(|_| invalid())?;
+
+    if url.scheme() != "https" && url.scheme() != "http" {
+        return Err(invalid());
+    }
+
+    // The url parser accepts characters such as commas in domains, which are never valid for an
+    // instance and are most likely a typo
+    match (url.host(), url.domain()) {
+        (Some(_), Some(domain))
+            if domain
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.') =>
+        {
+            Ok(())
+        }
+        // IP addresses do not have a domain
+        (Some(_), None) => Ok(()),
+        _ => Err(invalid()),
+    }
+}
+
 #[async_trait]
 impl Authenticator for JwtAuthenticator {
     async fn get_token(&self) -> SfResult<SfAccessToken> {
-        let form = LoginForm::new(&self.claims, &self.key)?;
+        let form = LoginForm::new(&self.claims, &self.key, self.format, &self.form_params)?;
         let response = self
             .inner
-            .post(&format!("{}/services/oauth2/token", self.instance))
+            .post(OAuthPaths::url(&self.instance, &self.paths.token))
             .form(&form)
             .send()
             .await?;
 
-        match response.status() {
-            StatusCode::OK => Ok(response.json().await?),
+        let headers = response.headers().clone();
+        let status = response.status();
+
+        match status {
+            StatusCode::OK => self.format.parse(&response.text().await?),
+            StatusCode::TOO_MANY_REQUESTS => Err(Error::RateLimited {
+                retry_after: retry_after(&headers),
+            }),
             _ => Err(SfResponse {
-                headers: response.headers().clone(),
-                status: response.status(),
-                body: Some(response.json::<SfLoginError>().await?),
+                headers,
+                status,
+                body: Some(self.format.parse::<SfLoginError>(&response.text().await?)?),
             })?,
         }
     }
 
+    fn set_http_client(&mut self, client: HttpClient) {
+        self.inner = client;
+    }
+
+    fn auth_url(&self) -> Option<&str> {
+        Some(&self.instance)
+    }
+
     async fn user_info(&self) -> SfResult<SfUserInfo> {
         let token = self.get_token().await?;
+        self.user_info_with_token(&token).await
+    }
 
-        tracing::debug!(url = ?format!("{}/services/oauth2/userinfo", self.instance), "Requesting user info");
+    async fn user_info_with_token(&self, token: &SfAccessToken) -> SfResult<SfUserInfo> {
+        let url = OAuthPaths::url(&self.instance, &self.paths.userinfo);
+        tracing::debug!(?url, "Requesting user info");
 
         let response = self
             .inner
-            .get(&format!("{}/services/oauth2/userinfo", self.instance))
-            .bearer_auth(token.access_token)
+            .get(url)
+            .bearer_auth(&token.access_token)
             .send()
             .await?;
 
         match response.status() {
             StatusCode::OK => Ok(response.json().await?),
+            StatusCode::TOO_MANY_REQUESTS => Err(Error::RateLimited {
+                retry_after: retry_after(response.headers()),
+            }),
             _ => Err(SfResponse {
                 headers: response.headers().clone(),
                 status: response.status(),
@@ -177,21 +320,29 @@ impl Authenticator for JwtAuthenticator {
 #[cfg(test)]
 pub mod tests {
     use rsa::{pkcs1::EncodeRsaPrivateKey, RsaPrivateKey};
+    use std::sync::OnceLock;
     use wiremock::{
-        matchers::{method, path},
+        matchers::{body_string_contains, header, method, path},
         Mock, MockServer, ResponseTemplate,
     };
 
     use super::*;
 
+    // Generating a key is slow, so share a single key across all tests
+    static PRIVATE_KEY: OnceLock<Vec<u8>> = OnceLock::new();
+
     pub fn private_key() -> Vec<u8> {
-        let mut rng = rand::thread_rng();
-        RsaPrivateKey::new(&mut rng, 2048)
-            .unwrap()
-            .to_pkcs1_pem(rsa::pkcs8::LineEnding::LF)
-            .unwrap()
-            .as_bytes()
-            .to_vec()
+        PRIVATE_KEY
+            .get_or_init(|| {
+                let mut rng = rand::thread_rng();
+                RsaPrivateKey::new(&mut rng, 2048)
+                    .unwrap()
+                    .to_pkcs1_pem(rsa::pkcs8::LineEnding::LF)
+                    .unwrap()
+                    .as_bytes()
+                    .to_vec()
+            })
+            .clone()
     }
 
     pub async fn add_token_mock(server: &MockServer) -> SfAccessToken {
@@ -206,7 +357,7 @@ pub mod tests {
         Mock::given(method("POST"))
             .and(path("/services/oauth2/token"))
             .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
-            .mount(&server)
+            .mount(server)
             .await;
 
         mock_response
@@ -222,11 +373,306 @@ pub mod tests {
             "test@company".to_string(),
         );
         let authenticator =
-            JwtAuthenticator::new(&mock_server.uri(), claims.clone(), private_key());
+            JwtAuthenticator::new(&mock_server.uri(), claims.clone(), private_key()).unwrap();
 
         let mock_response = add_token_mock(&mock_server).await;
         let token = authenticator.get_token().await;
 
         assert_eq!(mock_response, token.unwrap());
     }
+
+    #[tokio::test]
+    async fn get_token_rate_limited() {
+        let mock_server = MockServer::start().await;
+
+        let claims = LoginClaims::new(
+            "sf-client-id".to_string(),
+            AuthorizationServer::Test,
+            "test@company".to_string(),
+        );
+        let authenticator =
+            JwtAuthenticator::new(&mock_server.uri(), claims, private_key()).unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/services/oauth2/token"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "45"))
+            .mount(&mock_server)
+            .await;
+
+        let err = authenticator.get_token().await.unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::RateLimited {
+                retry_after: Some(duration)
+            } if duration == std::time::Duration::from_secs(45)
+        ));
+    }
+
+    #[tokio::test]
+    async fn get_token_urlencoded() {
+        let mock_server = MockServer::start().await;
+
+        let mut authenticator =
+            JwtAuthenticator::new(&mock_server.uri(), claims(), private_key()).unwrap();
+        authenticator.response_format(LoginResponseFormat::UrlEncoded);
+
+        Mock::given(method("POST"))
+            .and(path("/services/oauth2/token"))
+            .and(body_string_contains("format=urlencoded"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                "access_token=token&scope=api&instance_url=https%3A%2F%2Facme.my.salesforce.com&id=id&token_type=Bearer",
+                "application/x-www-form-urlencoded",
+            ))
+            .mount(&mock_server)
+            .await;
+
+        let token = authenticator.get_token().await.unwrap();
+
+        assert_eq!("token", token.access_token);
+        assert_eq!("https://acme.my.salesforce.com", token.instance_url);
+    }
+
+    #[tokio::test]
+    async fn get_token_form_params() {
+        let mock_server = MockServer::start().await;
+
+        let mut authenticator =
+            JwtAuthenticator::new(&mock_server.uri(), claims(), private_key()).unwrap();
+        authenticator.form_param("scope".to_string(), "api refresh_token".to_string());
+
+        Mock::given(method("POST"))
+            .and(path("/services/oauth2/token"))
+            .and(body_string_contains("scope=api+refresh_token"))
+            .and(body_string_contains("format=json"))
+            .and(body_string_contains("grant_type=urn"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(SfAccessToken {
+                access_token: "token".to_string(),
+                scope: "api refresh_token".to_string(),
+                instance_url: mock_server.uri(),
+                id: "id".to_string(),
+                token_type: "Bearer".to_string(),
+            }))
+            .mount(&mock_server)
+            .await;
+
+        let token = authenticator.get_token().await.unwrap();
+        assert_eq!("api refresh_token", token.scope);
+    }
+
+    #[tokio::test]
+    async fn token_and_user_info() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/oauth2/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(SfAccessToken {
+                access_token: "token".to_string(),
+                scope: "scope".to_string(),
+                instance_url: mock_server.uri(),
+                id: "id".to_string(),
+                token_type: "Bearer".to_string(),
+            }))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/oauth2/userinfo"))
+            .and(header("Authorization", "Bearer token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "sub": "https://login.salesforce.com/id/00D000000000001/005000000000001",
+                "user_id": "005000000000001",
+                "organization_id": "00D000000000001",
+                "preferred_username": "test@company.com",
+                "nickname": "test",
+                "name": "Test User",
+                "email": "test@company.com",
+                "email_verified": true,
+                "given_name": "Test",
+                "family_name": "User",
+                "zoneinfo": "America/Los_Angeles",
+                "profile": "https://acme.my.salesforce.com/005000000000001",
+                "picture": "https://acme.file.force.com/profilephoto/005/F",
+                "phone_number": "",
+                "phone_number_verified": false,
+                "is_salesforce_integration_user": false,
+                "active": true,
+                "user_type": "STANDARD",
+                "language": "en_US",
+                "locale": "en_US",
+                "utcOffset": -28800000,
+                "updated_at": "2023-01-02T03:04:05Z"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let authenticator =
+            JwtAuthenticator::new(&mock_server.uri(), claims(), private_key()).unwrap();
+
+        // A single token is requested for both
+        let (token, user_info) = authenticator.token_and_user_info().await.unwrap();
+        assert_eq!("token", token.access_token);
+        assert_eq!("005000000000001", user_info.user_id);
+    }
+
+    #[tokio::test]
+    async fn get_token_custom_path() {
+        let mock_server = MockServer::start().await;
+
+        let mut authenticator =
+            JwtAuthenticator::new(&mock_server.uri(), claims(), private_key()).unwrap();
+        authenticator.oauth_paths(OAuthPaths::default().token("/gateway/oauth2/token"));
+
+        Mock::given(method("POST"))
+            .and(path("/gateway/oauth2/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(SfAccessToken {
+                access_token: "token".to_string(),
+                scope: "scope".to_string(),
+                instance_url: mock_server.uri(),
+                id: "id".to_string(),
+                token_type: "Bearer".to_string(),
+            }))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let token = authenticator.get_token().await.unwrap();
+        assert_eq!("token", token.access_token);
+    }
+
+    #[tokio::test]
+    async fn revoke() {
+        let mock_server = MockServer::start().await;
+
+        let mut authenticator =
+            JwtAuthenticator::new(&mock_server.uri(), claims(), private_key()).unwrap();
+        authenticator.oauth_paths(OAuthPaths::default().revoke("/gateway/oauth2/revoke"));
+
+        let token = add_token_mock(&mock_server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/gateway/oauth2/revoke"))
+            .and(body_string_contains("token=access_token"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        authenticator.revoke(&token).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn revoke_invalid_token() {
+        let mock_server = MockServer::start().await;
+
+        let authenticator =
+            JwtAuthenticator::new(&mock_server.uri(), claims(), private_key()).unwrap();
+        let token = add_token_mock(&mock_server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/oauth2/revoke"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(SfLoginError {
+                error: "invalid_token".to_string(),
+                error_description: "invalid token".to_string(),
+            }))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let err = authenticator.revoke(&token).await.unwrap_err();
+        assert!(
+            matches!(err, Error::LoginFailure(ref response) if response.body.as_ref().unwrap().error == "invalid_token")
+        );
+    }
+
+    #[tokio::test]
+    async fn get_token_xml() {
+        let mock_server = MockServer::start().await;
+
+        let mut authenticator =
+            JwtAuthenticator::new(&mock_server.uri(), claims(), private_key()).unwrap();
+        authenticator.response_format(LoginResponseFormat::Xml);
+
+        Mock::given(method("POST"))
+            .and(path("/services/oauth2/token"))
+            .and(body_string_contains("format=xml"))
+            .respond_with(ResponseTemplate::new(400).set_body_raw(
+                "<OAuth><error>invalid_grant</error><error_description>user hasn't approved this consumer</error_description></OAuth>",
+                "application/xml",
+            ))
+            .mount(&mock_server)
+            .await;
+
+        match authenticator.get_token().await.unwrap_err() {
+            Error::LoginFailure(response) => {
+                assert_eq!("invalid_grant", response.body.unwrap().error)
+            }
+            err => panic!("Unexpected error {:?}", err),
+        }
+    }
+
+    fn claims() -> LoginClaims {
+        LoginClaims::new(
+            "sf-client-id".to_string(),
+            AuthorizationServer::Test,
+            "test@company".to_string(),
+        )
+    }
+
+    #[test]
+    fn claims_with_custom_claims() {
+        let claims = claims().claim("tenant".to_string(), serde_json::json!("acme"));
+        let value = serde_json::to_value(&claims).unwrap();
+
+        assert_eq!("sf-client-id", value["iss"]);
+        assert_eq!("test@company", value["sub"]);
+        assert_eq!("acme", value["tenant"]);
+    }
+
+    #[test]
+    fn claims_without_custom_claims() {
+        let value = serde_json::to_value(claims()).unwrap();
+
+        assert_eq!(4, value.as_object().unwrap().len());
+    }
+
+    #[test]
+    fn debug_redacts_key() {
+        let key = private_key();
+        let authenticator =
+            JwtAuthenticator::new("acme.my.salesforce.com", claims(), key.clone()).unwrap();
+        let debug = format!("{:?}", authenticator);
+
+        assert!(!debug.contains("PRIVATE KEY"));
+        assert!(!debug.contains(&format!("{:?}", &key[..16])));
+        assert!(debug.contains("[redacted]"));
+    }
+
+    #[test]
+    fn new_bare_domain() {
+        let authenticator =
+            JwtAuthenticator::new("acme.my.salesforce.com/", claims(), vec![]).unwrap();
+        assert_eq!("https://acme.my.salesforce.com", authenticator.instance);
+    }
+
+    #[test]
+    fn new_full_url() {
+        let authenticator =
+            JwtAuthenticator::new("http://localhost:8080/", claims(), vec![]).unwrap();
+        assert_eq!("http://localhost:8080", authenticator.instance);
+    }
+
+    #[test]
+    fn new_malformed_domain() {
+        for domain in ["acme,salesforce.com", "acme salesforce.com", "https://", ""] {
+            let result = JwtAuthenticator::new(domain, claims(), vec![]);
+            assert!(
+                matches!(result, Err(Error::InvalidInput(_))),
+                "{} should be rejected",
+                domain
+            );
+        }
+    }
 }
@@ -7,16 +7,31 @@
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::fmt::Display;
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
 
-use crate::error::SfResult;
+use reqwest::{Client as HttpClient, StatusCode};
 
+use crate::{
+    error::{Error, SfLoginError, SfResult},
+    util::{join_url, retry_after, Redacted},
+    SfResponse,
+};
+
+pub mod fallback;
+pub mod file_cache;
 pub mod jwt;
 pub mod session;
+pub mod soap;
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum AuthorizationServer {
     Live,
     Test,
+    /// A login url other than the standard ones, such as a My Domain
+    Custom(String),
 }
 
 impl Display for AuthorizationServer {
@@ -24,11 +39,127 @@ impl Display for AuthorizationServer {
         match self {
             Self::Live => write!(f, "https://login.salesforce.com"),
             Self::Test => write!(f, "https://test.salesforce.com"),
+            Self::Custom(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+/// Parses `live` or `production` as [`AuthorizationServer::Live`], `test` or `sandbox` as
+/// [`AuthorizationServer::Test`], and a url or domain as [`AuthorizationServer::Custom`]
+impl FromStr for AuthorizationServer {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        match s.to_ascii_lowercase().as_str() {
+            "live" | "production" => Ok(Self::Live),
+            "test" | "sandbox" => Ok(Self::Test),
+            lower if lower.starts_with("https://") || lower.starts_with("http://") => {
+                Ok(Self::Custom(s.trim_end_matches('/').to_string()))
+            }
+            lower if lower.contains('.') && !lower.contains(char::is_whitespace) => {
+                Ok(Self::Custom(format!("https://{}", s.trim_end_matches('/'))))
+            }
+            _ => Err(Error::InvalidInput(format!(
+                "{} is not a valid authorization server",
+                s
+            ))),
+        }
+    }
+}
+
+impl TryFrom<&str> for AuthorizationServer {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// The paths of the OAuth endpoints relative to the instance. These only need to be changed when a
+/// proxy or gateway serves the endpoints under a different prefix
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OAuthPaths {
+    pub token: String,
+    pub userinfo: String,
+    pub revoke: String,
+    pub introspect: String,
+}
+
+impl OAuthPaths {
+    pub fn token(mut self, path: impl Into<String>) -> Self {
+        self.token = path.into();
+        self
+    }
+
+    pub fn userinfo(mut self, path: impl Into<String>) -> Self {
+        self.userinfo = path.into();
+        self
+    }
+
+    pub fn revoke(mut self, path: impl Into<String>) -> Self {
+        self.revoke = path.into();
+        self
+    }
+
+    /// Introspection requires the credentials of a connected app, which the authenticators do not
+    /// hold, so this path is for callers that introspect tokens themselves, see
+    /// [`OAuthPaths::introspect_url`]
+    pub fn introspect(mut self, path: impl Into<String>) -> Self {
+        self.introspect = path.into();
+        self
+    }
+
+    /// The url of the introspect endpoint of an instance
+    pub fn introspect_url(&self, instance: &str) -> String {
+        Self::url(instance, &self.introspect)
+    }
+
+    pub(crate) fn url(instance: &str, path: &str) -> String {
+        join_url(instance, path)
+    }
+}
+
+/// Revokes an access token at the revoke endpoint of an instance, ending the session of the token
+pub(crate) async fn revoke_token(
+    client: &HttpClient,
+    url: String,
+    token: &SfAccessToken,
+) -> SfResult<()> {
+    tracing::debug!(?url, "Revoking token");
+
+    let response = client
+        .post(url)
+        .form(&[("token", &token.access_token)])
+        .send()
+        .await?;
+
+    match response.status() {
+        StatusCode::OK => Ok(()),
+        StatusCode::TOO_MANY_REQUESTS => Err(Error::RateLimited {
+            retry_after: retry_after(response.headers()),
+        }),
+        _ => Err(SfResponse {
+            headers: response.headers().clone(),
+            status: response.status(),
+            body: Some(response.json::<SfLoginError>().await?),
+        })?,
+    }
+}
+
+impl Default for OAuthPaths {
+    fn default() -> Self {
+        Self {
+            token: "/services/oauth2/token".to_string(),
+            userinfo: "/services/oauth2/userinfo".to_string(),
+            revoke: "/services/oauth2/revoke".to_string(),
+            introspect: "/services/oauth2/introspect".to_string(),
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Clone, Deserialize, Serialize, PartialEq)]
 pub struct SfAccessToken {
     pub access_token: String,
     pub scope: String,
@@ -37,7 +168,19 @@ pub struct SfAccessToken {
     pub token_type: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+impl fmt::Debug for SfAccessToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SfAccessToken")
+            .field("access_token", &Redacted)
+            .field("scope", &self.scope)
+            .field("instance_url", &self.instance_url)
+            .field("id", &self.id)
+            .field("token_type", &self.token_type)
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct SfUserInfo {
     pub sub: String,
     pub user_id: String,
@@ -68,4 +211,84 @@ pub struct SfUserInfo {
 pub trait Authenticator {
     async fn get_token(&self) -> SfResult<SfAccessToken>;
     async fn user_info(&self) -> SfResult<SfUserInfo>;
+
+    /// Fetches the user info of a token that was already requested, rather than requesting
+    /// another. Authenticators that can not use the token fall back to [`Authenticator::user_info`]
+    async fn user_info_with_token(&self, _token: &SfAccessToken) -> SfResult<SfUserInfo> {
+        self.user_info().await
+    }
+
+    /// Requests a token along with the user info of that token
+    async fn token_and_user_info(&self) -> SfResult<(SfAccessToken, SfUserInfo)> {
+        let token = self.get_token().await?;
+        let user_info = self.user_info_with_token(&token).await?;
+
+        Ok((token, user_info))
+    }
+
+    /// Replaces the HTTP client used to request tokens, so that it shares the configuration of
+    /// the client built by [`crate::SfClientBuilder`]. Authenticators that do not make requests
+    /// can ignore this
+    fn set_http_client(&mut self, _client: reqwest::Client) {}
+
+    /// The url of the instance or login domain that tokens are requested from, if known
+    fn auth_url(&self) -> Option<&str> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuthorizationServer, OAuthPaths, SfAccessToken};
+
+    #[test]
+    fn test_parse_authorization_server() {
+        assert_eq!(AuthorizationServer::Live, "live".parse().unwrap());
+        assert_eq!(AuthorizationServer::Live, "Production".parse().unwrap());
+        assert_eq!(AuthorizationServer::Test, "test".parse().unwrap());
+        assert_eq!(
+            AuthorizationServer::Test,
+            AuthorizationServer::try_from("sandbox").unwrap()
+        );
+        assert_eq!(
+            AuthorizationServer::Custom("https://acme.my.salesforce.com".to_string()),
+            "https://acme.my.salesforce.com/".parse().unwrap()
+        );
+        assert_eq!(
+            AuthorizationServer::Custom("https://acme.my.salesforce.com".to_string()),
+            "acme.my.salesforce.com".parse().unwrap()
+        );
+        assert!("staging".parse::<AuthorizationServer>().is_err());
+    }
+
+    #[test]
+    fn test_introspect_url() {
+        let instance = "https://acme.my.salesforce.com";
+
+        assert_eq!(
+            "https://acme.my.salesforce.com/services/oauth2/introspect",
+            OAuthPaths::default().introspect_url(instance)
+        );
+        assert_eq!(
+            "https://acme.my.salesforce.com/gateway/oauth2/introspect",
+            OAuthPaths::default()
+                .introspect("/gateway/oauth2/introspect")
+                .introspect_url(instance)
+        );
+    }
+
+    #[test]
+    fn test_access_token_debug_redacts_token() {
+        let token = SfAccessToken {
+            access_token: "secret_token".to_string(),
+            scope: "api".to_string(),
+            instance_url: "https://acme.my.salesforce.com".to_string(),
+            id: String::new(),
+            token_type: "Bearer".to_string(),
+        };
+        let debug = format!("{:?}", token);
+
+        assert!(!debug.contains("secret_token"));
+        assert!(debug.contains("https://acme.my.salesforce.com"));
+    }
 }
@@ -7,14 +7,28 @@
 use async_trait::async_trait;
 use reqwest::{Client as HttpClient, StatusCode};
 
-use crate::{error::SfResult, Authenticator, SfResponse};
+use crate::{
+    error::{Error, SfResult},
+    util::{retry_after, Redacted},
+    Authenticator, SfResponse,
+};
 
-use super::{SfAccessToken, SfUserInfo};
+use super::{revoke_token, OAuthPaths, SfAccessToken, SfUserInfo};
 
 pub struct SessionAuthenticator {
     inner: HttpClient,
     access_token: String,
     instance_url: String,
+    paths: OAuthPaths,
+}
+
+impl std::fmt::Debug for SessionAuthenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionAuthenticator")
+            .field("access_token", &Redacted)
+            .field("instance_url", &self.instance_url)
+            .finish_non_exhaustive()
+    }
 }
 
 impl SessionAuthenticator {
@@ -23,8 +37,22 @@ impl SessionAuthenticator {
             inner: HttpClient::new(),
             access_token,
             instance_url,
+            paths: OAuthPaths::default(),
         }
     }
+
+    /// Overrides the paths of the OAuth endpoints, which default to those under `/services/oauth2`
+    pub fn oauth_paths(&mut self, paths: OAuthPaths) -> &mut Self {
+        self.paths = paths;
+        self
+    }
+
+    /// Revokes a token, ending its session. Revoking the session of this authenticator leaves it
+    /// unable to authenticate, as it has no way to request another token
+    pub async fn revoke(&self, token: &SfAccessToken) -> SfResult<()> {
+        let url = OAuthPaths::url(&self.instance_url, &self.paths.revoke);
+        revoke_token(&self.inner, url, token).await
+    }
 }
 
 #[async_trait]
@@ -39,20 +67,35 @@ impl Authenticator for SessionAuthenticator {
         })
     }
 
+    fn set_http_client(&mut self, client: HttpClient) {
+        self.inner = client;
+    }
+
+    fn auth_url(&self) -> Option<&str> {
+        Some(&self.instance_url)
+    }
+
     async fn user_info(&self) -> SfResult<SfUserInfo> {
         let token = self.get_token().await?;
+        self.user_info_with_token(&token).await
+    }
 
-        tracing::debug!(url = ?format!("{}/services/oauth2/userinfo", self.instance_url), "Requesting user info");
+    async fn user_info_with_token(&self, token: &SfAccessToken) -> SfResult<SfUserInfo> {
+        let url = OAuthPaths::url(&self.instance_url, &self.paths.userinfo);
+        tracing::debug!(?url, "Requesting user info");
 
         let response = self
             .inner
-            .get(&format!("{}/services/oauth2/userinfo", self.instance_url))
-            .bearer_auth(token.access_token)
+            .get(url)
+            .bearer_auth(&token.access_token)
             .send()
             .await?;
 
         match response.status() {
             StatusCode::OK => Ok(response.json().await?),
+            StatusCode::TOO_MANY_REQUESTS => Err(Error::RateLimited {
+                retry_after: retry_after(response.headers()),
+            }),
             _ => Err(SfResponse {
                 headers: response.headers().clone(),
                 status: response.status(),
@@ -0,0 +1,300 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use async_trait::async_trait;
+use quick_xml::escape::escape;
+use reqwest::{header::CONTENT_TYPE, Client as HttpClient, StatusCode, Url};
+use serde::Deserialize;
+
+use crate::{
+    error::{Error, SfLoginError, SfResult},
+    util::{retry_after, Redacted},
+    SfResponse,
+};
+
+use super::{Authenticator, AuthorizationServer, OAuthPaths, SfAccessToken, SfUserInfo};
+
+#[derive(Debug, Deserialize)]
+struct Envelope {
+    #[serde(rename = "Body")]
+    body: Body,
+}
+
+#[derive(Debug, Deserialize)]
+struct Body {
+    #[serde(rename = "loginResponse")]
+    response: Option<LoginResponse>,
+    #[serde(rename = "Fault")]
+    fault: Option<Fault>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LoginResponse {
+    result: LoginResult,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LoginResult {
+    server_url: String,
+    session_id: String,
+    user_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Fault {
+    faultcode: String,
+    faultstring: String,
+}
+
+/// Logs in with a username and password through the SOAP API, for orgs that do not allow OAuth.
+/// The password must include the security token of the user when logging in from an untrusted
+/// network
+pub struct SoapLoginAuthenticator {
+    inner: HttpClient,
+    login_url: String,
+    version: String,
+    username: String,
+    password: String,
+}
+
+impl std::fmt::Debug for SoapLoginAuthenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SoapLoginAuthenticator")
+            .field("login_url", &self.login_url)
+            .field("version", &self.version)
+            .field("username", &self.username)
+            .field("password", &Redacted)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SoapLoginAuthenticator {
+    pub fn new(
+        server: AuthorizationServer,
+        version: String,
+        username: String,
+        password: String,
+    ) -> Self {
+        Self {
+            inner: HttpClient::new(),
+            login_url: server.to_string(),
+            version,
+            username,
+            password,
+        }
+    }
+
+    fn envelope(&self) -> String {
+        format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="utf-8"?>"#,
+                r#"<soapenv:Envelope xmlns:soapenv="http://schemas.xmlsoap.org/soap/envelope/" xmlns:urn="urn:partner.soap.sforce.com">"#,
+                "<soapenv:Body><urn:login><urn:username>{}</urn:username><urn:password>{}</urn:password></urn:login></soapenv:Body>",
+                "</soapenv:Envelope>"
+            ),
+            escape(self.username.as_str()),
+            escape(self.password.as_str())
+        )
+    }
+}
+
+// The server url of a login is the SOAP endpoint of the instance, such as
+// `https://acme.my.salesforce.com/services/Soap/u/58.0/00D000000000001`
+#[allow(clippy::result_large_err)]
+fn instance_url(server_url: &str) -> SfResult<String> {
+    let url = Url::parse(server_url).map_err(|err| Error::MalformedResponse {
+        error: err.to_string(),
+        body: server_url.to_string(),
+    })?;
+
+    Ok(url.origin().ascii_serialization())
+}
+
+#[async_trait]
+impl Authenticator for SoapLoginAuthenticator {
+    async fn get_token(&self) -> SfResult<SfAccessToken> {
+        let url = format!("{}/services/Soap/u/{}", self.login_url, self.version);
+        tracing::debug!(?url, "Requesting SOAP login");
+
+        let response = self
+            .inner
+            .post(&url)
+            .header(CONTENT_TYPE, "text/xml; charset=UTF-8")
+            .header("SOAPAction", "login")
+            .body(self.envelope())
+            .send()
+            .await?;
+
+        let headers = response.headers().clone();
+        let status = response.status();
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(Error::RateLimited {
+                retry_after: retry_after(&headers),
+            });
+        }
+
+        let text = response.text().await?;
+        let envelope: Envelope =
+            quick_xml::de::from_str(&text).map_err(|err| Error::MalformedResponse {
+                error: err.to_string(),
+                body: text.clone(),
+            })?;
+
+        match (envelope.body.response, envelope.body.fault) {
+            (Some(response), _) if status == StatusCode::OK => Ok(SfAccessToken {
+                instance_url: instance_url(&response.result.server_url)?,
+                access_token: response.result.session_id,
+                scope: String::new(),
+                id: response.result.user_id,
+                token_type: "Bearer".to_string(),
+            }),
+            (_, Some(fault)) => Err(SfResponse {
+                headers,
+                status,
+                body: Some(SfLoginError {
+                    error: fault
+                        .faultcode
+                        .rsplit(':')
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    error_description: fault.faultstring,
+                }),
+            })?,
+            _ => Err(SfResponse {
+                headers,
+                status,
+                body: Some(text),
+            })?,
+        }
+    }
+
+    fn set_http_client(&mut self, client: HttpClient) {
+        self.inner = client;
+    }
+
+    fn auth_url(&self) -> Option<&str> {
+        Some(&self.login_url)
+    }
+
+    async fn user_info(&self) -> SfResult<SfUserInfo> {
+        let token = self.get_token().await?;
+        self.user_info_with_token(&token).await
+    }
+
+    async fn user_info_with_token(&self, token: &SfAccessToken) -> SfResult<SfUserInfo> {
+        let url = OAuthPaths::url(&token.instance_url, &OAuthPaths::default().userinfo);
+        tracing::debug!(?url, "Requesting user info");
+
+        let response = self
+            .inner
+            .get(url)
+            .bearer_auth(&token.access_token)
+            .send()
+            .await?;
+
+        match response.status() {
+            StatusCode::OK => Ok(response.json().await?),
+            StatusCode::TOO_MANY_REQUESTS => Err(Error::RateLimited {
+                retry_after: retry_after(response.headers()),
+            }),
+            _ => Err(SfResponse {
+                headers: response.headers().clone(),
+                status: response.status(),
+                body: Some(response.text().await?),
+            })?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{body_string_contains, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{error::Error, Authenticator, AuthorizationServer};
+
+    use super::SoapLoginAuthenticator;
+
+    fn authenticator(server: &MockServer) -> SoapLoginAuthenticator {
+        SoapLoginAuthenticator::new(
+            AuthorizationServer::Custom(server.uri()),
+            "58.0".to_string(),
+            "user@acme.com".to_string(),
+            "p<ssword".to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_login() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/Soap/u/58.0"))
+            .and(header("SOAPAction", "login"))
+            .and(body_string_contains(
+                "<urn:username>user@acme.com</urn:username><urn:password>p&lt;ssword</urn:password>",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<soapenv:Envelope xmlns:soapenv="http://schemas.xmlsoap.org/soap/envelope/" xmlns="urn:partner.soap.sforce.com">
+  <soapenv:Body>
+    <loginResponse>
+      <result>
+        <metadataServerUrl>https://acme.my.salesforce.com/services/Soap/m/58.0/00D000000000001</metadataServerUrl>
+        <passwordExpired>false</passwordExpired>
+        <sandbox>false</sandbox>
+        <serverUrl>https://acme.my.salesforce.com/services/Soap/u/58.0/00D000000000001</serverUrl>
+        <sessionId>00D000000000001!session</sessionId>
+        <userId>005000000000001</userId>
+      </result>
+    </loginResponse>
+  </soapenv:Body>
+</soapenv:Envelope>"#,
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let token = authenticator(&server).get_token().await.unwrap();
+
+        assert_eq!("00D000000000001!session", token.access_token);
+        assert_eq!("https://acme.my.salesforce.com", token.instance_url);
+        assert_eq!("005000000000001", token.id);
+    }
+
+    #[tokio::test]
+    async fn test_login_fault() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/Soap/u/58.0"))
+            .respond_with(ResponseTemplate::new(500).set_body_string(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<soapenv:Envelope xmlns:soapenv="http://schemas.xmlsoap.org/soap/envelope/" xmlns:sf="urn:fault.partner.soap.sforce.com">
+  <soapenv:Body>
+    <soapenv:Fault>
+      <faultcode>sf:INVALID_LOGIN</faultcode>
+      <faultstring>INVALID_LOGIN: Invalid username, password, security token; or user locked out.</faultstring>
+    </soapenv:Fault>
+  </soapenv:Body>
+</soapenv:Envelope>"#,
+            ))
+            .mount(&server)
+            .await;
+
+        match authenticator(&server).get_token().await.unwrap_err() {
+            Error::LoginFailure(response) => {
+                assert_eq!("INVALID_LOGIN", response.body.unwrap().error)
+            }
+            err => panic!("Unexpected error {:?}", err),
+        }
+    }
+}
@@ -0,0 +1,404 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+use crate::{
+    error::{Error, SfResult},
+    CollectionError, CollectionResult, SObjectEnvelope, SfClient, MAX_COLLECTION_WRITE,
+};
+
+/// The result of creating a record that was enqueued in a [`BatchWriter`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchResult {
+    /// The position of the record in the order that records were enqueued, starting from zero
+    pub index: usize,
+    pub object: String,
+    /// The id of the created record, or the errors that prevented it from being created
+    pub outcome: Result<String, Vec<CollectionError>>,
+}
+
+#[derive(Debug)]
+struct PendingRecord {
+    index: usize,
+    record: SObjectEnvelope<Value>,
+}
+
+/// Accumulates records to create and creates them together through sObject Collections requests.
+/// Records are created once 200 are queued, or once the flush interval has passed since the
+/// oldest queued record was enqueued. Records that fail do not prevent the others from being
+/// created.
+///
+/// The writer does not run in the background. The flush interval is checked by
+/// [`BatchWriter::enqueue`], and [`BatchWriter::flush_when_due`] waits for it so that it can be
+/// polled with `tokio::select!` alongside the source of records. [`BatchWriter::flush`] must be
+/// called once no more records will be enqueued.
+///
+/// When a flush fails part way, the records that were already created are not lost. Their
+/// results are returned by the next flush, or can be taken with [`BatchWriter::take_completed`]
+pub struct BatchWriter<'a> {
+    client: &'a SfClient,
+    pending: Vec<PendingRecord>,
+    completed: Vec<BatchResult>,
+    enqueued: usize,
+    flush_interval: Option<Duration>,
+    oldest: Option<Instant>,
+}
+
+impl<'a> BatchWriter<'a> {
+    pub fn new(client: &'a SfClient) -> Self {
+        Self {
+            client,
+            pending: Vec::new(),
+            completed: Vec::new(),
+            enqueued: 0,
+            flush_interval: None,
+            oldest: None,
+        }
+    }
+
+    /// Flushes queued records once the oldest of them has been queued for the given interval
+    pub fn flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = Some(flush_interval);
+        self
+    }
+
+    /// The number of records that are queued and not yet created
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Takes the results of records that were created by a flush that then failed
+    pub fn take_completed(&mut self) -> Vec<BatchResult> {
+        std::mem::take(&mut self.completed)
+    }
+
+    fn due_at(&self) -> Option<Instant> {
+        Some(self.oldest? + self.flush_interval?)
+    }
+
+    /// Queues a record to be created, creating the queued records if the batch is full or the
+    /// flush interval has passed. Returns the results of any records that were created
+    pub async fn enqueue<T>(&mut self, object: &str, record: T) -> SfResult<Vec<BatchResult>>
+    where
+        T: Serialize,
+    {
+        let record =
+            serde_json::to_value(record).map_err(|err| Error::InvalidInput(err.to_string()))?;
+
+        self.pending.push(PendingRecord {
+            index: self.enqueued,
+            record: SObjectEnvelope::new(object.to_string(), record),
+        });
+        self.enqueued += 1;
+        self.oldest.get_or_insert_with(Instant::now);
+
+        let due = self.due_at().is_some_and(|due_at| due_at <= Instant::now());
+
+        if self.pending.len() >= MAX_COLLECTION_WRITE || due {
+            self.flush().await
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    /// Waits until the flush interval has passed since the oldest queued record was enqueued and
+    /// then creates the queued records. Never completes while no records are queued or no flush
+    /// interval is set
+    pub async fn flush_when_due(&mut self) -> SfResult<Vec<BatchResult>> {
+        match self.due_at() {
+            Some(due_at) => {
+                tokio::time::sleep_until(due_at.into()).await;
+                self.flush().await
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Creates every queued record. Records remain queued if a request fails, while the results
+    /// of records created by earlier requests of the flush are kept for the next flush
+    pub async fn flush(&mut self) -> SfResult<Vec<BatchResult>> {
+        while !self.pending.is_empty() {
+            let count = self.pending.len().min(MAX_COLLECTION_WRITE);
+
+            // Records of the same object are grouped, as a request may only switch between
+            // objects a limited number of times
+            let mut batch = self.pending[..count].iter().collect::<Vec<_>>();
+            batch.sort_by(|a, b| a.record.attributes.type_.cmp(&b.record.attributes.type_));
+
+            let body = serde_json::json!({
+                "allOrNone": false,
+                "records": batch.iter().map(|pending| &pending.record).collect::<Vec<_>>(),
+            });
+
+            let response = self
+                .client
+                .post::<_, Value>("composite/sobjects", body, &[StatusCode::OK])
+                .await?
+                .into_body()?;
+
+            let results = match response.as_array().map(Vec::len) {
+                Some(len) if len == batch.len() => {
+                    serde_json::from_value::<Vec<CollectionResult>>(response.clone()).ok()
+                }
+                _ => None,
+            }
+            .ok_or_else(|| Error::MalformedResponse {
+                error: format!("Expected a result for each of {} records", batch.len()),
+                body: response.to_string(),
+            })?;
+
+            self.completed.extend(
+                batch
+                    .iter()
+                    .zip(results)
+                    .map(|(pending, result)| BatchResult {
+                        index: pending.index,
+                        object: pending.record.attributes.type_.clone(),
+                        outcome: match (result.success, result.id) {
+                            (true, Some(id)) => Ok(id),
+                            _ => Err(result.errors),
+                        },
+                    }),
+            );
+
+            self.pending.drain(..count);
+        }
+
+        self.oldest = None;
+
+        let mut results = self.take_completed();
+        results.sort_by_key(|result| result.index);
+
+        Ok(results)
+    }
+}
+
+impl Drop for BatchWriter<'_> {
+    fn drop(&mut self) {
+        if !self.pending.is_empty() {
+            tracing::warn!(
+                records = self.pending.len(),
+                "Batch writer dropped with records that were not created"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{authenticator::jwt::tests::add_token_mock, tests::get_client};
+
+    use super::BatchWriter;
+
+    // Responds with a result for each record, failing records without a name
+    fn respond(request: &wiremock::Request) -> ResponseTemplate {
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let results = body["records"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| match record["Name"].as_str() {
+                Some(name) => serde_json::json!({ "id": format!("id-{}", name), "success": true, "errors": [] }),
+                None => serde_json::json!({
+                    "id": null,
+                    "success": false,
+                    "errors": [{ "statusCode": "REQUIRED_FIELD_MISSING", "message": "Required fields are missing: [Name]", "fields": ["Name"] }]
+                }),
+            })
+            .collect::<Vec<_>>();
+
+        ResponseTemplate::new(200).set_body_json(results)
+    }
+
+    #[tokio::test]
+    async fn test_batch_writer() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/composite/sobjects"))
+            .respond_with(respond)
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let mut writer = BatchWriter::new(&client);
+
+        // The first 199 records are queued and the 200th flushes the batch
+        for i in 0..199 {
+            let object = if i % 2 == 0 { "Account" } else { "Contact" };
+            let results = writer
+                .enqueue(object, serde_json::json!({ "Name": i.to_string() }))
+                .await
+                .unwrap();
+            assert!(results.is_empty());
+        }
+
+        let results = writer
+            .enqueue("Account", serde_json::json!({}))
+            .await
+            .unwrap();
+        assert_eq!(200, results.len());
+        assert_eq!(0, writer.pending());
+
+        assert_eq!(0, results[0].index);
+        assert_eq!("Account", results[0].object);
+        assert_eq!(Ok("id-0".to_string()), results[0].outcome);
+        assert_eq!("Contact", results[1].object);
+        assert_eq!(Ok("id-1".to_string()), results[1].outcome);
+        assert_eq!(
+            "REQUIRED_FIELD_MISSING",
+            results[199].outcome.as_ref().unwrap_err()[0].status_code
+        );
+
+        writer
+            .enqueue("Account", serde_json::json!({ "Name": "last" }))
+            .await
+            .unwrap();
+        let results = writer.flush().await.unwrap();
+        assert_eq!(200, results[0].index);
+        assert_eq!(Ok("id-last".to_string()), results[0].outcome);
+    }
+
+    #[tokio::test]
+    async fn test_batch_writer_flush_interval() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/composite/sobjects"))
+            .respond_with(respond)
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let mut writer = BatchWriter::new(&client).flush_interval(Duration::from_millis(50));
+
+        assert!(writer
+            .enqueue("Account", serde_json::json!({ "Name": "a" }))
+            .await
+            .unwrap()
+            .is_empty());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        let results = writer
+            .enqueue("Account", serde_json::json!({ "Name": "b" }))
+            .await
+            .unwrap();
+        assert_eq!(2, results.len());
+        assert_eq!(0, writer.pending());
+    }
+
+    #[tokio::test]
+    async fn test_batch_writer_flush_when_due() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/composite/sobjects"))
+            .respond_with(respond)
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let mut writer = BatchWriter::new(&client).flush_interval(Duration::from_millis(50));
+
+        // Nothing is due while no records are queued
+        assert!(
+            tokio::time::timeout(Duration::from_millis(60), writer.flush_when_due())
+                .await
+                .is_err()
+        );
+
+        let start = std::time::Instant::now();
+        writer
+            .enqueue("Account", serde_json::json!({ "Name": "a" }))
+            .await
+            .unwrap();
+
+        let results = writer.flush_when_due().await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(1, results.len());
+        assert_eq!(0, writer.pending());
+    }
+
+    #[tokio::test]
+    async fn test_batch_writer_partial_flush() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let request =
+            || Mock::given(method("POST")).and(path("/services/data/v12345.0/composite/sobjects"));
+
+        request()
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        request()
+            .respond_with(respond)
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        request()
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([])))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        request().respond_with(respond).mount(&server).await;
+
+        let client = get_client(&server).await;
+        let mut writer = BatchWriter::new(&client);
+
+        for i in 0..199 {
+            writer
+                .enqueue("Account", serde_json::json!({ "Name": i.to_string() }))
+                .await
+                .unwrap();
+        }
+
+        // The failed request leaves every record queued
+        assert!(writer
+            .enqueue("Account", serde_json::json!({ "Name": "199" }))
+            .await
+            .is_err());
+        assert_eq!(200, writer.pending());
+
+        // The first request succeeds, but the second is missing results
+        let error = writer
+            .enqueue("Account", serde_json::json!({ "Name": "200" }))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            crate::error::Error::MalformedResponse { .. }
+        ));
+        assert_eq!(1, writer.pending());
+
+        let results = writer.flush().await.unwrap();
+        assert_eq!(201, results.len());
+        assert_eq!(0, writer.pending());
+        assert!(results
+            .iter()
+            .enumerate()
+            .all(|(i, result)| i == result.index));
+        assert_eq!(Ok("id-200".to_string()), results[200].outcome);
+    }
+}
@@ -0,0 +1,255 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use reqwest::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+    multipart::{Form, Part},
+    Body, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncRead;
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    error::{Error, SfResult},
+    util::failure,
+    CreateObjectResponse, SfClient, SfResponse,
+};
+
+/// The size and type of the data in a blob field
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct BlobInfo {
+    pub content_length: Option<u64>,
+    pub content_type: Option<String>,
+}
+
+// The JSON part of a multipart insert is named for the object, except for ContentVersion
+fn entity_part_name(object: &str) -> String {
+    if object.eq_ignore_ascii_case("ContentVersion") {
+        "entity_content".to_string()
+    } else {
+        format!("entity_{}", object.to_lowercase())
+    }
+}
+
+impl SfClient {
+    /// Creates a record with binary data in a blob field, such as the `VersionData` of a
+    /// `ContentVersion` or the `Body` of a `Document`. The data is sent as part of a multipart
+    /// request, which allows files larger than the limit of base64 encoded JSON bodies
+    pub async fn create_blob<T>(
+        &self,
+        object: &str,
+        blob_field: &str,
+        record: T,
+        file_name: &str,
+        data: Vec<u8>,
+    ) -> SfResult<SfResponse<CreateObjectResponse>>
+    where
+        T: Serialize,
+    {
+        self.create_blob_part(object, blob_field, record, file_name, Part::bytes(data))
+            .await
+    }
+
+    /// Creates a record with binary data in a blob field, streaming the data from a reader
+    /// rather than buffering it in memory. Salesforce requires the length of the data for some
+    /// objects, so it should be provided when known. Streamed requests are not retried
+    pub async fn create_blob_from_reader<T, R>(
+        &self,
+        object: &str,
+        blob_field: &str,
+        record: T,
+        file_name: &str,
+        reader: R,
+        length: Option<u64>,
+    ) -> SfResult<SfResponse<CreateObjectResponse>>
+    where
+        T: Serialize,
+        R: AsyncRead + Send + Sync + 'static,
+    {
+        let body = Body::wrap_stream(ReaderStream::new(reader));
+        let part = match length {
+            Some(length) => Part::stream_with_length(body, length),
+            None => Part::stream(body),
+        };
+
+        self.create_blob_part(object, blob_field, record, file_name, part)
+            .await
+    }
+
+    /// Fetches the size and type of the data in a blob field without downloading it
+    pub async fn blob_info(&self, object: &str, id: &str, field: &str) -> SfResult<BlobInfo> {
+        let url = self.url(&format!("{}/{}", self.record_path(object, id), field));
+        tracing::trace!(?url, "HEAD request");
+
+        self.send_with(
+            self.inner.head(&url).bearer_auth(&self.bearer),
+            |response| async move {
+                if response.status() != StatusCode::OK {
+                    return Err(failure(response).await);
+                }
+
+                let headers = response.headers();
+                let header = |name| {
+                    headers
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                        .map(str::to_string)
+                };
+
+                Ok(BlobInfo {
+                    content_length: header(CONTENT_LENGTH).and_then(|value| value.parse().ok()),
+                    content_type: header(CONTENT_TYPE),
+                })
+            },
+        )
+        .await
+    }
+
+    async fn create_blob_part<T>(
+        &self,
+        object: &str,
+        blob_field: &str,
+        record: T,
+        file_name: &str,
+        part: Part,
+    ) -> SfResult<SfResponse<CreateObjectResponse>>
+    where
+        T: Serialize,
+    {
+        let entity =
+            serde_json::to_string(&record).map_err(|err| Error::InvalidInput(err.to_string()))?;
+
+        let form = Form::new()
+            .part(
+                entity_part_name(object),
+                Part::text(entity).mime_str("application/json")?,
+            )
+            .part(
+                blob_field.to_string(),
+                part.file_name(file_name.to_string())
+                    .mime_str("application/octet-stream")?,
+            );
+
+        let url = self.url(&self.object_path(object));
+        tracing::trace!(?url, "Multipart POST request");
+
+        self.send(
+            self.inner
+                .post(&url)
+                .bearer_auth(&self.bearer)
+                .multipart(form),
+            &[StatusCode::CREATED],
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use wiremock::{
+        matchers::{header_regex, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{authenticator::jwt::tests::add_token_mock, tests::get_client, SfResponse};
+
+    use super::{entity_part_name, BlobInfo};
+
+    #[test]
+    fn test_entity_part_name() {
+        assert_eq!("entity_content", entity_part_name("ContentVersion"));
+        assert_eq!("entity_document", entity_part_name("Document"));
+    }
+
+    #[tokio::test]
+    async fn test_blob_info() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("HEAD"))
+            .and(path(
+                "/services/data/v12345.0/sobjects/ContentVersion/068000000000001/VersionData",
+            ))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Content-Type", "application/pdf")
+                    .insert_header("Content-Length", "52428800"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let info = client
+            .blob_info("ContentVersion", "068000000000001", "VersionData")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            BlobInfo {
+                content_length: Some(52428800),
+                content_type: Some("application/pdf".to_string())
+            },
+            info
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_blob_from_reader() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/sobjects/ContentVersion"))
+            .and(header_regex(
+                "Content-Type",
+                "^multipart/form-data; boundary=",
+            ))
+            .respond_with(|request: &wiremock::Request| {
+                let body = String::from_utf8_lossy(&request.body);
+                let expected = [
+                    r#"name="entity_content""#,
+                    r#"{"PathOnClient":"report.pdf","Title":"Report"}"#,
+                    r#"name="VersionData"; filename="report.pdf""#,
+                    "%PDF-1.4 contents",
+                ];
+
+                if expected.iter().all(|part| body.contains(part)) {
+                    ResponseTemplate::new(201).set_body_json(serde_json::json!({
+                        "id": "068000000000001",
+                        "success": true,
+                        "errors": []
+                    }))
+                } else {
+                    ResponseTemplate::new(400)
+                }
+            })
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let data = b"%PDF-1.4 contents".to_vec();
+        let length = data.len() as u64;
+
+        let response = client
+            .create_blob_from_reader(
+                "ContentVersion",
+                "VersionData",
+                serde_json::json!({ "PathOnClient": "report.pdf", "Title": "Report" }),
+                "report.pdf",
+                Cursor::new(data),
+                Some(length),
+            )
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        assert_eq!(Some("068000000000001".to_string()), response.id);
+    }
+}
@@ -0,0 +1,361 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use reqwest::{Client, Url, Version};
+use std::time::Duration;
+
+use crate::{
+    error::{Error, SfResult},
+    Authenticator, SfClient,
+};
+
+// Generic login domains always differ from the instance they authenticate for
+const LOGIN_HOSTS: [&str; 2] = ["login.salesforce.com", "test.salesforce.com"];
+
+// Whether a token was issued for an instance other than the domain it was requested from, which
+// is usually the result of a My Domain change or an org migration
+fn instance_mismatch(auth_url: &str, instance_url: &str) -> bool {
+    let host = |url: &str| {
+        Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_lowercase))
+    };
+
+    match (host(auth_url), host(instance_url)) {
+        (Some(auth_host), Some(instance_host)) => {
+            !LOGIN_HOSTS.contains(&auth_host.as_str()) && auth_host != instance_host
+        }
+        _ => false,
+    }
+}
+
+/// Tuning of the connection pool of the HTTP client. The defaults match those of `reqwest`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolConfig {
+    /// The maximum number of idle connections kept open to each host
+    pub max_idle_per_host: usize,
+    /// How long idle connections are kept open, or indefinitely if `None`
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_idle_per_host: usize::MAX,
+            idle_timeout: Some(Duration::from_secs(90)),
+        }
+    }
+}
+
+/// Builds an [`SfClient`] along with the HTTP client that it uses. The HTTP client is shared with
+/// the authenticator so that token requests are made with the same configuration
+#[derive(Clone, Debug)]
+pub struct SfClientBuilder {
+    version: String,
+    danger_accept_invalid_certs: bool,
+    pool_config: PoolConfig,
+    http_version: Option<Version>,
+}
+
+impl SfClientBuilder {
+    pub fn new(version: String) -> Self {
+        Self {
+            version,
+            danger_accept_invalid_certs: false,
+            pool_config: PoolConfig::default(),
+            http_version: None,
+        }
+    }
+
+    /// Disables verification of TLS certificates, for development against local instances or
+    /// proxies with self-signed certificates.
+    ///
+    /// **This must never be used in production.** Any certificate is trusted, including those
+    /// for hosts other than the instance, which leaves all requests open to interception
+    pub fn with_danger_accept_invalid_certs(mut self, accept_invalid_certs: bool) -> Self {
+        self.danger_accept_invalid_certs = accept_invalid_certs;
+        self
+    }
+
+    /// Configures the connection pool, such as to keep more connections open to reduce
+    /// connection churn under sustained load
+    pub fn with_pool_config(mut self, pool_config: PoolConfig) -> Self {
+        self.pool_config = pool_config;
+        self
+    }
+
+    /// Sends every request with the given HTTP version, either HTTP/1.1 or HTTP/2, rather than
+    /// negotiating the version with the server. HTTP/2 multiplexes concurrent requests over a
+    /// single connection
+    pub fn with_http_version(mut self, version: Version) -> Self {
+        self.http_version = Some(version);
+        self
+    }
+
+    /// Sends every request with HTTP/2 without first negotiating it with the server
+    pub fn with_http2_prior_knowledge(self) -> Self {
+        self.with_http_version(Version::HTTP_2)
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn http_client(&self) -> SfResult<Client> {
+        if self.danger_accept_invalid_certs {
+            tracing::warn!("TLS certificate verification is disabled");
+        }
+
+        let builder = Client::builder()
+            .danger_accept_invalid_certs(self.danger_accept_invalid_certs)
+            .pool_max_idle_per_host(self.pool_config.max_idle_per_host)
+            .pool_idle_timeout(self.pool_config.idle_timeout);
+
+        let builder = match self.http_version {
+            None => builder,
+            Some(Version::HTTP_11) => builder.http1_only(),
+            Some(Version::HTTP_2) => builder.http2_prior_knowledge(),
+            Some(version) => {
+                return Err(Error::InvalidInput(format!(
+                    "HTTP version {:?} is not supported",
+                    version
+                )))
+            }
+        };
+
+        Ok(builder.build()?)
+    }
+
+    pub async fn build(self, mut authenticator: impl Authenticator) -> SfResult<SfClient> {
+        let client = self.http_client()?;
+        authenticator.set_http_client(client.clone());
+
+        let token = authenticator.get_token().await?;
+        let auth_url = authenticator.auth_url().map(str::to_string);
+
+        if let Some(auth_url) = &auth_url {
+            if instance_mismatch(auth_url, &token.instance_url) {
+                tracing::warn!(
+                    auth_url,
+                    instance_url = token.instance_url,
+                    "Token was issued for an instance other than the domain it was requested from"
+                );
+            }
+        }
+
+        Ok(SfClient::from_token(client, self.version, token, auth_url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Method, Version};
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use tokio::{
+        io::copy_bidirectional,
+        net::{TcpListener, TcpStream},
+    };
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        authenticator::{
+            jwt::{
+                tests::{add_token_mock, private_key},
+                JwtAuthenticator, LoginClaims,
+            },
+            SfAccessToken,
+        },
+        error::Error,
+        AuthorizationServer, SfClient,
+    };
+
+    use super::{instance_mismatch, PoolConfig};
+
+    fn authenticator(server: &MockServer) -> JwtAuthenticator {
+        let claims = LoginClaims::new(
+            "123".to_string(),
+            AuthorizationServer::Test,
+            "test@company.com".to_string(),
+        );
+        JwtAuthenticator::new(&server.uri(), claims, private_key()).unwrap()
+    }
+
+    // Forwards connections to the server, counting each connection that is opened
+    async fn counting_proxy(server: &MockServer) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap());
+        let target = *server.address();
+
+        let connections = Arc::new(AtomicUsize::new(0));
+        let count = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut inbound, _)) = listener.accept().await {
+                count.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut outbound = TcpStream::connect(target).await.unwrap();
+                    let _ = copy_bidirectional(&mut inbound, &mut outbound).await;
+                });
+            }
+        });
+
+        (uri, connections)
+    }
+
+    #[tokio::test]
+    async fn test_build_with_pool_config() {
+        let server = MockServer::start().await;
+        let (proxy_uri, connections) = counting_proxy(&server).await;
+
+        // Route requests after login through the proxy, so that only their connections are counted
+        Mock::given(method("POST"))
+            .and(path("/services/oauth2/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(SfAccessToken {
+                access_token: "access_token".to_string(),
+                scope: "scope".to_string(),
+                instance_url: proxy_uri,
+                id: "id".to_string(),
+                token_type: "token_type".to_string(),
+            }))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/123"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .expect(4)
+            .mount(&server)
+            .await;
+
+        let no_idle = PoolConfig {
+            max_idle_per_host: 0,
+            idle_timeout: Some(Duration::from_secs(1)),
+        };
+
+        // Idle connections are reused by default, and closed when none may be kept
+        for (pool_config, expected) in [(PoolConfig::default(), 1), (no_idle, 2)] {
+            let client = SfClient::builder("12345.0".to_string())
+                .with_pool_config(pool_config)
+                .build(authenticator(&server))
+                .await
+                .unwrap();
+
+            let before = connections.load(Ordering::SeqCst);
+            for _ in 0..2 {
+                client
+                    .get_object::<serde_json::Value>("Lead", "123")
+                    .await
+                    .unwrap();
+            }
+            assert_eq!(expected, connections.load(Ordering::SeqCst) - before);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_with_http_version() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/limits"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .mount(&server)
+            .await;
+
+        for version in [Version::HTTP_11, Version::HTTP_2] {
+            let client = SfClient::builder("12345.0".to_string())
+                .with_http_version(version)
+                .build(authenticator(&server))
+                .await
+                .unwrap();
+
+            let response = client
+                .send_raw::<()>(Method::GET, "limits", None)
+                .await
+                .unwrap();
+            assert_eq!(version, response.version());
+        }
+
+        let err = SfClient::builder("12345.0".to_string())
+            .with_http_version(Version::HTTP_3)
+            .build(authenticator(&server))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput(_)));
+    }
+
+    #[test]
+    fn test_instance_mismatch() {
+        assert!(!instance_mismatch(
+            "https://acme.my.salesforce.com",
+            "https://acme.my.salesforce.com/"
+        ));
+        assert!(!instance_mismatch(
+            "https://login.salesforce.com",
+            "https://acme.my.salesforce.com"
+        ));
+        assert!(instance_mismatch(
+            "https://acme.my.salesforce.com",
+            "https://acme2.my.salesforce.com"
+        ));
+        assert!(!instance_mismatch(
+            "not a url",
+            "https://acme.my.salesforce.com"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_auth_url() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/oauth2/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(SfAccessToken {
+                access_token: "access_token".to_string(),
+                scope: "scope".to_string(),
+                instance_url: "https://acme2.my.salesforce.com".to_string(),
+                id: "id".to_string(),
+                token_type: "token_type".to_string(),
+            }))
+            .mount(&server)
+            .await;
+
+        let client = SfClient::new("12345.0".to_string(), authenticator(&server))
+            .await
+            .unwrap();
+
+        assert_eq!(Some(server.uri().as_str()), client.auth_url());
+        assert_eq!("https://acme2.my.salesforce.com", client.instance_url());
+    }
+
+    #[tokio::test]
+    async fn test_build_accepting_invalid_certs() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/123"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .mount(&server)
+            .await;
+
+        let client = SfClient::builder("12345.0".to_string())
+            .with_danger_accept_invalid_certs(true)
+            .build(authenticator(&server))
+            .await
+            .unwrap();
+
+        client
+            .get_object::<serde_json::Value>("Lead", "123")
+            .await
+            .unwrap();
+    }
+}
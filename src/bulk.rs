@@ -0,0 +1,783 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use csv::{ByteRecord, ReaderBuilder};
+use flate2::{write::GzEncoder, Compression};
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use reqwest::{
+    header::{ACCEPT, CONTENT_ENCODING, CONTENT_TYPE},
+    Response, StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{io::Write, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    error::{Error, SfResult},
+    util::failure,
+    SfClient, SfResponse,
+};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BulkOperation {
+    Insert,
+    Update,
+    Upsert,
+    Delete,
+    HardDelete,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub enum BulkJobState {
+    Open,
+    UploadComplete,
+    InProgress,
+    JobComplete,
+    Failed,
+    Aborted,
+}
+
+impl BulkJobState {
+    /// Whether the job has stopped processing, successfully or not
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::JobComplete | Self::Failed | Self::Aborted)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateIngestJobRequest {
+    pub object: String,
+    pub operation: BulkOperation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_id_field_name: Option<String>,
+    pub content_type: String,
+}
+
+impl CreateIngestJobRequest {
+    pub fn new(object: String, operation: BulkOperation) -> Self {
+        Self {
+            object,
+            operation,
+            external_id_field_name: None,
+            content_type: "CSV".to_string(),
+        }
+    }
+
+    pub fn external_id_field_name(mut self, field: String) -> Self {
+        self.external_id_field_name = Some(field);
+        self
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkJobInfo {
+    pub id: String,
+    pub object: String,
+    pub operation: String,
+    pub state: BulkJobState,
+    #[serde(default)]
+    pub number_records_processed: Option<u64>,
+    #[serde(default)]
+    pub number_records_failed: Option<u64>,
+    #[serde(default)]
+    pub error_message: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateQueryJobRequest {
+    pub operation: String,
+    pub query: String,
+}
+
+impl CreateQueryJobRequest {
+    pub fn new(query: String) -> Self {
+        Self {
+            operation: "query".to_string(),
+            query,
+        }
+    }
+
+    /// Includes deleted and archived records in the results
+    pub fn query_all(mut self) -> Self {
+        self.operation = "queryAll".to_string();
+        self
+    }
+}
+
+// Locator header of query job results. A value of "null" marks the last page
+const SFORCE_LOCATOR: &str = "Sforce-Locator";
+
+#[derive(Debug, Serialize)]
+struct UpdateJobState {
+    state: BulkJobState,
+}
+
+// Splits CSV data that arrives in chunks into whole records, so that each record can be
+// deserialized as soon as it has arrived rather than after the whole body has been read
+struct CsvRecords {
+    buffer: Vec<u8>,
+    // How far the buffer has been scanned for the end of a record, and whether that position is
+    // within a quoted field, where line breaks do not end the record
+    scanned: usize,
+    quoted: bool,
+    headers: Option<ByteRecord>,
+}
+
+impl CsvRecords {
+    fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            scanned: 0,
+            quoted: false,
+            headers: None,
+        }
+    }
+
+    // Deserializes the records that were completed by a chunk
+    #[allow(clippy::result_large_err)]
+    fn push<T>(&mut self, chunk: &[u8]) -> SfResult<Vec<T>>
+    where
+        T: DeserializeOwned,
+    {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut end = 0;
+        for (i, byte) in self.buffer.iter().enumerate().skip(self.scanned) {
+            match byte {
+                // Escaped quotes are doubled, so they toggle the state twice
+                b'"' => self.quoted = !self.quoted,
+                b'\n' if !self.quoted => end = i + 1,
+                _ => {}
+            }
+        }
+        self.scanned = self.buffer.len();
+
+        self.take(end)
+    }
+
+    // Deserializes the last record, which does not necessarily end with a line break
+    #[allow(clippy::result_large_err)]
+    fn finish<T>(&mut self) -> SfResult<Vec<T>>
+    where
+        T: DeserializeOwned,
+    {
+        self.take(self.buffer.len())
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn take<T>(&mut self, end: usize) -> SfResult<Vec<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let complete: Vec<u8> = self.buffer.drain(..end).collect();
+        self.scanned -= end;
+
+        let mut reader = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(complete.as_slice());
+        let mut records = Vec::new();
+
+        for record in reader.byte_records() {
+            let record = record?;
+            match &self.headers {
+                Some(headers) => records.push(record.deserialize(Some(headers))?),
+                None => self.headers = Some(record),
+            }
+        }
+
+        Ok(records)
+    }
+}
+
+// Deserializes the records of a CSV response as they arrive
+fn csv_records<T>(response: Response) -> impl Stream<Item = SfResult<T>>
+where
+    T: DeserializeOwned,
+{
+    let chunks = response.bytes_stream();
+
+    stream::try_unfold(
+        Some((Box::pin(chunks), CsvRecords::new())),
+        |state| async move {
+            let Some((mut chunks, mut records)) = state else {
+                return Ok::<_, Error>(None);
+            };
+
+            loop {
+                match chunks.next().await {
+                    Some(chunk) => {
+                        let complete = records.push(&chunk?)?;
+                        if !complete.is_empty() {
+                            return Ok(Some((
+                                stream::iter(complete.into_iter().map(Ok)),
+                                Some((chunks, records)),
+                            )));
+                        }
+                    }
+                    None => {
+                        let complete = records.finish()?;
+                        return Ok(Some((stream::iter(complete.into_iter().map(Ok)), None)));
+                    }
+                }
+            }
+        },
+    )
+    .try_flatten()
+}
+
+fn gzip_encode(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+impl SfClient {
+    /// Creates a Bulk API 2.0 ingest job. Data is then added to the job with
+    /// [`SfClient::upload_ingest_job_data`] and the job is queued for processing with
+    /// [`SfClient::close_ingest_job`]
+    pub async fn create_ingest_job(
+        &self,
+        request: &CreateIngestJobRequest,
+    ) -> SfResult<SfResponse<BulkJobInfo>> {
+        self.post("jobs/ingest/", request, &[StatusCode::OK]).await
+    }
+
+    pub async fn ingest_job(&self, job_id: &str) -> SfResult<SfResponse<BulkJobInfo>> {
+        self.get(&format!("jobs/ingest/{}/", job_id)).await
+    }
+
+    /// Uploads the CSV data of an open ingest job. When `gzip` is set the data is compressed before
+    /// it is sent, which considerably reduces the size of large uploads
+    pub async fn upload_ingest_job_data(
+        &self,
+        job_id: &str,
+        csv: Vec<u8>,
+        gzip: bool,
+    ) -> SfResult<SfResponse<()>> {
+        let url = self.url(&format!("jobs/ingest/{}/batches/", job_id));
+        tracing::trace!(?url, gzip, "PUT request");
+
+        let mut request = self
+            .inner
+            .put(&url)
+            .bearer_auth(&self.bearer)
+            .header(CONTENT_TYPE, "text/csv");
+
+        request = if gzip {
+            // Compressing large uploads takes long enough to stall other tasks of the runtime
+            let compressed = tokio::task::spawn_blocking(move || gzip_encode(&csv))
+                .await
+                .map_err(|err| Error::Compression(std::io::Error::other(err)))?
+                .map_err(Error::Compression)?;
+
+            request.header(CONTENT_ENCODING, "gzip").body(compressed)
+        } else {
+            request.body(csv)
+        };
+
+        self.send(request, &[StatusCode::CREATED]).await
+    }
+
+    /// Marks all data as uploaded, queueing the job for processing
+    pub async fn close_ingest_job(&self, job_id: &str) -> SfResult<SfResponse<BulkJobInfo>> {
+        self.update_job_state(
+            &format!("jobs/ingest/{}/", job_id),
+            BulkJobState::UploadComplete,
+        )
+        .await
+    }
+
+    pub async fn abort_ingest_job(&self, job_id: &str) -> SfResult<SfResponse<BulkJobInfo>> {
+        self.update_job_state(&format!("jobs/ingest/{}/", job_id), BulkJobState::Aborted)
+            .await
+    }
+
+    /// Polls an ingest job at the given interval until it has finished processing. If the token
+    /// is cancelled first the job is aborted, so that it does not continue to run, and
+    /// [`Error::Cancelled`] is returned
+    pub async fn wait_for_ingest_job(
+        &self,
+        job_id: &str,
+        interval: Duration,
+        cancel: &CancellationToken,
+    ) -> SfResult<BulkJobInfo> {
+        self.wait_for_job(&format!("jobs/ingest/{}/", job_id), interval, cancel)
+            .await
+    }
+
+    /// Creates a Bulk API 2.0 query job. Once [`SfClient::query_job`] reports the job as
+    /// complete the results can be read with [`SfClient::query_job_results`]
+    pub async fn create_query_job(
+        &self,
+        request: &CreateQueryJobRequest,
+    ) -> SfResult<SfResponse<BulkJobInfo>> {
+        self.post("jobs/query", request, &[StatusCode::OK]).await
+    }
+
+    pub async fn query_job(&self, job_id: &str) -> SfResult<SfResponse<BulkJobInfo>> {
+        self.get(&format!("jobs/query/{}", job_id)).await
+    }
+
+    pub async fn abort_query_job(&self, job_id: &str) -> SfResult<SfResponse<BulkJobInfo>> {
+        self.update_job_state(&format!("jobs/query/{}", job_id), BulkJobState::Aborted)
+            .await
+    }
+
+    /// Polls a query job until it has finished processing, aborting it if the token is cancelled
+    /// first. See [`SfClient::wait_for_ingest_job`]
+    pub async fn wait_for_query_job(
+        &self,
+        job_id: &str,
+        interval: Duration,
+        cancel: &CancellationToken,
+    ) -> SfResult<BulkJobInfo> {
+        self.wait_for_job(&format!("jobs/query/{}", job_id), interval, cancel)
+            .await
+    }
+
+    /// Streams the results of a completed query job, deserializing each CSV row into a record as
+    /// soon as it has been received, so that pages are never held in memory as a whole. Result
+    /// pages are fetched as the stream is consumed, following the `Sforce-Locator` of each page
+    /// until all results have been read
+    pub fn query_job_results<T>(&self, job_id: &str) -> impl Stream<Item = SfResult<T>> + '_
+    where
+        T: DeserializeOwned + 'static,
+    {
+        let url = self.url(&format!("jobs/query/{}/results", job_id));
+
+        // The state holds the locator of the next page, which is absent for the first page. The
+        // state itself is None once every page has been read
+        stream::try_unfold(Some(None), move |locator: Option<Option<String>>| {
+            let url = url.clone();
+
+            async move {
+                let Some(locator) = locator else {
+                    return Ok::<_, Error>(None);
+                };
+
+                let (records, next) = self
+                    .query_job_results_page::<T>(&url, locator.as_deref())
+                    .await?;
+
+                Ok(Some((records, next.map(Some))))
+            }
+        })
+        .try_flatten()
+    }
+
+    pub(crate) async fn query_job_results_page<T>(
+        &self,
+        url: &str,
+        locator: Option<&str>,
+    ) -> SfResult<(impl Stream<Item = SfResult<T>>, Option<String>)>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        tracing::trace!(?url, ?locator, "GET request");
+
+        let mut request = self
+            .inner
+            .get(url)
+            .bearer_auth(&self.bearer)
+            .header(ACCEPT, "text/csv");
+
+        if let Some(locator) = locator {
+            request = request.query(&[("locator", locator)]);
+        }
+
+        self.send_with(request, |response| async move {
+            if response.status() != StatusCode::OK {
+                return Err(failure(response).await);
+            }
+
+            let next = response
+                .headers()
+                .get(SFORCE_LOCATOR)
+                .and_then(|value| value.to_str().ok())
+                .filter(|value| !value.is_empty() && *value != "null")
+                .map(|value| value.to_string());
+
+            Ok((csv_records(response), next))
+        })
+        .await
+    }
+
+    async fn update_job_state(
+        &self,
+        path: &str,
+        state: BulkJobState,
+    ) -> SfResult<SfResponse<BulkJobInfo>> {
+        self.patch(path, UpdateJobState { state }).await
+    }
+
+    async fn wait_for_job(
+        &self,
+        path: &str,
+        interval: Duration,
+        cancel: &CancellationToken,
+    ) -> SfResult<BulkJobInfo> {
+        loop {
+            let job: BulkJobInfo = tokio::select! {
+                job = self.get(path) => job?.into_body()?,
+                _ = cancel.cancelled() => return self.cancel_job(path).await,
+            };
+
+            if job.state.is_finished() {
+                return Ok(job);
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = cancel.cancelled() => return self.cancel_job(path).await,
+            }
+        }
+    }
+
+    async fn cancel_job(&self, path: &str) -> SfResult<BulkJobInfo> {
+        // The job may have finished in the meantime, in which case it can no longer be aborted
+        if let Err(err) = self.update_job_state(path, BulkJobState::Aborted).await {
+            tracing::warn!(?err, path, "Failed to abort cancelled job");
+        }
+
+        Err(Error::Cancelled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::read::GzDecoder;
+    use futures::TryStreamExt;
+    use serde::Deserialize;
+    use std::{io::Read, time::Duration};
+    use tokio_util::sync::CancellationToken;
+    use wiremock::{
+        matchers::{body_json, header, method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{authenticator::jwt::tests::add_token_mock, error::Error, tests::get_client};
+
+    use super::{BulkJobState, BulkOperation, CreateIngestJobRequest, CsvRecords};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "PascalCase")]
+    struct Lead {
+        id: String,
+        company: String,
+    }
+
+    fn job_info(state: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": "750000000000001",
+            "object": "Lead",
+            "operation": "upsert",
+            "state": state,
+            "externalIdFieldName": "Ext__c",
+            "contentType": "CSV"
+        })
+    }
+
+    #[tokio::test]
+    async fn test_create_and_close_ingest_job() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/jobs/ingest/"))
+            .and(body_json(serde_json::json!({
+                "object": "Lead",
+                "operation": "upsert",
+                "externalIdFieldName": "Ext__c",
+                "contentType": "CSV"
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(job_info("Open")))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/services/data/v12345.0/jobs/ingest/750000000000001/"))
+            .and(body_json(serde_json::json!({ "state": "UploadComplete" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(job_info("UploadComplete")))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        let job = client
+            .create_ingest_job(
+                &CreateIngestJobRequest::new("Lead".to_string(), BulkOperation::Upsert)
+                    .external_id_field_name("Ext__c".to_string()),
+            )
+            .await
+            .unwrap()
+            .into_body()
+            .unwrap();
+        assert_eq!(BulkJobState::Open, job.state);
+
+        let job = client
+            .close_ingest_job(&job.id)
+            .await
+            .unwrap()
+            .into_body()
+            .unwrap();
+        assert_eq!(BulkJobState::UploadComplete, job.state);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_ingest_job() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/jobs/ingest/750000000000001/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(job_info("InProgress")))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/jobs/ingest/750000000000001/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(job_info("JobComplete")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let job = client
+            .wait_for_ingest_job(
+                "750000000000001",
+                Duration::from_millis(1),
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(BulkJobState::JobComplete, job.state);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_query_job_cancelled() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/jobs/query/750000000000001"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(job_info("InProgress")))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/services/data/v12345.0/jobs/query/750000000000001"))
+            .and(body_json(serde_json::json!({ "state": "Aborted" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(job_info("Aborted")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let cancel = CancellationToken::new();
+
+        let wait = client.wait_for_query_job("750000000000001", Duration::from_secs(60), &cancel);
+        let (result, _) = tokio::join!(wait, async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            cancel.cancel();
+        });
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn test_upload_gzip() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("PUT"))
+            .and(path(
+                "/services/data/v12345.0/jobs/ingest/750000000000001/batches/",
+            ))
+            .and(header("Content-Encoding", "gzip"))
+            .and(header("Content-Type", "text/csv"))
+            .respond_with(ResponseTemplate::new(201))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let csv = b"Company,Ext__c\nAcme,123\n".to_vec();
+
+        client
+            .upload_ingest_job_data("750000000000001", csv.clone(), true)
+            .await
+            .unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        let upload = requests
+            .iter()
+            .find(|request| request.method == wiremock::http::Method::Put)
+            .unwrap();
+
+        let mut decoded = Vec::new();
+        GzDecoder::new(upload.body.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+
+        assert_eq!(csv, decoded);
+    }
+
+    #[tokio::test]
+    async fn test_upload_uncompressed() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("PUT"))
+            .and(path(
+                "/services/data/v12345.0/jobs/ingest/750000000000001/batches/",
+            ))
+            .respond_with(ResponseTemplate::new(201))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let csv = b"Company,Ext__c\nAcme,123\n".to_vec();
+
+        client
+            .upload_ingest_job_data("750000000000001", csv.clone(), false)
+            .await
+            .unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        let upload = requests
+            .iter()
+            .find(|request| request.method == wiremock::http::Method::Put)
+            .unwrap();
+
+        assert!(!upload.headers.contains_key(&"Content-Encoding".into()));
+        assert_eq!(csv, upload.body);
+    }
+
+    #[tokio::test]
+    async fn test_query_job_results() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path(
+                "/services/data/v12345.0/jobs/query/750000000000002/results",
+            ))
+            .and(query_param("locator", "MTAwMDA"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Sforce-Locator", "null")
+                    .set_body_raw("Id,Company\n003,Initech\n", "text/csv"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(
+                "/services/data/v12345.0/jobs/query/750000000000002/results",
+            ))
+            .and(header("Accept", "text/csv"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Sforce-Locator", "MTAwMDA")
+                    .set_body_raw("Id,Company\n001,Acme\n002,\"Globex, Inc\"\n", "text/csv"),
+            )
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        let records: Vec<Lead> = client
+            .query_job_results("750000000000002")
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                Lead {
+                    id: "001".to_string(),
+                    company: "Acme".to_string()
+                },
+                Lead {
+                    id: "002".to_string(),
+                    company: "Globex, Inc".to_string()
+                },
+                Lead {
+                    id: "003".to_string(),
+                    company: "Initech".to_string()
+                },
+            ],
+            records
+        );
+    }
+
+    #[test]
+    fn test_csv_records_split() {
+        let csv = b"Id,Company\r\n001,\"Acme\nWest\"\r\n002,\"Globex, \"\"Inc\"\"\"\r\n003,Initech";
+        let expected = vec![
+            Lead {
+                id: "001".to_string(),
+                company: "Acme\nWest".to_string(),
+            },
+            Lead {
+                id: "002".to_string(),
+                company: "Globex, \"Inc\"".to_string(),
+            },
+            Lead {
+                id: "003".to_string(),
+                company: "Initech".to_string(),
+            },
+        ];
+
+        // Records are only returned once they are complete, wherever the chunks are split
+        for split in 0..=csv.len() {
+            let mut records = CsvRecords::new();
+            let mut leads: Vec<Lead> = records.push(&csv[..split]).unwrap();
+            leads.extend(records.push(&csv[split..]).unwrap());
+            leads.extend(records.finish().unwrap());
+
+            assert_eq!(expected, leads, "split at {}", split);
+        }
+
+        let mut records = CsvRecords::new();
+        assert!(records.push::<Lead>(b"Id,Company\n001").unwrap().is_empty());
+        assert_eq!(
+            vec![Lead {
+                id: "001".to_string(),
+                company: "Acme".to_string()
+            }],
+            records.push::<Lead>(b",Acme\n").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_job_results_failure() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path(
+                "/services/data/v12345.0/jobs/query/750000000000002/results",
+            ))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!([
+                { "errorCode": "NOT_FOUND", "message": "The requested resource does not exist" }
+            ])))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        let err = client
+            .query_job_results::<Lead>("750000000000002")
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, Error::ApiFailure(response) if response.status == 404));
+    }
+}
@@ -0,0 +1,271 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Method, StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::{
+    error::{Error, SfResult},
+    util::ApiErrors,
+    SfApiError, SfResponse,
+};
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompositeRequest {
+    /// Rolls back every subrequest if any of them fail
+    pub all_or_none: bool,
+    /// Allows independent subrequests to be processed in parallel
+    #[serde(default)]
+    pub collate_subrequests: bool,
+    pub composite_request: Vec<CompositeSubrequest>,
+}
+
+impl CompositeRequest {
+    pub fn new(composite_request: Vec<CompositeSubrequest>) -> Self {
+        Self {
+            all_or_none: false,
+            collate_subrequests: false,
+            composite_request,
+        }
+    }
+
+    pub fn all_or_none(mut self, all_or_none: bool) -> Self {
+        self.all_or_none = all_or_none;
+        self
+    }
+
+    pub fn collate_subrequests(mut self, collate_subrequests: bool) -> Self {
+        self.collate_subrequests = collate_subrequests;
+        self
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompositeSubrequest {
+    pub method: String,
+    pub url: String,
+    pub reference_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub http_headers: HashMap<String, String>,
+}
+
+impl CompositeSubrequest {
+    /// Creates a subrequest. The url must be the full resource path including the API version,
+    /// see [`crate::SfClient::data_path`]
+    pub fn new(method: Method, url: String, reference_id: String) -> Self {
+        Self {
+            method: method.to_string(),
+            url,
+            reference_id,
+            body: None,
+            http_headers: HashMap::new(),
+        }
+    }
+
+    pub fn body(mut self, body: Value) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    pub fn header(mut self, name: String, value: String) -> Self {
+        self.http_headers.insert(name, value);
+        self
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompositeResponse {
+    pub composite_response: Vec<CompositeSubresponse>,
+}
+
+impl CompositeResponse {
+    pub fn take(&mut self, reference_id: &str) -> Option<CompositeSubresponse> {
+        let index = self
+            .composite_response
+            .iter()
+            .position(|response| response.reference_id == reference_id)?;
+
+        Some(self.composite_response.remove(index))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompositeSubresponse {
+    #[serde(default)]
+    pub body: Value,
+    #[serde(default)]
+    pub http_headers: HashMap<String, String>,
+    pub http_status_code: u16,
+    pub reference_id: String,
+}
+
+impl CompositeSubresponse {
+    /// Converts the subresponse into the response that would have been received had the request
+    /// been made individually
+    #[allow(clippy::result_large_err)]
+    pub fn into_response<T>(self) -> SfResult<SfResponse<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let status = StatusCode::from_u16(self.http_status_code).map_err(|_| {
+            Error::InvalidInput(format!("Invalid status code {}", self.http_status_code))
+        })?;
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.http_headers {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                headers.insert(name, value);
+            }
+        }
+
+        if status.is_success() {
+            Ok(SfResponse {
+                headers,
+                status,
+                body: match self.body {
+                    Value::Null => None,
+                    body => Some(from_value(body)?),
+                },
+            })
+        } else {
+            Err(SfResponse {
+                headers,
+                status,
+                body: Some(Vec::<SfApiError>::from(from_value::<ApiErrors>(self.body)?)),
+            }
+            .into())
+        }
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn from_value<T>(value: Value) -> SfResult<T>
+where
+    T: DeserializeOwned,
+{
+    let body = value.to_string();
+    serde_json::from_value(value).map_err(|error| Error::UnexpectedBody { error, body })
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{Method, StatusCode};
+    use serde_json::json;
+
+    use crate::{error::Error, SfApiError};
+
+    use super::{CompositeRequest, CompositeResponse, CompositeSubrequest};
+
+    #[test]
+    fn test_serialize_request() {
+        let request = CompositeRequest::new(vec![CompositeSubrequest::new(
+            Method::PATCH,
+            "/services/data/v58.0/sobjects/Account/001".to_string(),
+            "update".to_string(),
+        )
+        .body(json!({ "Name": "Acme" }))])
+        .all_or_none(true)
+        .collate_subrequests(true);
+
+        assert_eq!(
+            json!({
+                "allOrNone": true,
+                "collateSubrequests": true,
+                "compositeRequest": [{
+                    "method": "PATCH",
+                    "url": "/services/data/v58.0/sobjects/Account/001",
+                    "referenceId": "update",
+                    "body": { "Name": "Acme" }
+                }]
+            }),
+            serde_json::to_value(&request).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_subresponse_into_response() {
+        let mut response: CompositeResponse = serde_json::from_value(json!({
+            "compositeResponse": [
+                {
+                    "body": null,
+                    "httpHeaders": {},
+                    "httpStatusCode": 204,
+                    "referenceId": "update"
+                },
+                {
+                    "body": [{ "errorCode": "NOT_FOUND", "message": "Not found" }],
+                    "httpHeaders": {},
+                    "httpStatusCode": 404,
+                    "referenceId": "fetch"
+                }
+            ]
+        }))
+        .unwrap();
+
+        let update = response
+            .take("update")
+            .unwrap()
+            .into_response::<()>()
+            .unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, update.status);
+
+        let err = response
+            .take("fetch")
+            .unwrap()
+            .into_response::<serde_json::Value>()
+            .unwrap_err();
+
+        match err {
+            Error::ApiFailure(response) => {
+                assert_eq!(StatusCode::NOT_FOUND, response.status);
+                assert_eq!(
+                    vec![SfApiError {
+                        error_code: "NOT_FOUND".to_string(),
+                        message: "Not found".to_string(),
+                    }],
+                    response.body.unwrap()
+                );
+            }
+            err => panic!("Unexpected error {:?}", err),
+        }
+
+        assert!(response.take("fetch").is_none());
+
+        // Some subresponses report a single error object rather than an array
+        let mut response: CompositeResponse = serde_json::from_value(json!({
+            "compositeResponse": [{
+                "body": { "errorCode": "NOT_FOUND", "message": "Not found" },
+                "httpHeaders": {},
+                "httpStatusCode": 404,
+                "referenceId": "fetch"
+            }]
+        }))
+        .unwrap();
+
+        let err = response
+            .take("fetch")
+            .unwrap()
+            .into_response::<serde_json::Value>()
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::ApiFailure(response) if response.body.as_ref().unwrap()[0].error_code == "NOT_FOUND")
+        );
+    }
+}
@@ -0,0 +1,100 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+//! Compound fields, which group several fields of a record into a single read-only value. They
+//! are returned by queries and fetches but must be written through their component fields, such
+//! as `BillingCity` for `BillingAddress`
+
+use serde::{Deserialize, Serialize};
+
+/// An address field, such as `BillingAddress` of an Account or `MailingAddress` of a Contact
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Address {
+    pub street: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub state_code: Option<String>,
+    pub postal_code: Option<String>,
+    pub country: Option<String>,
+    pub country_code: Option<String>,
+    pub geocode_accuracy: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+impl Address {
+    /// The geocoded location of the address, if it has been geocoded
+    pub fn location(&self) -> Option<GeoLocation> {
+        Some(GeoLocation {
+            latitude: self.latitude?,
+            longitude: self.longitude?,
+        })
+    }
+}
+
+/// A geolocation field, or the location of an address
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct GeoLocation {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::{Address, GeoLocation};
+
+    #[derive(Debug, Deserialize)]
+    struct Account {
+        #[serde(rename = "BillingAddress")]
+        billing_address: Option<Address>,
+        #[serde(rename = "ShippingAddress")]
+        shipping_address: Option<Address>,
+        #[serde(rename = "Location__c")]
+        location: Option<GeoLocation>,
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let account: Account = serde_json::from_value(json!({
+            "BillingAddress": {
+                "city": "Emeryville",
+                "country": "United States",
+                "countryCode": "US",
+                "geocodeAccuracy": "Address",
+                "latitude": 37.8313,
+                "longitude": -122.2853,
+                "postalCode": "94608",
+                "state": "California",
+                "stateCode": "CA",
+                "street": "1251 Park Ave"
+            },
+            "ShippingAddress": null,
+            "Location__c": { "latitude": 37.8313, "longitude": -122.2853 }
+        }))
+        .unwrap();
+
+        let billing_address = account.billing_address.unwrap();
+        assert_eq!(Some("Emeryville"), billing_address.city.as_deref());
+        assert_eq!(Some("94608"), billing_address.postal_code.as_deref());
+        assert_eq!(
+            Some(GeoLocation {
+                latitude: 37.8313,
+                longitude: -122.2853
+            }),
+            billing_address.location()
+        );
+        assert_eq!(None, account.shipping_address);
+        assert_eq!(billing_address.location(), account.location);
+
+        // Addresses that have not been geocoded omit the location
+        let address: Address = serde_json::from_value(json!({ "city": "Emeryville" })).unwrap();
+        assert_eq!(None, address.location());
+    }
+}
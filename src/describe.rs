@@ -0,0 +1,1094 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::Method;
+use serde::{
+    de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use crate::{
+    composite::{CompositeRequest, CompositeSubrequest},
+    error::{Error, SfResult},
+    ObjectDescription, ObjectDescriptionResponse, ObjectDescriptionsResponse, SfClient, SfResponse,
+    MAX_COMPOSITE_SUBREQUESTS,
+};
+
+// The full describe contains a large amount of metadata that varies between API versions. Only
+// commonly used properties are modeled, and every property falls back to a default so that
+// changes in the describe do not break deserialization
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FullObjectDescription {
+    pub name: String,
+    pub label: String,
+    pub label_plural: String,
+    pub key_prefix: Option<String>,
+    pub custom: bool,
+    pub createable: bool,
+    pub updateable: bool,
+    pub deletable: bool,
+    pub queryable: bool,
+    pub searchable: bool,
+    pub fields: Vec<FieldDescription>,
+    pub child_relationships: Vec<ChildRelationship>,
+    pub record_type_infos: Vec<RecordTypeInfo>,
+    pub urls: HashMap<String, String>,
+}
+
+impl FullObjectDescription {
+    /// Removes every property from the record that is not an updateable field of this object,
+    /// including the `attributes` property of fetched records. Field names are matched case
+    /// insensitively as they are by Salesforce
+    pub fn retain_updateable(&self, record: &mut Map<String, Value>) {
+        record.retain(|name, _| {
+            self.fields
+                .iter()
+                .any(|field| field.updateable && field.name.eq_ignore_ascii_case(name))
+        });
+    }
+
+    /// The field metadata of the object keyed by field name, for detecting changes to the schema
+    /// of the object with [`diff`]
+    pub fn schema(&self) -> ObjectSchema {
+        ObjectSchema {
+            name: self.name.clone(),
+            fields: self
+                .fields
+                .iter()
+                .map(|field| (field.name.clone(), field.clone()))
+                .collect(),
+        }
+    }
+
+    /// The record type assigned to new records of the user by default, if the object has record
+    /// types other than the master record type
+    pub fn default_record_type(&self) -> Option<&RecordTypeInfo> {
+        self.record_type_infos
+            .iter()
+            .find(|info| info.default_record_type_mapping && info.available && !info.master)
+    }
+}
+
+/// A snapshot of the fields of an object. Fields are sorted by name so that serialized schemas
+/// can be stored and compared directly
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ObjectSchema {
+    pub name: String,
+    pub fields: BTreeMap<String, FieldDescription>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldChange {
+    Added(FieldDescription),
+    Removed(FieldDescription),
+    Modified {
+        old: Box<FieldDescription>,
+        new: Box<FieldDescription>,
+    },
+}
+
+/// Lists the fields that were added, removed, or modified between two schemas, ordered by field
+/// name
+pub fn diff(old: &ObjectSchema, new: &ObjectSchema) -> Vec<FieldChange> {
+    let mut changes = old
+        .fields
+        .iter()
+        .filter_map(|(name, old_field)| match new.fields.get(name) {
+            None => Some((name, FieldChange::Removed(old_field.clone()))),
+            Some(new_field) if new_field != old_field => Some((
+                name,
+                FieldChange::Modified {
+                    old: Box::new(old_field.clone()),
+                    new: Box::new(new_field.clone()),
+                },
+            )),
+            Some(_) => None,
+        })
+        .chain(
+            new.fields
+                .iter()
+                .filter(|(name, _)| !old.fields.contains_key(*name))
+                .map(|(name, field)| (name, FieldChange::Added(field.clone()))),
+        )
+        .collect::<Vec<_>>();
+
+    changes.sort_by_key(|(name, _)| *name);
+    changes.into_iter().map(|(_, change)| change).collect()
+}
+
+impl SfClient {
+    /// Fetches the complete metadata of an object, reusing the metadata fetched by previous calls
+    /// for the same object. Changes to the object are not seen for the lifetime of the client
+    pub async fn describe_object_cached(&self, object: &str) -> SfResult<FullObjectDescription> {
+        let key = object.to_lowercase();
+        if let Some(describe) = self.describe_cache.lock().unwrap().get(&key) {
+            return Ok(describe.clone());
+        }
+
+        let describe = self.describe_object_full(object).await?.into_body()?;
+        self.describe_cache
+            .lock()
+            .unwrap()
+            .insert(key, describe.clone());
+
+        Ok(describe)
+    }
+
+    /// Fetches the basic metadata of several objects, keyed by the names they were requested
+    /// with. Objects are described through composite requests of up to 25 objects each, and any
+    /// failed describe fails the whole batch
+    pub async fn describe_objects_batch(
+        &self,
+        objects: &[&str],
+    ) -> SfResult<HashMap<String, ObjectDescriptionResponse>> {
+        let mut descriptions = HashMap::new();
+
+        for chunk in objects.chunks(MAX_COMPOSITE_SUBREQUESTS) {
+            let subrequests = chunk
+                .iter()
+                .enumerate()
+                .map(|(index, object)| {
+                    CompositeSubrequest::new(
+                        Method::GET,
+                        self.data_path(&self.object_path(object)),
+                        format!("describe{}", index),
+                    )
+                })
+                .collect();
+            let request = CompositeRequest::new(subrequests).collate_subrequests(true);
+
+            let mut response = self.composite(&request).await?.into_body()?;
+            for (index, object) in chunk.iter().enumerate() {
+                let reference_id = format!("describe{}", index);
+                let description = response
+                    .take(&reference_id)
+                    .ok_or(Error::MissingSubresponse(reference_id))?
+                    .into_response()?
+                    .into_body()?;
+
+                descriptions.insert(object.to_string(), description);
+            }
+        }
+
+        Ok(descriptions)
+    }
+
+    /// Determines the object of each record id from the key prefix of the id, the first three
+    /// characters. The key prefixes of every object are fetched once with the global describe
+    /// and reused. Ids with an unknown prefix are paired with `None`
+    pub async fn classify_ids(&self, ids: &[&str]) -> SfResult<Vec<(String, Option<String>)>> {
+        let cached = self.key_prefixes.lock().unwrap().clone();
+        let key_prefixes = match cached {
+            Some(key_prefixes) => key_prefixes,
+            None => {
+                let key_prefixes = self
+                    .describe_objects()
+                    .await?
+                    .into_body()?
+                    .sobjects
+                    .into_iter()
+                    .filter_map(|object| Some((object.key_prefix?, object.name)))
+                    .collect::<HashMap<_, _>>();
+
+                *self.key_prefixes.lock().unwrap() = Some(key_prefixes.clone());
+                key_prefixes
+            }
+        };
+
+        Ok(ids
+            .iter()
+            .map(|id| {
+                let object = id
+                    .get(..3)
+                    .and_then(|prefix| key_prefixes.get(prefix))
+                    .cloned();
+
+                (id.to_string(), object)
+            })
+            .collect())
+    }
+
+    /// The record type assigned to new records of an object by default, see
+    /// [`FullObjectDescription::default_record_type`]
+    pub async fn default_record_type(&self, object: &str) -> SfResult<Option<RecordTypeInfo>> {
+        Ok(self
+            .describe_object_cached(object)
+            .await?
+            .default_record_type()
+            .cloned())
+    }
+
+    /// The values of a dependent picklist that are valid for each value of its controlling field,
+    /// for every record type of the object. See [`FieldDescription::dependencies`]
+    #[allow(clippy::result_large_err)]
+    pub async fn picklist_dependencies(
+        &self,
+        object: &str,
+        field: &str,
+    ) -> SfResult<BTreeMap<String, Vec<String>>> {
+        let describe = self.describe_object_cached(object).await?;
+        let find = |name: &str| {
+            describe
+                .fields
+                .iter()
+                .find(|field| field.name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| {
+                    Error::InvalidInput(format!("{} does not have a {} field", object, name))
+                })
+        };
+
+        let dependent = find(field)?;
+        let controller = find(dependent.controller_name.as_deref().ok_or_else(|| {
+            Error::InvalidInput(format!("{}.{} is not a dependent picklist", object, field))
+        })?)?;
+
+        Ok(dependent.dependencies(controller))
+    }
+
+    /// The values of a dependent picklist that are valid for each value of its controlling field,
+    /// limited to the values available to a record type
+    pub async fn record_type_picklist_dependencies(
+        &self,
+        object: &str,
+        record_type_id: &str,
+        field: &str,
+    ) -> SfResult<BTreeMap<String, Vec<String>>> {
+        let values: RecordTypePicklistValues = self
+            .get(&format!(
+                "ui-api/object-info/{}/picklist-values/{}/{}",
+                object, record_type_id, field
+            ))
+            .await?
+            .into_body()?;
+
+        Ok(values.dependencies())
+    }
+
+    /// Fetches the compact layouts of an object, which determine the fields shown in the
+    /// highlights panel of a record and in the mobile app
+    pub async fn describe_compact_layouts(
+        &self,
+        object: &str,
+    ) -> SfResult<SfResponse<CompactLayouts>> {
+        self.get(&self.object_path(&format!("{}/describe/compactLayouts", object)))
+            .await
+    }
+
+    /// Fetches the layouts of the approval pages of an object, one for each approval process
+    pub async fn describe_approval_layouts(
+        &self,
+        object: &str,
+    ) -> SfResult<SfResponse<ApprovalLayouts>> {
+        self.get(&self.object_path(&format!("{}/describe/approvalLayouts", object)))
+            .await
+    }
+}
+
+// The values of a record type picklist from the UI API, where the valid controlling values of
+// each value are listed by their index rather than as a bitmap
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct RecordTypePicklistValues {
+    controller_values: HashMap<String, usize>,
+    values: Vec<RecordTypePicklistValue>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct RecordTypePicklistValue {
+    value: String,
+    valid_for: Vec<usize>,
+}
+
+impl RecordTypePicklistValues {
+    fn dependencies(&self) -> BTreeMap<String, Vec<String>> {
+        self.controller_values
+            .iter()
+            .map(|(controlling, index)| {
+                let valid = self
+                    .values
+                    .iter()
+                    .filter(|value| value.valid_for.contains(index))
+                    .map(|value| value.value.clone())
+                    .collect();
+
+                (controlling.clone(), valid)
+            })
+            .collect()
+    }
+}
+
+/// Removes the named fields from the record, for when the non-updateable fields of an object are
+/// already known
+pub fn remove_fields(record: &mut Map<String, Value>, fields: &[&str]) {
+    record.retain(|name, _| !fields.iter().any(|field| field.eq_ignore_ascii_case(name)));
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FieldDescription {
+    pub name: String,
+    pub label: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub length: u32,
+    pub precision: u32,
+    pub scale: u32,
+    pub custom: bool,
+    pub nillable: bool,
+    pub createable: bool,
+    pub updateable: bool,
+    pub unique: bool,
+    pub external_id: bool,
+    pub id_lookup: bool,
+    pub calculated: bool,
+    pub default_value: Option<Value>,
+    pub picklist_values: Vec<PicklistValue>,
+    pub reference_to: Vec<String>,
+    pub relationship_name: Option<String>,
+    pub controller_name: Option<String>,
+    pub dependent_picklist: bool,
+}
+
+impl FieldDescription {
+    /// The active values of this dependent picklist that are valid for each value of the
+    /// controlling field. Checkbox controlling fields have the values `false` and `true`
+    pub fn dependencies(&self, controller: &FieldDescription) -> BTreeMap<String, Vec<String>> {
+        let controlling_values = if controller.type_ == "boolean" {
+            vec!["false".to_string(), "true".to_string()]
+        } else {
+            controller
+                .picklist_values
+                .iter()
+                .map(|value| value.value.clone())
+                .collect()
+        };
+
+        controlling_values
+            .into_iter()
+            .enumerate()
+            .map(|(index, controlling)| {
+                let valid = self
+                    .picklist_values
+                    .iter()
+                    .filter(|value| value.active && value.is_valid_for(index))
+                    .map(|value| value.value.clone())
+                    .collect();
+
+                (controlling, valid)
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PicklistValue {
+    pub active: bool,
+    pub default_value: bool,
+    pub label: Option<String>,
+    pub value: String,
+    pub valid_for: Option<String>,
+}
+
+impl PicklistValue {
+    /// Whether this value of a dependent picklist is valid for the value of the controlling field
+    /// at the given index. `valid_for` is a base64 encoded bitmap with a bit for each controlling
+    /// value, starting from the most significant bit of the first byte
+    pub fn is_valid_for(&self, controlling_index: usize) -> bool {
+        self.valid_for
+            .as_deref()
+            .and_then(|valid_for| STANDARD.decode(valid_for).ok())
+            .and_then(|bitmap| bitmap.get(controlling_index / 8).copied())
+            .map(|byte| byte & (0x80 >> (controlling_index % 8)) != 0)
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RecordTypeInfo {
+    pub record_type_id: String,
+    pub name: String,
+    pub developer_name: String,
+    pub available: bool,
+    pub default_record_type_mapping: bool,
+    pub master: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ChildRelationship {
+    #[serde(rename = "childSObject")]
+    pub child_sobject: String,
+    pub field: String,
+    pub relationship_name: Option<String>,
+    pub cascade_delete: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CompactLayouts {
+    pub compact_layouts: Vec<CompactLayout>,
+    pub default_compact_layout_id: Option<String>,
+    pub record_type_compact_layout_mappings: Vec<RecordTypeCompactLayoutMapping>,
+}
+
+impl CompactLayouts {
+    /// The compact layout used for records without a record type specific layout
+    pub fn default_layout(&self) -> Option<&CompactLayout> {
+        let id = self.default_compact_layout_id.as_deref()?;
+        self.compact_layouts
+            .iter()
+            .find(|layout| layout.id.as_deref() == Some(id))
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CompactLayout {
+    pub id: Option<String>,
+    pub name: String,
+    pub label: String,
+    pub object_type: String,
+    pub field_items: Vec<LayoutItem>,
+}
+
+impl CompactLayout {
+    /// The names of the fields of the layout, in the order that they are displayed
+    pub fn field_names(&self) -> Vec<&str> {
+        field_names(&self.field_items)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RecordTypeCompactLayoutMapping {
+    pub available: bool,
+    pub compact_layout_id: Option<String>,
+    pub compact_layout_name: String,
+    pub record_type_id: String,
+    pub record_type_name: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ApprovalLayouts {
+    pub approval_layouts: Vec<ApprovalLayout>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ApprovalLayout {
+    pub id: Option<String>,
+    pub name: String,
+    pub label: String,
+    pub layout_items: Vec<LayoutItem>,
+}
+
+impl ApprovalLayout {
+    /// The names of the fields of the layout, in the order that they are displayed
+    pub fn field_names(&self) -> Vec<&str> {
+        field_names(&self.layout_items)
+    }
+}
+
+/// An item of a layout, made up of one or more components such as the fields of an address
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LayoutItem {
+    pub label: String,
+    pub layout_components: Vec<LayoutComponent>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LayoutComponent {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub value: Option<String>,
+}
+
+fn field_names(items: &[LayoutItem]) -> Vec<&str> {
+    items
+        .iter()
+        .flat_map(|item| &item.layout_components)
+        .filter(|component| component.type_ == "Field")
+        .filter_map(|component| component.value.as_deref())
+        .collect()
+}
+
+// Deserializes the global describe, keeping only the objects that match the predicate. Each
+// object is dropped as soon as it is parsed, rather than after the whole list has been collected
+#[derive(Clone)]
+pub(crate) struct FilteredDescriptions<F>(pub F);
+
+impl<'de, F> DeserializeSeed<'de> for FilteredDescriptions<F>
+where
+    F: Fn(&ObjectDescription) -> bool,
+{
+    type Value = ObjectDescriptionsResponse;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, F> Visitor<'de> for FilteredDescriptions<F>
+where
+    F: Fn(&ObjectDescription) -> bool,
+{
+    type Value = ObjectDescriptionsResponse;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a global describe")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut encoding = None;
+        let mut max_batch_size = None;
+        let mut sobjects = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "encoding" => encoding = Some(map.next_value()?),
+                "maxBatchSize" => max_batch_size = Some(map.next_value()?),
+                "sobjects" => sobjects = Some(map.next_value_seed(MatchingObjects(&self.0))?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(ObjectDescriptionsResponse {
+            encoding: encoding.ok_or_else(|| de::Error::missing_field("encoding"))?,
+            max_batch_size: max_batch_size
+                .ok_or_else(|| de::Error::missing_field("maxBatchSize"))?,
+            sobjects: sobjects.ok_or_else(|| de::Error::missing_field("sobjects"))?,
+        })
+    }
+}
+
+struct MatchingObjects<'a, F>(&'a F);
+
+impl<'de, F> DeserializeSeed<'de> for MatchingObjects<'_, F>
+where
+    F: Fn(&ObjectDescription) -> bool,
+{
+    type Value = Vec<ObjectDescription>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F> Visitor<'de> for MatchingObjects<'_, F>
+where
+    F: Fn(&ObjectDescription) -> bool,
+{
+    type Value = Vec<ObjectDescription>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of object descriptions")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut objects = Vec::new();
+
+        while let Some(object) = seq.next_element::<ObjectDescription>()? {
+            if (self.0)(&object) {
+                objects.push(object);
+            }
+        }
+
+        Ok(objects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{authenticator::jwt::tests::add_token_mock, tests::get_client, SfResponse};
+
+    use super::{
+        diff, remove_fields, FieldChange, FieldDescription, FullObjectDescription, PicklistValue,
+    };
+
+    fn field(name: &str, updateable: bool) -> FieldDescription {
+        FieldDescription {
+            name: name.to_string(),
+            updateable,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_retain_updateable() {
+        let describe = FullObjectDescription {
+            name: "Lead".to_string(),
+            fields: vec![
+                field("Id", false),
+                field("CreatedDate", false),
+                field("Company", true),
+                field("Email", true),
+            ],
+            ..Default::default()
+        };
+
+        let mut record = json!({
+            "attributes": { "type": "Lead", "url": "/services/data/v58.0/sobjects/Lead/123" },
+            "Id": "123",
+            "CreatedDate": "2023-01-01T00:00:00.000+0000",
+            "Company": "Acme",
+            "email": "test@acme.com",
+            "Unknown__c": "value"
+        })
+        .as_object()
+        .unwrap()
+        .clone();
+
+        describe.retain_updateable(&mut record);
+
+        assert_eq!(
+            json!({ "Company": "Acme", "email": "test@acme.com" }),
+            json!(record)
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = FullObjectDescription {
+            name: "Lead".to_string(),
+            fields: vec![
+                field("Company", true),
+                field("Email", true),
+                field("Legacy__c", true),
+            ],
+            ..Default::default()
+        };
+        let new = FullObjectDescription {
+            name: "Lead".to_string(),
+            fields: vec![
+                field("Score__c", true),
+                field("Email", false),
+                field("Company", true),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            vec![
+                FieldChange::Modified {
+                    old: Box::new(field("Email", true)),
+                    new: Box::new(field("Email", false)),
+                },
+                FieldChange::Removed(field("Legacy__c", true)),
+                FieldChange::Added(field("Score__c", true)),
+            ],
+            diff(&old.schema(), &new.schema())
+        );
+        assert!(diff(&new.schema(), &new.schema()).is_empty());
+
+        // Schemas serialize with their fields sorted by name
+        let names = serde_json::to_value(new.schema()).unwrap()["fields"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["Company", "Email", "Score__c"], names);
+    }
+
+    #[test]
+    fn test_remove_fields() {
+        let mut record = json!({ "Id": "123", "SystemModstamp": "2023-01-01", "Company": "Acme" })
+            .as_object()
+            .unwrap()
+            .clone();
+
+        remove_fields(&mut record, &["id", "SystemModstamp"]);
+
+        assert_eq!(json!({ "Company": "Acme" }), json!(record));
+    }
+
+    #[tokio::test]
+    async fn test_classify_ids() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "encoding": "UTF-8",
+                "maxBatchSize": 200,
+                "sobjects": [
+                    { "name": "Account", "label": "Account", "keyPrefix": "001" },
+                    { "name": "Contact", "label": "Contact", "keyPrefix": "003" },
+                    { "name": "AccountHistory", "label": "Account History", "keyPrefix": null }
+                ]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        for _ in 0..2 {
+            assert_eq!(
+                vec![
+                    (
+                        "001000000000001AAA".to_string(),
+                        Some("Account".to_string())
+                    ),
+                    ("003000000000001".to_string(), Some("Contact".to_string())),
+                    ("a00000000000001".to_string(), None),
+                    ("00".to_string(), None),
+                ],
+                client
+                    .classify_ids(&[
+                        "001000000000001AAA",
+                        "003000000000001",
+                        "a00000000000001",
+                        "00"
+                    ])
+                    .await
+                    .unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_valid_for() {
+        // 0b1010_0000, 0b0000_0001
+        let value = PicklistValue {
+            valid_for: Some("oAE=".to_string()),
+            ..Default::default()
+        };
+
+        let valid = (0..16)
+            .filter(|index| value.is_valid_for(*index))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![0, 2, 15], valid);
+        assert!(!value.is_valid_for(100));
+        assert!(!PicklistValue::default().is_valid_for(0));
+    }
+
+    #[tokio::test]
+    async fn test_picklist_dependencies() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let value = |value: &str, valid_for: Option<&str>| json!({ "active": true, "value": value, "validFor": valid_for });
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Account/describe"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "name": "Account",
+                "fields": [
+                    {
+                        "name": "Country__c",
+                        "type": "picklist",
+                        "picklistValues": [value("US", None), value("CA", None)]
+                    },
+                    {
+                        "name": "Region__c",
+                        "type": "picklist",
+                        "controllerName": "Country__c",
+                        "dependentPicklist": true,
+                        "picklistValues": [
+                            value("California", Some("gA==")),
+                            value("Ontario", Some("QA==")),
+                            value("Other", Some("wA=="))
+                        ]
+                    }
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let dependencies = client
+            .picklist_dependencies("Account", "Region__c")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            vec!["California".to_string(), "Other".to_string()],
+            dependencies["US"]
+        );
+        assert_eq!(
+            vec!["Ontario".to_string(), "Other".to_string()],
+            dependencies["CA"]
+        );
+        assert!(client
+            .picklist_dependencies("Account", "Country__c")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_record_type_picklist_dependencies() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path(
+                "/services/data/v12345.0/ui-api/object-info/Account/picklist-values/012000000000001AAA/Region__c",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "controllerValues": { "US": 0, "CA": 1 },
+                "defaultValue": null,
+                "values": [
+                    { "label": "California", "value": "California", "validFor": [0] },
+                    { "label": "Ontario", "value": "Ontario", "validFor": [1] }
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let dependencies = client
+            .record_type_picklist_dependencies("Account", "012000000000001AAA", "Region__c")
+            .await
+            .unwrap();
+
+        assert_eq!(vec!["California".to_string()], dependencies["US"]);
+        assert_eq!(vec!["Ontario".to_string()], dependencies["CA"]);
+    }
+
+    #[tokio::test]
+    async fn test_describe_objects_batch() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let objects = (0..30)
+            .map(|index| format!("Object{}__c", index))
+            .collect::<Vec<_>>();
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/composite"))
+            .respond_with(|request: &wiremock::Request| {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                let responses = body["compositeRequest"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|subrequest| {
+                        let name = subrequest["url"].as_str().unwrap().rsplit('/').next();
+                        json!({
+                            "body": { "objectDescribe": { "name": name, "label": name } },
+                            "httpHeaders": {},
+                            "httpStatusCode": 200,
+                            "referenceId": subrequest["referenceId"]
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                ResponseTemplate::new(200).set_body_json(json!({ "compositeResponse": responses }))
+            })
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let descriptions = client
+            .describe_objects_batch(&objects.iter().map(String::as_str).collect::<Vec<_>>())
+            .await
+            .unwrap();
+
+        assert_eq!(30, descriptions.len());
+        assert_eq!(
+            "Object27__c",
+            descriptions["Object27__c"].object_describe.name
+        );
+    }
+
+    #[tokio::test]
+    async fn test_describe_compact_layouts() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let field = |label: &str, name: &str| {
+            json!({
+                "label": label,
+                "layoutComponents": [{ "type": "Field", "value": name, "details": { "name": name } }]
+            })
+        };
+
+        Mock::given(method("GET"))
+            .and(path(
+                "/services/data/v12345.0/sobjects/Account/describe/compactLayouts",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "compactLayouts": [
+                    {
+                        "id": null,
+                        "name": "SYSTEM",
+                        "label": "System Default",
+                        "objectType": "Account",
+                        "fieldItems": [field("Account Name", "Name")],
+                        "actions": []
+                    },
+                    {
+                        "id": "0AH000000000001AAA",
+                        "name": "Sales",
+                        "label": "Sales",
+                        "objectType": "Account",
+                        "fieldItems": [
+                            field("Account Name", "Name"),
+                            field("Phone", "Phone"),
+                            field("Owner", "OwnerId")
+                        ]
+                    }
+                ],
+                "defaultCompactLayoutId": "0AH000000000001AAA",
+                "recordTypeCompactLayoutMappings": [{
+                    "available": true,
+                    "compactLayoutId": "0AH000000000001AAA",
+                    "compactLayoutName": "Sales",
+                    "recordTypeId": "012000000000000AAA",
+                    "recordTypeName": "Master"
+                }]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let layouts = client
+            .describe_compact_layouts("Account")
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        assert_eq!(2, layouts.compact_layouts.len());
+        assert_eq!(
+            vec!["Name", "Phone", "OwnerId"],
+            layouts.default_layout().unwrap().field_names()
+        );
+        assert_eq!(
+            "Sales",
+            layouts.record_type_compact_layout_mappings[0].compact_layout_name
+        );
+    }
+
+    #[tokio::test]
+    async fn test_describe_approval_layouts() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path(
+                "/services/data/v12345.0/sobjects/Opportunity/describe/approvalLayouts",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "approvalLayouts": [{
+                    "id": "04a000000000001AAA",
+                    "name": "Discount_Approval",
+                    "label": "Discount Approval",
+                    "layoutItems": [
+                        { "label": "Amount", "layoutComponents": [{ "type": "Field", "value": "Amount" }] },
+                        { "label": "", "layoutComponents": [{ "type": "EmptySpace" }] },
+                        { "label": "Discount", "layoutComponents": [{ "type": "Field", "value": "Discount__c" }] }
+                    ]
+                }]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let layouts = client
+            .describe_approval_layouts("Opportunity")
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        assert_eq!("Discount_Approval", layouts.approval_layouts[0].name);
+        assert_eq!(
+            vec!["Amount", "Discount__c"],
+            layouts.approval_layouts[0].field_names()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_default_record_type() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let record_type = |id: &str, name: &str, default: bool, master: bool| {
+            json!({
+                "recordTypeId": id,
+                "name": name,
+                "developerName": name,
+                "available": true,
+                "defaultRecordTypeMapping": default,
+                "master": master
+            })
+        };
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Account/describe"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "name": "Account",
+                "recordTypeInfos": [
+                    record_type("012000000000001AAA", "Customer", false, false),
+                    record_type("012000000000002AAA", "Partner", true, false),
+                    record_type("012000000000000AAA", "Master", false, true)
+                ]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/describe"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "name": "Lead",
+                "recordTypeInfos": [record_type("012000000000000AAA", "Master", true, true)]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        for _ in 0..2 {
+            let record_type = client
+                .default_record_type("Account")
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!("012000000000002AAA", record_type.record_type_id);
+            assert_eq!("Partner", record_type.name);
+        }
+
+        assert_eq!(None, client.default_record_type("Lead").await.unwrap());
+    }
+}
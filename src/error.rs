@@ -4,29 +4,80 @@
 
 // Copyright 2023 Oxide Computer Company
 
-use reqwest::Error as ClientError;
-use serde::Deserialize;
-use std::env::VarError;
+use reqwest::{Error as ClientError, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::{env::VarError, time::Duration};
 use thiserror::Error;
 
 use crate::{SfApiError, SfResponse};
 
+// Errors carry the full response (including headers) so that callers can inspect them. This puts
+// them over the size that clippy::result_large_err allows, which synchronous functions returning
+// them opt out of individually
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("API request failed {0}")]
-    ApiFailure(#[from] SfResponse<Vec<SfApiError>>),
-    #[error("Request failed {0}")]
-    Client(#[from] ClientError),
+    ApiFailure(SfResponse<Vec<SfApiError>>),
+    #[error("Request failed")]
+    Client(#[source] ClientError),
+    #[error("Operation was cancelled")]
+    Cancelled,
+    #[error("Failed to connect")]
+    Connect(#[source] ClientError),
     #[error("Failed to create authentication assertion {0}")]
     FailedToCreateAssertion(#[from] jsonwebtoken::errors::Error),
+    #[error("Failed to compress request body")]
+    Compression(#[source] std::io::Error),
+    #[error("Failed to parse CSV response {0}")]
+    Csv(#[from] csv::Error),
+    #[error("Response with {status} status did not contain a body")]
+    EmptyBody {
+        status: StatusCode,
+        path: Option<String>,
+    },
+    #[error("Invalid input {0}")]
+    InvalidInput(String),
     #[error("Failed to load key {0}")]
     LoadKey(#[from] std::io::Error),
     #[error("Login request failed {0}")]
     LoginFailure(#[from] SfResponse<SfLoginError>),
+    #[error("Failed to parse response {error}")]
+    MalformedResponse { error: String, body: String },
+    #[error("Composite response did not contain a response for {0}")]
+    MissingSubresponse(String),
     #[error("Failed to find necessary environment variables {0}")]
     MissingEnvConfig(#[from] VarError),
+    #[error("Received a non-JSON response with content type {content_type}")]
+    NonJsonResponse {
+        content_type: String,
+        body_snippet: String,
+    },
+    #[error("Failed to access token cache")]
+    TokenCache(#[source] std::io::Error),
+    #[error("Request timed out")]
+    Timeout(#[source] ClientError),
+    #[error(
+        "Query timed out, make the query more selective or run it as a Bulk API query job {0}"
+    )]
+    QueryTimeout(SfResponse<Vec<SfApiError>>),
+    #[cfg(feature = "pubsub")]
+    #[error("Pub/Sub API request failed {0}")]
+    PubSub(#[from] tonic::Status),
+    #[cfg(feature = "pubsub")]
+    #[error("Failed to connect to the Pub/Sub API {0}")]
+    PubSubConnect(#[from] tonic::transport::Error),
+    #[cfg(feature = "streaming")]
+    #[error("Streaming API request failed {0}")]
+    Streaming(String),
+    #[error("Request body was too large for {path}")]
+    PayloadTooLarge { path: String },
+    #[error("API usage of {used} of {max} requests exceeds the usage guard")]
+    UsageBudgetExceeded { used: u64, max: u64 },
+    #[error("Request was rate limited, retry after {retry_after:?}")]
+    RateLimited { retry_after: Option<Duration> },
     #[error("Failed to deserialize response")]
     UnexpectedBody {
+        #[source]
         error: serde_json::Error,
         body: String,
     },
@@ -34,10 +85,188 @@ pub enum Error {
     UnknownApiFailure(#[from] SfResponse<String>),
 }
 
+impl Error {
+    /// A summary of the error over multiple lines, listing each error returned by the API or
+    /// otherwise each of the underlying errors that caused it
+    pub fn context(&self) -> String {
+        let (mut context, response) = match self {
+            Self::ApiFailure(response) => (
+                format!("API request failed with {} status", response.status),
+                response,
+            ),
+            Self::QueryTimeout(response) => (
+                format!(
+                    "Query timed out with {} status, make the query more selective or run it as a Bulk API query job",
+                    response.status
+                ),
+                response,
+            ),
+            err => {
+                let mut context = err.to_string();
+                let mut source = std::error::Error::source(err);
+
+                while let Some(err) = source {
+                    context.push_str(&format!("\n  caused by: {}", err));
+                    source = err.source();
+                }
+
+                return context;
+            }
+        };
+
+        for error in response.body.iter().flatten() {
+            context.push_str(&format!("\n  {}: {}", error.error_code, error.message));
+        }
+
+        context
+    }
+}
+
+// Queries that time out are separated from other failures as they need to be rewritten or run
+// through the Bulk API rather than retried
+impl From<SfResponse<Vec<SfApiError>>> for Error {
+    fn from(response: SfResponse<Vec<SfApiError>>) -> Self {
+        let timed_out = response
+            .body
+            .iter()
+            .flatten()
+            .any(|error| error.error_code == "QUERY_TIMEOUT");
+
+        if timed_out {
+            Self::QueryTimeout(response)
+        } else {
+            Self::ApiFailure(response)
+        }
+    }
+}
+
+// Timeouts and connection failures are separated from other client errors as they are usually
+// safe to retry
+impl From<ClientError> for Error {
+    fn from(err: ClientError) -> Self {
+        if err.is_timeout() {
+            Self::Timeout(err)
+        } else if err.is_connect() {
+            Self::Connect(err)
+        } else {
+            Self::Client(err)
+        }
+    }
+}
+
 pub type SfResult<T> = Result<T, Error>;
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct SfLoginError {
     pub error: String,
     pub error_description: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use reqwest::{header::HeaderMap, StatusCode};
+    use std::time::Duration;
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    use crate::{SfApiError, SfResponse};
+
+    use super::Error;
+
+    #[tokio::test]
+    async fn test_timeout_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+
+        let err: Error = reqwest::Client::builder()
+            .timeout(Duration::from_millis(50))
+            .build()
+            .unwrap()
+            .get(server.uri())
+            .send()
+            .await
+            .unwrap_err()
+            .into();
+
+        assert!(matches!(err, Error::Timeout(_)));
+    }
+
+    #[test]
+    fn test_query_timeout_error() {
+        let response = |error_code: &str| SfResponse {
+            headers: HeaderMap::new(),
+            status: StatusCode::BAD_REQUEST,
+            body: Some(vec![SfApiError {
+                error_code: error_code.to_string(),
+                message: "Your query request was running for too long.".to_string(),
+            }]),
+        };
+
+        assert!(matches!(
+            Error::from(response("QUERY_TIMEOUT")),
+            Error::QueryTimeout(_)
+        ));
+        assert!(matches!(
+            Error::from(response("MALFORMED_QUERY")),
+            Error::ApiFailure(_)
+        ));
+    }
+
+    #[test]
+    fn test_context() {
+        let err = Error::ApiFailure(SfResponse {
+            headers: HeaderMap::new(),
+            status: StatusCode::BAD_REQUEST,
+            body: Some(vec![
+                SfApiError {
+                    error_code: "INVALID_FIELD".to_string(),
+                    message: "No such column 'Foo'".to_string(),
+                },
+                SfApiError {
+                    error_code: "MALFORMED_QUERY".to_string(),
+                    message: "Unexpected token".to_string(),
+                },
+            ]),
+        });
+
+        assert_eq!(
+            "API request failed with 400 Bad Request status\n  INVALID_FIELD: No such column 'Foo'\n  MALFORMED_QUERY: Unexpected token",
+            err.context()
+        );
+        assert!(std::error::Error::source(&err).is_none());
+
+        let err = Error::UnexpectedBody {
+            error: serde_json::from_str::<u8>("x").unwrap_err(),
+            body: "x".to_string(),
+        };
+
+        assert_eq!(
+            "Failed to deserialize response\n  caused by: expected value at line 1 column 1",
+            err.context()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_error() {
+        // Bind and drop a listener to find a port that nothing is listening on
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let err: Error = reqwest::get(format!("http://{}", addr))
+            .await
+            .unwrap_err()
+            .into();
+
+        assert!(matches!(err, Error::Connect(_)));
+
+        // The underlying error is only included once, as the cause
+        let context = err.context();
+        let mut lines = context.lines();
+        assert_eq!(Some("Failed to connect"), lines.next());
+        assert!(lines.next().unwrap().starts_with("  caused by: "));
+    }
+}
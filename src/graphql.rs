@@ -0,0 +1,141 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{error::SfResult, SfClient, SfResponse};
+
+#[derive(Debug, Serialize)]
+struct GraphQlRequest<'a> {
+    query: &'a str,
+    variables: Value,
+}
+
+/// The result of a GraphQL query. Queries may partially succeed, in which case both the data and
+/// the errors for the parts of the query that failed are present
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct GraphQlResponse<R> {
+    #[serde(default = "Option::default")]
+    pub data: Option<R>,
+    #[serde(default)]
+    pub errors: Vec<GraphQlError>,
+}
+
+impl<R> GraphQlResponse<R> {
+    pub fn is_partial(&self) -> bool {
+        self.data.is_some() && !self.errors.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct GraphQlError {
+    pub message: String,
+    #[serde(default)]
+    pub locations: Vec<GraphQlLocation>,
+    /// The path to the field of the response that failed, as field names and list indices
+    #[serde(default)]
+    pub path: Vec<Value>,
+    #[serde(default)]
+    pub extensions: Option<Value>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct GraphQlLocation {
+    pub line: u32,
+    pub column: u32,
+}
+
+impl SfClient {
+    /// Runs a query against the GraphQL API. Errors in the query are reported in the body of a
+    /// successful response rather than as an error
+    pub async fn graphql<R>(
+        &self,
+        query: &str,
+        variables: Value,
+    ) -> SfResult<SfResponse<GraphQlResponse<R>>>
+    where
+        R: DeserializeOwned + 'static,
+    {
+        self.post(
+            "graphql",
+            GraphQlRequest { query, variables },
+            &[StatusCode::OK],
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use wiremock::{
+        matchers::{body_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{authenticator::jwt::tests::add_token_mock, tests::get_client, SfResponse};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Data {
+        uiapi: serde_json::Value,
+    }
+
+    #[tokio::test]
+    async fn test_graphql_partial_success() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let query = "query accounts($first: Int) { uiapi { query { Account(first: $first) { edges { node { Id } } } } } }";
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/graphql"))
+            .and(body_json(serde_json::json!({
+                "query": query,
+                "variables": { "first": 1 }
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": {
+                    "uiapi": {
+                        "query": {
+                            "Account": { "edges": [{ "node": { "Id": "001000000000001" } }] },
+                            "Secret__c": null
+                        }
+                    }
+                },
+                "errors": [{
+                    "message": "Secret__c is not supported by UI API",
+                    "locations": [{ "line": 1, "column": 80 }],
+                    "path": ["uiapi", "query", "Secret__c"],
+                    "extensions": { "ErrorType": "ValidationError" }
+                }]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let response = client
+            .graphql::<Data>(query, serde_json::json!({ "first": 1 }))
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        assert!(response.is_partial());
+        assert_eq!(
+            "001000000000001",
+            response.data.unwrap().uiapi["query"]["Account"]["edges"][0]["node"]["Id"]
+        );
+        assert_eq!(80, response.errors[0].locations[0].column);
+        assert_eq!(
+            vec![
+                serde_json::json!("uiapi"),
+                serde_json::json!("query"),
+                serde_json::json!("Secret__c")
+            ],
+            response.errors[0].path
+        );
+    }
+}
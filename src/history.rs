@@ -0,0 +1,140 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    error::SfResult, normalize_object_name, util::soql_literal, QueryResponse, SfClient, SfResponse,
+};
+
+/// A change to a tracked field of a record, as selected by [`SfClient::field_history`]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct FieldHistory {
+    pub id: String,
+    pub field: String,
+    pub old_value: Option<Value>,
+    pub new_value: Option<Value>,
+    #[serde(with = "crate::serde::datetime")]
+    pub created_date: DateTime<Utc>,
+    pub created_by_id: String,
+}
+
+// The history object of an object and the field of it that references the changed record.
+// Opportunity is the only standard object whose history is not named `{Object}History`
+#[allow(clippy::result_large_err)]
+fn history_object(object: &str) -> SfResult<(String, String)> {
+    let object = normalize_object_name(object)?;
+
+    Ok(match object.strip_suffix("__c") {
+        Some(base) => (format!("{}__History", base), "ParentId".to_string()),
+        None if object.eq_ignore_ascii_case("Opportunity") => (
+            "OpportunityFieldHistory".to_string(),
+            "OpportunityId".to_string(),
+        ),
+        None => (format!("{}History", object), format!("{}Id", object)),
+    })
+}
+
+impl SfClient {
+    /// Queries the field history of a record from the history object of its object, such as
+    /// `AccountHistory` or `Widget__History`, oldest change first. The fields of
+    /// [`FieldHistory`] are selected
+    pub async fn field_history<T>(
+        &self,
+        object: &str,
+        parent_id: &str,
+    ) -> SfResult<SfResponse<QueryResponse<T>>>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        let (history, parent_field) = history_object(object)?;
+
+        self.query(&format!(
+            "SELECT Id, Field, OldValue, NewValue, CreatedDate, CreatedById FROM {} WHERE {} = {} ORDER BY CreatedDate",
+            history,
+            parent_field,
+            soql_literal(&Value::String(parent_id.to_string()))?
+        ))
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{authenticator::jwt::tests::add_token_mock, tests::get_client, SfResponse};
+
+    use super::{history_object, FieldHistory};
+
+    #[test]
+    fn test_history_object() {
+        let names = |object| history_object(object).unwrap();
+
+        assert_eq!(
+            ("AccountHistory".to_string(), "AccountId".to_string()),
+            names("Account")
+        );
+        assert_eq!(
+            (
+                "OpportunityFieldHistory".to_string(),
+                "OpportunityId".to_string()
+            ),
+            names("Opportunity")
+        );
+        assert_eq!(
+            ("acme__Widget__History".to_string(), "ParentId".to_string()),
+            names("acme__Widget__C")
+        );
+        assert!(history_object("Widget c").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_field_history() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/query/"))
+            .and(query_param(
+                "q",
+                "SELECT Id, Field, OldValue, NewValue, CreatedDate, CreatedById FROM Widget__History WHERE ParentId = 'a00000000000001' ORDER BY CreatedDate",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "totalSize": 1,
+                "done": true,
+                "records": [{
+                    "attributes": { "type": "Widget__History", "url": "" },
+                    "Id": "017000000000001",
+                    "Field": "Status__c",
+                    "OldValue": "Draft",
+                    "NewValue": "Active",
+                    "CreatedDate": "2023-01-02T03:04:05.000+0000",
+                    "CreatedById": "005000000000001"
+                }]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let history = client
+            .field_history::<FieldHistory>("Widget__c", "a00000000000001")
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        let change = &history.records[0].object;
+        assert_eq!("Status__c", change.field);
+        assert_eq!(Some(serde_json::json!("Active")), change.new_value);
+    }
+}
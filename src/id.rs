@@ -0,0 +1,107 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+//! Conversion between the case-sensitive 15 character form of record ids and the case-insensitive
+//! 18 character form. The last three characters of an 18 character id encode which letters of
+//! the first 15 are uppercase, five characters per checksum character
+
+const CHECKSUM_CHARS: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ012345";
+
+fn checksum(id15: &[u8]) -> String {
+    id15.chunks(5)
+        .map(|chunk| {
+            let flags = chunk
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| c.is_ascii_uppercase())
+                .fold(0, |flags, (i, _)| flags | 1 << i);
+
+            CHECKSUM_CHARS[flags] as char
+        })
+        .collect()
+}
+
+/// Converts an id to its 18 character form. 18 character ids are returned with their casing
+/// and checksum normalized, so that either form of an id converts to the same value. Returns
+/// `None` if the id is not 15 or 18 alphanumeric characters
+pub fn to_18(id: &str) -> Option<String> {
+    if !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+
+    match id.len() {
+        15 => Some(format!("{}{}", id, checksum(id.as_bytes()))),
+        18 => to_18(&to_15(id)),
+        _ => None,
+    }
+}
+
+/// Converts an id to its 15 character form. The casing of 18 character ids is restored from
+/// their checksum, so ids that have been converted to a single case are still converted
+/// correctly. Ids of other lengths are returned unchanged
+pub fn to_15(id: &str) -> String {
+    if id.len() != 18 || !id.is_ascii() {
+        return id.to_string();
+    }
+
+    let (id15, suffix) = id.split_at(15);
+    let flags = suffix
+        .bytes()
+        .map(|c| {
+            CHECKSUM_CHARS
+                .iter()
+                .position(|&checksum| checksum == c.to_ascii_uppercase())
+        })
+        .collect::<Option<Vec<_>>>();
+
+    match flags {
+        Some(flags) => id15
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                if flags[i / 5] & 1 << (i % 5) != 0 {
+                    c.to_ascii_uppercase()
+                } else {
+                    c.to_ascii_lowercase()
+                }
+            })
+            .collect(),
+        None => id15.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_15, to_18};
+
+    const IDS: [(&str, &str); 4] = [
+        ("001D000000IqhSL", "001D000000IqhSLIAZ"),
+        ("0015000000Gv7qJ", "0015000000Gv7qJAAR"),
+        ("00Q000000000001", "00Q000000000001EAA"),
+        ("a0B5e00000ABCde", "a0B5e00000ABCdeEAH"),
+    ];
+
+    #[test]
+    fn test_to_18() {
+        for (id15, id18) in IDS {
+            assert_eq!(Some(id18.to_string()), to_18(id15));
+            assert_eq!(Some(id18.to_string()), to_18(id18));
+            assert_eq!(Some(id18.to_string()), to_18(&id18.to_lowercase()));
+        }
+
+        assert_eq!(None, to_18("001D000000IqhS"));
+        assert_eq!(None, to_18("001D000000IqhS!"));
+    }
+
+    #[test]
+    fn test_to_15() {
+        for (id15, id18) in IDS {
+            assert_eq!(id15, to_15(id18));
+            assert_eq!(id15, to_15(&id18.to_uppercase()));
+            assert_eq!(id15, to_15(id15));
+        }
+    }
+}
@@ -4,52 +4,368 @@
 
 // Copyright 2023 Oxide Computer Company
 
-use error::SfResult;
-use reqwest::{header::HeaderMap, Client, StatusCode};
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::fmt;
+use ::serde::{de::DeserializeOwned, Deserialize, Serialize};
+use authenticator::SfAccessToken;
+use chrono::{DateTime, Utc};
+use composite::{CompositeRequest, CompositeResponse, CompositeSubrequest};
+use describe::{FilteredDescriptions, FullObjectDescription};
+use error::{Error as SfError, SfLoginError, SfResult};
+use futures::{stream, Stream, TryStreamExt};
+use limits::{ApiUsage, CachedLimits};
+use names::validate_field_name;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, ACCEPT_LANGUAGE, LOCATION},
+    Client, Method, Request, RequestBuilder, Response, StatusCode,
+};
+use retry::RetryPolicy;
+use serde_json::{Map, Value};
+use std::{
+    any::Any,
+    collections::{HashMap, VecDeque},
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+};
 use thiserror::Error;
-use util::deser_body;
+use tracing::Instrument;
+use util::{
+    deser_body_seed, failure, handle_response, join_url, soql_datetime, soql_literal, Redacted,
+};
 
+pub mod actions;
 pub mod authenticator;
 pub use authenticator::{
-    jwt::{JwtAuthenticator, LoginClaims},
+    fallback::FallbackAuthenticator,
+    file_cache::FileCacheAuthenticator,
+    jwt::{JwtAuthenticator, LoginClaims, LoginResponseFormat},
     session::SessionAuthenticator,
-    Authenticator, AuthorizationServer,
+    soap::SoapLoginAuthenticator,
+    Authenticator, AuthorizationServer, OAuthPaths,
 };
 
-use crate::util::is_unit;
+pub mod batch;
+pub mod blob;
+pub mod builder;
+pub mod bulk;
+pub mod composite;
+pub mod compound;
+pub mod describe;
 pub mod error;
+pub mod graphql;
+pub mod history;
+pub mod id;
+pub mod limits;
+pub mod locator;
+mod names;
+pub mod process;
+#[cfg(feature = "pubsub")]
+pub mod pubsub;
+pub mod recycle_bin;
+pub mod replication;
+pub mod retry;
+pub mod serde;
+#[cfg(feature = "streaming")]
+pub mod streaming;
+#[cfg(feature = "metrics")]
+mod telemetry;
+#[cfg(feature = "test-util")]
+pub mod test_util;
 mod util;
 
+pub use batch::{BatchResult, BatchWriter};
+pub use builder::{PoolConfig, SfClientBuilder};
+pub use compound::{Address, GeoLocation};
+pub use locator::{Locator, Page};
+pub use names::normalize_object_name;
+
+// Maximum number of records that can be retrieved by a single sObject Collections request
+const MAX_COLLECTION_RETRIEVE: usize = 2000;
+const MAX_COLLECTION_WRITE: usize = 200;
+
+// Maximum number of subrequests of a single composite request
+const MAX_COMPOSITE_SUBREQUESTS: usize = 25;
+
+// Bounds of the batch size of query results
+const MIN_QUERY_BATCH_SIZE: u16 = 200;
+const MAX_QUERY_BATCH_SIZE: u16 = 2000;
+
+const SFORCE_QUERY_OPTIONS: &str = "Sforce-Query-Options";
+const SFORCE_AUTO_ASSIGN: &str = "Sforce-Auto-Assign";
+
+/// A client for the Salesforce REST API.
+///
+/// `SfClient` is `Send + Sync` and all of its methods take `&self`, so a single client can be
+/// shared between tasks and threads by wrapping it in an [`std::sync::Arc`]. Requests made
+/// through a shared client reuse the same underlying connection pool.
+///
+/// Every future and stream returned by the client can be cancelled by dropping it, such as with
+/// `tokio::select!`, and holds no resources that outlive it. Bulk jobs continue to run on the
+/// server when a future is dropped, so the polling helpers such as
+/// [`SfClient::wait_for_ingest_job`] accept a cancellation token and abort the job when it is
+/// cancelled.
 pub struct SfClient {
     inner: Client,
     instance_url: String,
+    auth_url: Option<String>,
     version: String,
     bearer: String,
+    strict_create: bool,
+    query_batch_size: Option<u16>,
+    retry_policy: RetryPolicy,
+    limits_cache: Arc<Mutex<Option<CachedLimits>>>,
+    describe_cache: Arc<Mutex<HashMap<String, FullObjectDescription>>>,
+    key_prefixes: Arc<Mutex<Option<HashMap<String, String>>>>,
+    api_usage: Arc<Mutex<Option<ApiUsage>>>,
+    usage_guard: Option<u8>,
+    locale: Option<HeaderValue>,
+    correlation_id_header: Option<HeaderName>,
+    correlation_id: Option<HeaderValue>,
     #[cfg(feature = "keep-alive")]
     keep_alive: Option<tokio::task::JoinHandle<()>>,
 }
 
+impl fmt::Debug for SfClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SfClient")
+            .field("instance_url", &self.instance_url)
+            .field("auth_url", &self.auth_url)
+            .field("version", &self.version)
+            .field("bearer", &Redacted)
+            .finish_non_exhaustive()
+    }
+}
+
 impl SfClient {
     pub async fn new(version: String, authenticator: impl Authenticator) -> SfResult<Self> {
-        let token = authenticator.get_token().await?;
-        Ok(Self {
-            inner: Client::new(),
+        Self::builder(version).build(authenticator).await
+    }
+
+    /// Creates a builder for clients that require additional configuration of their underlying
+    /// HTTP client
+    pub fn builder(version: String) -> SfClientBuilder {
+        SfClientBuilder::new(version)
+    }
+
+    fn from_token(
+        inner: Client,
+        version: String,
+        token: SfAccessToken,
+        auth_url: Option<String>,
+    ) -> Self {
+        Self {
+            inner,
             instance_url: token.instance_url,
+            auth_url,
             version,
             bearer: token.access_token,
+            strict_create: false,
+            query_batch_size: None,
+            retry_policy: RetryPolicy::default(),
+            limits_cache: Arc::default(),
+            describe_cache: Arc::default(),
+            key_prefixes: Arc::default(),
+            api_usage: Arc::default(),
+            usage_guard: None,
+            locale: None,
+            correlation_id_header: None,
+            correlation_id: None,
             #[cfg(feature = "keep-alive")]
             keep_alive: None,
-        })
+        }
+    }
+
+    /// When enabled, creates that respond with `success: false` are returned as
+    /// [`SfError::ApiFailure`] rather than as a successful response
+    pub fn with_strict_create(&mut self, strict_create: bool) -> &mut Self {
+        self.strict_create = strict_create;
+        self
+    }
+
+    /// Creates a client that authenticates with the given access token instead of the token of
+    /// this client, for requests made on behalf of another user of the same instance. The
+    /// returned client shares the connection pool and the recorded API usage of this client and
+    /// is cheap to create, so one may be created for each request
+    pub fn with_access_token(&self, access_token: String) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            instance_url: self.instance_url.clone(),
+            auth_url: self.auth_url.clone(),
+            version: self.version.clone(),
+            bearer: access_token,
+            strict_create: self.strict_create,
+            query_batch_size: self.query_batch_size,
+            retry_policy: self.retry_policy.clone(),
+            // Limits and field visibility are not necessarily shared between users
+            limits_cache: Arc::default(),
+            describe_cache: Arc::default(),
+            key_prefixes: Arc::default(),
+            // API usage is counted for the whole org
+            api_usage: self.api_usage.clone(),
+            usage_guard: self.usage_guard,
+            locale: self.locale.clone(),
+            correlation_id_header: self.correlation_id_header.clone(),
+            correlation_id: self.correlation_id.clone(),
+            #[cfg(feature = "keep-alive")]
+            keep_alive: None,
+        }
+    }
+
+    /// Sets the number of records returned in each batch of query results, between 200 and
+    /// 2000. Larger batches require fewer requests to read large results. Salesforce may return
+    /// smaller batches than requested, such as for queries that select many fields
+    #[allow(clippy::result_large_err)]
+    pub fn with_query_batch_size(&mut self, batch_size: u16) -> SfResult<&mut Self> {
+        if !(MIN_QUERY_BATCH_SIZE..=MAX_QUERY_BATCH_SIZE).contains(&batch_size) {
+            return Err(SfError::InvalidInput(format!(
+                "Query batch size must be between {} and {} but was {}",
+                MIN_QUERY_BATCH_SIZE, MAX_QUERY_BATCH_SIZE, batch_size
+            )));
+        }
+
+        self.query_batch_size = Some(batch_size);
+        Ok(self)
+    }
+
+    /// Retries requests that fail with transient API errors, see [`RetryPolicy`]
+    pub fn with_retry_policy(&mut self, retry_policy: RetryPolicy) -> &mut Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Refuses to send requests with [`SfError::UsageBudgetExceeded`] once the API usage of the org
+    /// has exceeded the given percentage of its daily limit, until the next day (UTC). Usage is
+    /// taken from the `Sforce-Limit-Info` header of the latest response. Percentages over 100 are
+    /// rejected with [`SfError::InvalidInput`]
+    #[allow(clippy::result_large_err)]
+    pub fn with_usage_guard(&mut self, threshold_percent: u8) -> SfResult<&mut Self> {
+        if threshold_percent > 100 {
+            return Err(SfError::InvalidInput(format!(
+                "Usage guard threshold must be a percentage between 0 and 100 but was {}",
+                threshold_percent
+            )));
+        }
+
+        self.usage_guard = Some(threshold_percent);
+        Ok(self)
+    }
+
+    /// Requests labels, such as those of describes and picklist values, in the given language
+    /// rather than the language of the user, for example `de` or `fr-FR`
+    #[allow(clippy::result_large_err)]
+    pub fn with_locale(&mut self, locale: &str) -> SfResult<&mut Self> {
+        let locale = HeaderValue::from_str(locale)
+            .map_err(|_| SfError::InvalidInput(format!("{:?} is not a valid locale", locale)))?;
+
+        self.locale = Some(locale);
+        Ok(self)
+    }
+
+    /// Sends the correlation id of a client, see [`SfClient::correlation_id`], in a header with
+    /// the given name. Salesforce ignores the header, but it can be logged by proxies
+    #[allow(clippy::result_large_err)]
+    pub fn with_correlation_id_header(&mut self, name: String) -> SfResult<&mut Self> {
+        let name = HeaderName::try_from(name)
+            .map_err(|err| SfError::InvalidInput(format!("Invalid header name: {}", err)))?;
+
+        self.correlation_id_header = Some(name);
+        Ok(self)
+    }
+
+    /// Creates a client whose requests are tagged with the given correlation id, which is
+    /// recorded on the tracing span of each request and sent in the header set by
+    /// [`SfClient::with_correlation_id_header`]. Like [`SfClient::with_access_token`], the
+    /// returned client shares the connection pool of this client and may be created per request.
+    /// It also shares the caches and the recorded API usage of this client
+    #[allow(clippy::result_large_err)]
+    pub fn correlation_id(&self, correlation_id: &str) -> SfResult<Self> {
+        let value = HeaderValue::from_str(correlation_id).map_err(|_| {
+            SfError::InvalidInput(format!(
+                "{:?} is not a valid correlation id",
+                correlation_id
+            ))
+        })?;
+
+        let mut client = self.share();
+        client.correlation_id = Some(value);
+        Ok(client)
+    }
+
+    // Creates a client that authenticates as the same user, and so shares the caches of this client
+    // along with its API usage
+    fn share(&self) -> Self {
+        let mut client = self.with_access_token(self.bearer.clone());
+        client.limits_cache = self.limits_cache.clone();
+        client.describe_cache = self.describe_cache.clone();
+        client.key_prefixes = self.key_prefixes.clone();
+        client
+    }
+
+    /// The instance that requests are sent to, as returned with the access token
+    pub fn instance_url(&self) -> &str {
+        &self.instance_url
+    }
+
+    /// The url that the access token was requested from, if known. This can differ from the
+    /// instance url, such as when authenticating through a generic login domain or after the
+    /// org has been migrated to another instance
+    pub fn auth_url(&self) -> Option<&str> {
+        self.auth_url.as_deref()
+    }
+
+    /// Whether the instance is a sandbox rather than production, as determined by its url.
+    /// Sandboxes are served from `*.sandbox.my.salesforce.com` domains, or for orgs without
+    /// enhanced domains from `{domain}--{sandbox}.my.salesforce.com` and `cs*.salesforce.com`
+    pub fn is_sandbox(&self) -> bool {
+        let Some(host) = reqwest::Url::parse(&self.instance_url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_ascii_lowercase()))
+        else {
+            return false;
+        };
+
+        let first_label = host.split('.').next().unwrap_or_default();
+
+        host.ends_with(".sandbox.my.salesforce.com")
+            || (host.ends_with(".my.salesforce.com") && first_label.contains("--"))
+            || (host.ends_with(".salesforce.com")
+                && first_label.len() > 2
+                && first_label.starts_with("cs")
+                && first_label[2..].chars().all(|c| c.is_ascii_digit()))
+    }
+
+    /// Classic UI link to a record, which redirects to Lightning for users that have it enabled
+    pub fn record_url(&self, id: &str) -> String {
+        join_url(&self.instance_url, id)
+    }
+
+    pub fn lightning_record_url(&self, object: &str, id: &str) -> String {
+        join_url(
+            &self.instance_url,
+            &format!("lightning/r/{}/{}/view", object, id),
+        )
     }
 
     fn url(&self, path: &str) -> String {
-        let url = format!(
-            "{}/services/data/v{}/{}",
-            self.instance_url, self.version, path
-        );
-        url
+        self.service_url(Service::Data, path)
+    }
+
+    /// The full url of a resource under the given service
+    pub fn service_url(&self, service: Service, path: &str) -> String {
+        join_url(&self.instance_url, &self.service_path(service, path))
+    }
+
+    /// The path of a resource under the given service relative to the instance
+    pub fn service_path(&self, service: Service, path: &str) -> String {
+        format!(
+            "{}/{}",
+            service.root(&self.version),
+            path.trim_start_matches('/')
+        )
+    }
+
+    /// The path of a REST resource relative to the instance, as used by composite subrequests
+    pub fn data_path(&self, path: &str) -> String {
+        self.service_path(Service::Data, path)
     }
 
     fn object_path(&self, path: &str) -> String {
@@ -57,67 +373,102 @@ impl SfClient {
         url
     }
 
+    // Ids and external id values are encoded as they may contain reserved characters
+    fn record_path(&self, object: &str, id: &str) -> String {
+        self.object_path(&format!("{}/{}", object, urlencoding::encode(id)))
+    }
+
     async fn get<T>(&self, path: &str) -> SfResult<SfResponse<T>>
     where
-        T: DeserializeOwned,
+        T: DeserializeOwned + 'static,
+    {
+        self.get_url(&self.url(path)).await
+    }
+
+    async fn get_url<T>(&self, url: &str) -> SfResult<SfResponse<T>>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        self.get_url_with_headers(url, HeaderMap::new()).await
+    }
+
+    async fn get_url_with_headers<T>(
+        &self,
+        url: &str,
+        headers: HeaderMap,
+    ) -> SfResult<SfResponse<T>>
+    where
+        T: DeserializeOwned + 'static,
     {
-        let url = self.url(path);
         tracing::trace!(?url, "GET request");
 
-        let response = self
-            .inner
-            .get(&url)
-            .bearer_auth(&self.bearer)
-            .send()
-            .await?;
-        let headers = response.headers().clone();
-        let status = response.status().clone();
-        let body = response.text().await?;
+        self.send(
+            self.inner
+                .get(url)
+                .bearer_auth(&self.bearer)
+                .headers(headers),
+            &[StatusCode::OK],
+        )
+        .await
+    }
 
-        match status {
-            StatusCode::OK => Ok(SfResponse {
-                headers,
-                status,
-                body: deser_body(&body)?,
-            }),
-            _ => Err(SfResponse {
-                headers,
-                status,
-                body: Some(deser_body::<Vec<SfApiError>>(&body)?),
-            })?,
+    fn query_options(&self) -> Option<String> {
+        self.query_batch_size
+            .map(|batch_size| format!("batchSize={}", batch_size))
+    }
+
+    async fn get_query_page<T>(&self, url: &str) -> SfResult<SfResponse<QueryResponse<T>>>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        let mut headers = HeaderMap::new();
+        if let Some(options) = self
+            .query_options()
+            .and_then(|options| options.parse().ok())
+        {
+            headers.insert(SFORCE_QUERY_OPTIONS, options);
         }
+
+        self.get_url_with_headers(url, headers).await
+    }
+
+    async fn post<T, U>(
+        &self,
+        path: &str,
+        body: T,
+        expected_statuses: &[StatusCode],
+    ) -> SfResult<SfResponse<U>>
+    where
+        T: Serialize,
+        U: DeserializeOwned + 'static,
+    {
+        self.post_with_headers(path, body, HeaderMap::new(), expected_statuses)
+            .await
     }
 
-    async fn post<T>(&self, path: &str, body: T) -> SfResult<SfResponse<CreateObjectResponse>>
+    async fn post_with_headers<T, U>(
+        &self,
+        path: &str,
+        body: T,
+        headers: HeaderMap,
+        expected_statuses: &[StatusCode],
+    ) -> SfResult<SfResponse<U>>
     where
         T: Serialize,
+        U: DeserializeOwned + 'static,
     {
         let url = self.url(path);
         tracing::trace!(?url, "POST request");
 
-        let response = self
-            .inner
-            .post(&url)
-            .bearer_auth(&self.bearer)
-            .json(&body)
-            .send()
-            .await?;
-        let headers = response.headers().clone();
-        let status = response.status().clone();
-        let body = response.text().await?;
-
-        match status {
-            StatusCode::CREATED => Ok(SfResponse {
-                headers,
-                status,
-                body: deser_body(&body)?,
-            }),
-            _ => Err(SfResponse {
-                headers,
-                status,
-                body: Some(deser_body::<Vec<SfApiError>>(&body)?),
-            })?,
-        }
+        self.send(
+            self.inner
+                .post(&url)
+                .bearer_auth(&self.bearer)
+                .headers(headers)
+                .json(&body),
+            expected_statuses,
+        )
+        .await
     }
 
     async fn patch<T, U>(&self, path: &str, body: T) -> SfResult<SfResponse<U>>
@@ -128,154 +479,784 @@ impl SfClient {
         let url = self.url(path);
         tracing::trace!(?url, "PATCH request");
 
-        let response = self
-            .inner
-            .patch(&url)
-            .bearer_auth(&self.bearer)
-            .json(&body)
-            .send()
-            .await?;
-        let headers = response.headers().clone();
-        let status = response.status().clone();
-        let body = response.text().await?;
-
-        match status {
-            StatusCode::NO_CONTENT | StatusCode::CREATED | StatusCode::OK => Ok(SfResponse {
-                headers,
-                status,
-                body: if is_unit::<U>() && body == "" {
-                    None
-                } else {
-                    Some(deser_body(&body)?)
-                },
-            }),
-            _ => Err(SfResponse {
-                headers,
-                status,
-                body: Some(deser_body::<Vec<SfApiError>>(&body)?),
-            })?,
-        }
+        self.send(
+            self.inner.patch(&url).bearer_auth(&self.bearer).json(&body),
+            &[StatusCode::OK, StatusCode::CREATED, StatusCode::NO_CONTENT],
+        )
+        .await
     }
 
     async fn delete(&self, path: &str) -> SfResult<SfResponse<()>> {
         let url = self.url(path);
         tracing::trace!(?url, "DELETE request");
 
-        let response = self
-            .inner
-            .delete(&url)
-            .bearer_auth(&self.bearer)
-            .send()
-            .await?;
-        let headers = response.headers().clone();
-        let status = response.status().clone();
-        let body = response.text().await?;
+        self.send(
+            self.inner.delete(&url).bearer_auth(&self.bearer),
+            &[StatusCode::NO_CONTENT],
+        )
+        .await
+    }
 
-        match status {
-            StatusCode::NO_CONTENT => Ok(SfResponse {
-                headers,
-                status,
-                body: Some(()),
-            }),
-            _ => Err(SfResponse {
-                headers,
-                status,
-                body: Some(deser_body::<Vec<SfApiError>>(&body)?),
-            })?,
-        }
+    // Sends a request, retrying it according to the retry policy
+    async fn send<T>(
+        &self,
+        request: RequestBuilder,
+        expected_statuses: &[StatusCode],
+    ) -> SfResult<SfResponse<T>>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        self.send_with(request, |response| {
+            handle_response(response, expected_statuses)
+        })
+        .await
     }
 
-    pub async fn describe_objects(&self) -> SfResult<SfResponse<ObjectDescriptionsResponse>> {
-        self.get(&self.object_path("")).await
+    // Sends a request, retrying it according to the retry policy, and reads each response with
+    // the handler. Every request made with the token of the client is sent through here, so that
+    // each one is checked against the usage guard, carries the locale and correlation id, and is
+    // recorded in metrics
+    async fn send_with<T, F, Fut>(&self, request: RequestBuilder, handle: F) -> SfResult<T>
+    where
+        F: Fn(Response) -> Fut,
+        Fut: Future<Output = SfResult<T>>,
+    {
+        let mut request = request.build()?;
+        if let Some(locale) = &self.locale {
+            request
+                .headers_mut()
+                .entry(ACCEPT_LANGUAGE)
+                .or_insert_with(|| locale.clone());
+        }
+
+        if let (Some(name), Some(value)) = (&self.correlation_id_header, &self.correlation_id) {
+            request.headers_mut().insert(name.clone(), value.clone());
+        }
+
+        let span = tracing::debug_span!(
+            "sf_request",
+            correlation_id = self
+                .correlation_id
+                .as_ref()
+                .and_then(|value| value.to_str().ok())
+        );
+
+        async move {
+            let mut attempt = 0;
+
+            loop {
+                // Requests with streamed bodies can not be cloned and so are not retried
+                let Some(retry) = request.try_clone() else {
+                    return self.execute(request, &handle).await;
+                };
+
+                match self.execute(retry, &handle).await {
+                    Err(err) if self.retry_policy.should_retry(attempt, &err) => {
+                        let delay = self.retry_policy.delay(attempt);
+                        tracing::debug!(?err, ?delay, attempt, "Retrying request");
+
+                        #[cfg(feature = "metrics")]
+                        telemetry::record_retry(&request);
+
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    result => return result,
+                }
+            }
+        }
+        .instrument(span)
+        .await
     }
 
-    pub async fn describe_object(
-        &self,
-        object: &str,
-    ) -> SfResult<SfResponse<ObjectDescriptionResponse>> {
-        self.get(&self.object_path(object)).await
+    // Sends a single attempt of a request
+    async fn execute<T, F, Fut>(&self, request: Request, handle: &F) -> SfResult<T>
+    where
+        F: Fn(Response) -> Fut,
+        Fut: Future<Output = SfResult<T>>,
+    {
+        self.check_usage()?;
+
+        #[cfg(feature = "metrics")]
+        let timer = telemetry::RequestTimer::start(&request);
+
+        let response = match self.inner.execute(request).await {
+            Ok(response) => response,
+            Err(err) => {
+                #[cfg(feature = "metrics")]
+                timer.finish(None);
+
+                return Err(err.into());
+            }
+        };
+
+        self.record_usage(response.headers());
+
+        #[cfg(feature = "metrics")]
+        let status = response.status();
+
+        let result = handle(response).await;
+
+        #[cfg(feature = "metrics")]
+        timer.finish(Some(status));
+
+        result
     }
 
-    pub async fn create_object<T>(
+    /// Sends an authenticated request to a path relative to the REST API root and returns the
+    /// response as it was received, for resources that this client does not model. The status of
+    /// the response is not checked and its body is not read, both are left to the caller. Like
+    /// every other request, it is checked against the usage guard and carries the locale and
+    /// correlation id of the client
+    pub async fn send_raw<T>(
         &self,
-        object: &str,
-        body: T,
-    ) -> SfResult<SfResponse<CreateObjectResponse>>
+        method: Method,
+        path: &str,
+        body: Option<T>,
+    ) -> SfResult<reqwest::Response>
     where
         T: Serialize,
     {
-        self.post(&self.object_path(object), body).await
+        let url = self.url(path);
+        tracing::trace!(?url, %method, "Raw request");
+
+        let mut request = self.inner.request(method, &url).bearer_auth(&self.bearer);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        self.send_with(request, |response| async { Ok(response) })
+            .await
     }
 
-    pub async fn get_object<T>(&self, object: &str, id: &str) -> SfResult<SfResponse<T>>
-    where
-        T: DeserializeOwned,
-    {
-        self.get::<T>(&self.object_path(&format!("{}/{}", object, id)))
+    /// Lists the API versions supported by the instance
+    pub async fn versions(&self) -> SfResult<SfResponse<Vec<ApiVersion>>> {
+        self.get_url(&join_url(&self.instance_url, "services/data/"))
             .await
     }
 
-    pub async fn query<T>(&self, query: &str) -> SfResult<SfResponse<QueryResponse<T>>>
+    /// Lists the REST resources available in the API version of the client, such as `sobjects`
+    /// or `graphql`, keyed by name with their paths as values
+    pub async fn resources(&self) -> SfResult<SfResponse<HashMap<String, String>>> {
+        self.get("").await
+    }
+
+    /// The most recent API version supported by the instance
+    pub async fn latest_version(&self) -> SfResult<Option<ApiVersion>> {
+        let versions = self.versions().await?.into_body()?;
+
+        Ok(versions.into_iter().max_by(|a, b| {
+            a.as_f64()
+                .unwrap_or_default()
+                .total_cmp(&b.as_f64().unwrap_or_default())
+        }))
+    }
+
+    pub async fn describe_objects(&self) -> SfResult<SfResponse<ObjectDescriptionsResponse>> {
+        self.get(&self.object_path("")).await
+    }
+
+    /// Fetches the global describe and retains only the objects that match the predicate.
+    /// Objects are filtered while the response is parsed, so only the matching objects are held
+    /// in memory, which suits orgs with a very large number of objects
+    pub async fn describe_global_filtered<F>(
+        &self,
+        predicate: F,
+    ) -> SfResult<SfResponse<ObjectDescriptionsResponse>>
     where
-        T: DeserializeOwned,
+        F: Fn(&ObjectDescription) -> bool,
     {
-        let query = urlencoding::encode(query);
-        self.get::<QueryResponse<T>>(&format!("query/?q={}", query))
+        let url = self.url(&self.object_path(""));
+        tracing::trace!(?url, "GET request");
+
+        let predicate = &predicate;
+        self.send_with(
+            self.inner.get(&url).bearer_auth(&self.bearer),
+            |response| async move {
+                if response.status() != StatusCode::OK {
+                    return Err(failure(response).await);
+                }
+
+                let headers = response.headers().clone();
+                let status = response.status();
+                let body = response.text().await?;
+
+                let descriptions = deser_body_seed(&body, FilteredDescriptions(predicate))?;
+
+                Ok(SfResponse {
+                    headers,
+                    status,
+                    body: Some(descriptions),
+                })
+            },
+        )
+        .await
+    }
+
+    /// Alias of [`SfClient::describe_object_basic`]
+    pub async fn describe_object(
+        &self,
+        object: &str,
+    ) -> SfResult<SfResponse<ObjectDescriptionResponse>> {
+        self.describe_object_basic(object).await
+    }
+
+    /// Fetches the basic description of an object along with its recently viewed items. This is
+    /// considerably cheaper than [`SfClient::describe_object_full`]
+    pub async fn describe_object_basic(
+        &self,
+        object: &str,
+    ) -> SfResult<SfResponse<ObjectDescriptionResponse>> {
+        self.get(&self.object_path(object)).await
+    }
+
+    /// Fetches the complete metadata of an object, including its fields and relationships
+    pub async fn describe_object_full(
+        &self,
+        object: &str,
+    ) -> SfResult<SfResponse<FullObjectDescription>> {
+        self.get(&self.object_path(&format!("{}/describe", object)))
             .await
     }
 
-    pub async fn update_object<T>(
+    pub async fn create_object<T>(
         &self,
         object: &str,
-        id: &str,
         body: T,
-    ) -> SfResult<SfResponse<()>>
+    ) -> SfResult<SfResponse<CreateObjectResponse>>
     where
         T: Serialize,
     {
-        self.patch(&self.object_path(&format!("{}/{}", object, id)), body)
+        self.create_object_with_options(object, body, &CreateOptions::default())
             .await
     }
 
-    pub async fn upsert_object<T>(
+    /// Creates a record, setting the record type and owner from the options when they are not
+    /// modelled by the record itself. Options override the same fields of the record
+    pub async fn create_object_with_options<T>(
         &self,
         object: &str,
-        id: &ExternalId,
         body: T,
+        options: &CreateOptions,
     ) -> SfResult<SfResponse<CreateObjectResponse>>
     where
         T: Serialize,
     {
-        self.patch(
-            &self.object_path(&format!("{}/{}/{}", object, id.field, id.value)),
-            body,
-        )
-        .await
+        let mut body =
+            serde_json::to_value(body).map_err(|err| SfError::InvalidInput(err.to_string()))?;
+
+        for (field, value) in [
+            ("RecordTypeId", &options.record_type_id),
+            ("OwnerId", &options.owner_id),
+        ] {
+            if let Some(value) = value {
+                body.as_object_mut()
+                    .ok_or_else(|| {
+                        SfError::InvalidInput(format!(
+                            "Unable to set {} on a record that is not an object",
+                            field
+                        ))
+                    })?
+                    .insert(field.to_string(), Value::String(value.clone()));
+            }
+        }
+
+        let mut headers = HeaderMap::new();
+        if let Some(rule) = &options.assignment_rule {
+            headers.insert(SFORCE_AUTO_ASSIGN, rule.header_value()?);
+        }
+
+        let response: SfResponse<CreateObjectResponse> = self
+            .post_with_headers(
+                &self.object_path(object),
+                body,
+                headers,
+                &[StatusCode::CREATED],
+            )
+            .await?;
+
+        match response.body {
+            Some(body) if self.strict_create && !body.success => Err(SfResponse {
+                headers: response.headers,
+                status: response.status,
+                body: Some(body.errors),
+            })?,
+            body => Ok(SfResponse { body, ..response }),
+        }
     }
 
-    pub async fn delete_object(&self, object: &str, id: &str) -> SfResult<SfResponse<()>> {
-        self.delete(&self.object_path(&format!("{}/{}", object, id)))
-            .await
+    pub async fn get_object<T>(&self, object: &str, id: &str) -> SfResult<SfResponse<T>>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        self.get::<T>(&self.record_path(object, id)).await
     }
 
-    #[cfg(feature = "keep-alive")]
-    pub fn start_keep_alive(&mut self, mut interval: tokio::time::Interval) {
-        let client = self.inner.clone();
-        let bearer = self.bearer.clone();
-        let url = self.url("");
+    /// Checks whether a record exists without retrieving it
+    pub async fn object_exists(&self, object: &str, id: &str) -> SfResult<bool> {
+        let url = self.url(&self.record_path(object, id));
+        tracing::trace!(?url, "HEAD request");
+
+        self.send_with(
+            self.inner.head(&url).bearer_auth(&self.bearer),
+            |response| async move {
+                match response.status() {
+                    StatusCode::OK => Ok(true),
+                    StatusCode::NOT_FOUND => Ok(false),
+                    _ => Err(failure(response).await),
+                }
+            },
+        )
+        .await
+    }
 
-        self.keep_alive = Some(tokio::spawn(async move {
+    /// Retrieves multiple records of the same object type in a single request. Ids that do not
+    /// match a record are returned as `None` in the position they were requested.
+    ///
+    /// The ids and fields are sent in the body of the request rather than the url, so that up to
+    /// 2000 records can be retrieved without the url exceeding the length that Salesforce accepts
+    pub async fn get_objects<T>(
+        &self,
+        object: &str,
+        ids: &[&str],
+        fields: &[&str],
+    ) -> SfResult<SfResponse<Vec<Option<T>>>>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        if ids.is_empty() || ids.len() > MAX_COLLECTION_RETRIEVE {
+            return Err(SfError::InvalidInput(format!(
+                "Expected between 1 and {} ids but received {}",
+                MAX_COLLECTION_RETRIEVE,
+                ids.len()
+            )));
+        }
+
+        if fields.is_empty() {
+            return Err(SfError::InvalidInput(
+                "At least one field must be requested".to_string(),
+            ));
+        }
+
+        self.post(
+            &format!("composite/sobjects/{}", object),
+            serde_json::json!({ "ids": ids, "fields": fields }),
+            &[StatusCode::OK],
+        )
+        .await
+    }
+
+    pub async fn query<T>(&self, query: &str) -> SfResult<SfResponse<QueryResponse<T>>>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        tracing::debug!(soql = query, "Query");
+        self.get_query_page(&self.build_query_url(query)).await
+    }
+
+    /// The url that [`SfClient::query`] requests for a query, with the query encoded
+    pub fn build_query_url(&self, query: &str) -> String {
+        self.url(&format!("query/?q={}", urlencoding::encode(query)))
+    }
+
+    /// Fetches the plans that the query optimizer considered for a query, without running it. The
+    /// plan with the lowest relative cost is listed first
+    pub async fn query_explain(&self, query: &str) -> SfResult<SfResponse<QueryPlanResponse>> {
+        self.get(&format!("query/?explain={}", urlencoding::encode(query)))
+            .await
+    }
+
+    /// Runs a query that includes deleted and archived records
+    pub async fn query_all<T>(&self, query: &str) -> SfResult<SfResponse<QueryResponse<T>>>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        tracing::debug!(soql = query, "Query all");
+        let query = urlencoding::encode(query);
+        self.get_query_page(&self.url(&format!("queryAll/?q={}", query)))
+            .await
+    }
+
+    /// Runs a query and streams its records, fetching the next batch of results as the stream
+    /// is consumed until every record has been read
+    #[allow(clippy::result_large_err)]
+    pub fn query_stream<T>(&self, query: &str) -> impl Stream<Item = SfResult<T>> + '_
+    where
+        T: DeserializeOwned + 'static,
+    {
+        tracing::debug!(soql = query, "Query stream");
+        let url = self.build_query_url(query);
+
+        stream::try_unfold(Some(url), move |url| async move {
+            let Some(url) = url else {
+                return Ok::<_, SfError>(None);
+            };
+
+            let page = self.get_query_page::<T>(&url).await?.into_body()?;
+            let next = page
+                .next_records_url
+                .map(|path| join_url(&self.instance_url, &path));
+
+            Ok(Some((
+                stream::iter(page.records.into_iter().map(|record| Ok(record.object))),
+                next,
+            )))
+        })
+        .try_flatten()
+    }
+
+    /// Streams the given fields of every record of an object that has been modified after
+    /// `since`, ordered by modification time
+    pub fn changed_since<T>(
+        &self,
+        object: &str,
+        fields: &[&str],
+        since: DateTime<Utc>,
+    ) -> impl Stream<Item = SfResult<T>> + '_
+    where
+        T: DeserializeOwned + 'static,
+    {
+        self.query_stream(&format!(
+            "SELECT {} FROM {} WHERE SystemModstamp > {} ORDER BY SystemModstamp",
+            fields.join(", "),
+            object,
+            soql_datetime(&since)
+        ))
+    }
+
+    /// Runs a query through the composite API so that the query is sent in the request body
+    /// rather than the url. Use this for queries that would exceed url length limits, such as
+    /// those with large `IN` clauses
+    pub async fn query_post<T>(&self, query: &str) -> SfResult<SfResponse<QueryResponse<T>>>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        let query = urlencoding::encode(query);
+        let mut subrequest = CompositeSubrequest::new(
+            Method::GET,
+            self.data_path(&format!("query/?q={}", query)),
+            "query".to_string(),
+        );
+
+        if let Some(options) = self.query_options() {
+            subrequest = subrequest.header(SFORCE_QUERY_OPTIONS.to_string(), options);
+        }
+
+        let request = CompositeRequest::new(vec![subrequest]);
+
+        self.composite(&request)
+            .await?
+            .body
+            .and_then(|mut body| body.take("query"))
+            .ok_or_else(|| SfError::MissingSubresponse("query".to_string()))?
+            .into_response()
+    }
+
+    /// Runs a composite request with additional request headers, such as `Prefer`. Requests that
+    /// Salesforce accepts for asynchronous processing return a `202 Accepted` response without a
+    /// body, see [`SfResponse::location`] for where to poll for the result
+    pub async fn composite_with_headers(
+        &self,
+        request: &CompositeRequest,
+        headers: HeaderMap,
+    ) -> SfResult<SfResponse<CompositeResponse>> {
+        self.post_with_headers(
+            "composite",
+            request,
+            headers,
+            &[StatusCode::OK, StatusCode::ACCEPTED],
+        )
+        .await
+    }
+
+    /// Runs a series of subrequests in a single call. Subrequests are executed in order
+    /// and may reference the results of earlier subrequests
+    pub async fn composite(
+        &self,
+        request: &CompositeRequest,
+    ) -> SfResult<SfResponse<CompositeResponse>> {
+        self.post("composite", request, &[StatusCode::OK]).await
+    }
+
+    /// Runs an aggregate (`GROUP BY`) query, returning each row as a map keyed by the selected
+    /// field names and aliases. Use [`SfClient::query`] with a struct that names the aliases when
+    /// the shape of the result is known ahead of time.
+    pub async fn query_aggregate(
+        &self,
+        query: &str,
+    ) -> SfResult<SfResponse<QueryResponse<Map<String, Value>>>> {
+        self.query(query).await
+    }
+
+    pub async fn update_object<T>(
+        &self,
+        object: &str,
+        id: &str,
+        body: T,
+    ) -> SfResult<SfResponse<()>>
+    where
+        T: Serialize,
+    {
+        self.patch(&self.record_path(object, id), body).await
+    }
+
+    /// Updates a record and fetches its new representation in a single round trip by running
+    /// both requests through the composite API. The update is applied with `allOrNone` so that
+    /// the record is only fetched once the update has succeeded
+    pub async fn update_and_return<T, U>(
+        &self,
+        object: &str,
+        id: &str,
+        body: T,
+    ) -> SfResult<SfResponse<U>>
+    where
+        T: Serialize,
+        U: DeserializeOwned,
+    {
+        let body =
+            serde_json::to_value(body).map_err(|err| SfError::InvalidInput(err.to_string()))?;
+        let record_path = self.data_path(&self.record_path(object, id));
+
+        let request = CompositeRequest::new(vec![
+            CompositeSubrequest::new(Method::PATCH, record_path.clone(), "update".to_string())
+                .body(body),
+            CompositeSubrequest::new(Method::GET, record_path, "fetch".to_string()),
+        ])
+        .all_or_none(true);
+
+        let mut response = self
+            .composite(&request)
+            .await?
+            .body
+            .ok_or_else(|| SfError::MissingSubresponse("update".to_string()))?;
+
+        response
+            .take("update")
+            .ok_or_else(|| SfError::MissingSubresponse("update".to_string()))?
+            .into_response::<()>()?;
+
+        response
+            .take("fetch")
+            .ok_or_else(|| SfError::MissingSubresponse("fetch".to_string()))?
+            .into_response()
+    }
+
+    /// Updates a record only if a field still has an expected value. A guard query and the update
+    /// are run through the composite API with `allOrNone`, and the update references the record
+    /// found by the guard so that it fails when the guard finds nothing. Returns whether the
+    /// record was updated.
+    ///
+    /// The check is not atomic: SOQL through the REST API can not lock the record, so a write that
+    /// lands between the guard and the update is overwritten. The object and the field must be API
+    /// names, otherwise [`SfError::InvalidInput`] is returned
+    pub async fn update_object_if<T>(
+        &self,
+        object: &str,
+        id: &str,
+        field: &str,
+        expected: &Value,
+        body: T,
+    ) -> SfResult<bool>
+    where
+        T: Serialize,
+    {
+        let body =
+            serde_json::to_value(body).map_err(|err| SfError::InvalidInput(err.to_string()))?;
+        let object = normalize_object_name(object)?;
+        let guard = format!(
+            "SELECT Id FROM {} WHERE Id = {} AND {} = {}",
+            object,
+            soql_literal(&Value::String(id.to_string()))?,
+            validate_field_name(field)?,
+            soql_literal(expected)?
+        );
+
+        let request = CompositeRequest::new(vec![
+            CompositeSubrequest::new(
+                Method::GET,
+                self.data_path(&format!("query/?q={}", urlencoding::encode(&guard))),
+                "guard".to_string(),
+            ),
+            CompositeSubrequest::new(
+                Method::PATCH,
+                self.data_path(&self.object_path(&format!("{}/@{{guard.records[0].Id}}", object))),
+                "update".to_string(),
+            )
+            .body(body),
+        ])
+        .all_or_none(true);
+
+        let mut response = self
+            .composite(&request)
+            .await?
+            .body
+            .ok_or_else(|| SfError::MissingSubresponse("update".to_string()))?;
+
+        let update = response
+            .take("update")
+            .ok_or_else(|| SfError::MissingSubresponse("update".to_string()))?
+            .into_response::<()>();
+
+        // The update fails to resolve its reference when the guard did not match, which rolls
+        // back the guard along with it
+        match update {
+            Ok(_) => Ok(true),
+            Err(SfError::ApiFailure(failure)) if unresolved_reference(&failure) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Updates a record using only the fields of the body that the describe reports as
+    /// updateable. This allows records that were fetched with read-only fields such as `Id` or
+    /// `CreatedDate` to be written back
+    pub async fn update_object_with_describe<T>(
+        &self,
+        object: &str,
+        id: &str,
+        body: T,
+        describe: &FullObjectDescription,
+    ) -> SfResult<SfResponse<()>>
+    where
+        T: Serialize,
+    {
+        let mut record = match serde_json::to_value(body) {
+            Ok(Value::Object(record)) => record,
+            Ok(_) => {
+                return Err(SfError::InvalidInput(
+                    "Record must serialize to an object".to_string(),
+                ))
+            }
+            Err(err) => return Err(SfError::InvalidInput(err.to_string())),
+        };
+
+        describe.retain_updateable(&mut record);
+
+        self.update_object(object, id, record).await
+    }
+
+    /// Fetches the describe of the object and updates the record using only its updateable
+    /// fields. See [`SfClient::update_object_with_describe`]
+    pub async fn update_object_updateable<T>(
+        &self,
+        object: &str,
+        id: &str,
+        body: T,
+    ) -> SfResult<SfResponse<()>>
+    where
+        T: Serialize,
+    {
+        let describe = self.describe_object_full(object).await?.into_body()?;
+        self.update_object_with_describe(object, id, body, &describe)
+            .await
+    }
+
+    pub async fn upsert_object<T>(
+        &self,
+        object: &str,
+        id: &ExternalId,
+        body: T,
+    ) -> SfResult<SfResponse<CreateObjectResponse>>
+    where
+        T: Serialize,
+    {
+        self.patch(
+            &self.record_path(&format!("{}/{}", object, id.field), &id.value),
+            body,
+        )
+        .await
+    }
+
+    /// Creates or updates up to 200 records by the value of an external id field in a single
+    /// request. An outcome is returned for each record, in the order of the records.
+    ///
+    /// If the request is rejected as too large, the records are split into smaller batches
+    /// unless `all_or_none` is set, as batches would not be rolled back together
+    pub async fn upsert_objects<T>(
+        &self,
+        object: &str,
+        external_id_field: &str,
+        records: &[T],
+        all_or_none: bool,
+    ) -> SfResult<Vec<UpsertOutcome>>
+    where
+        T: Serialize,
+    {
+        if records.is_empty() || records.len() > MAX_COLLECTION_WRITE {
+            return Err(SfError::InvalidInput(format!(
+                "Expected between 1 and {} records but received {}",
+                MAX_COLLECTION_WRITE,
+                records.len()
+            )));
+        }
+
+        let path = format!("composite/sobjects/{}/{}", object, external_id_field);
+        let mut outcomes = Vec::with_capacity(records.len());
+        let mut pending = VecDeque::from([records]);
+
+        while let Some(batch) = pending.pop_front() {
+            let body = serde_json::json!({
+                "allOrNone": all_or_none,
+                "records": batch
+                    .iter()
+                    .map(|record| SObjectEnvelope::new(object.to_string(), record))
+                    .collect::<Vec<_>>(),
+            });
+
+            match self.patch::<_, Vec<CollectionResult>>(&path, body).await {
+                Ok(response) => {
+                    outcomes.extend(response.into_body()?.into_iter().map(UpsertOutcome::from))
+                }
+                Err(SfError::PayloadTooLarge { .. }) if !all_or_none && batch.len() > 1 => {
+                    tracing::debug!(records = batch.len(), "Splitting batch that was too large");
+
+                    let (first, second) = batch.split_at(batch.len() / 2);
+                    pending.push_front(second);
+                    pending.push_front(first);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(outcomes)
+    }
+
+    pub async fn delete_object(&self, object: &str, id: &str) -> SfResult<SfResponse<()>> {
+        self.delete(&self.record_path(object, id)).await
+    }
+
+    /// Deletes a record, treating a record that does not exist as already deleted. Returns
+    /// whether a record was deleted by this request
+    pub async fn delete_object_idempotent(&self, object: &str, id: &str) -> SfResult<bool> {
+        match self.delete_object(object, id).await {
+            Ok(_) => Ok(true),
+            Err(SfError::ApiFailure(response)) if response.status == StatusCode::NOT_FOUND => {
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Periodically sends a request to keep the session of the token alive. The requests are sent
+    /// like any other, so they are recorded in the API usage and are subject to the usage guard
+    #[cfg(feature = "keep-alive")]
+    pub fn start_keep_alive(&mut self, mut interval: tokio::time::Interval) {
+        let client = self.share();
+        let url = self.url("");
+
+        self.keep_alive = Some(tokio::spawn(async move {
             loop {
                 interval.tick().await;
                 tracing::trace!(?url, "Keep-alive GET request");
 
-                match client.get(&url).bearer_auth(&bearer).send().await {
-                    Ok(response) => match response.status() {
-                        StatusCode::OK => (),
-                        status => {
-                            tracing::warn!(?status, "Keep-alive returned non-OK status code");
-                        }
-                    },
+                let request = client.inner.get(&url).bearer_auth(&client.bearer);
+                match client
+                    .send_with(request, |response| async move { Ok(response.status()) })
+                    .await
+                {
+                    Ok(StatusCode::OK) => (),
+                    Ok(status) => {
+                        tracing::warn!(?status, "Keep-alive returned non-OK status code");
+                    }
                     Err(err) => {
                         tracing::warn!(?err, "Failed to make keep-alive request");
                     }
@@ -285,27 +1266,118 @@ impl SfClient {
     }
 }
 
-#[derive(Debug, Error)]
+// Whether a composite subrequest failed as a reference to an earlier subrequest had no value
+fn unresolved_reference(failure: &SfResponse<Vec<SfApiError>>) -> bool {
+    failure.body.iter().flatten().any(|error| {
+        error.error_code == "INVALID_REFERENCE"
+            || (error.error_code == "PROCESSING_HALTED"
+                && error.message.to_lowercase().contains("invalid reference"))
+    })
+}
+
+/// The top level Salesforce services that are served from an instance
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Service {
+    /// REST API
+    Data,
+    /// Bulk API 1.0
+    Async,
+    /// SOAP Partner API
+    Soap,
+}
+
+impl Service {
+    fn root(&self, version: &str) -> String {
+        // Versions are accepted with or without their prefix, such as `58.0` or `v58.0`
+        let version = version.trim_start_matches('v');
+
+        match self {
+            Self::Data => format!("/services/data/v{}", version),
+            Self::Async => format!("/services/async/{}", version),
+            Self::Soap => format!("/services/Soap/u/{}", version),
+        }
+    }
+}
+
+/// A response and its body. Requests that return no content, such as updates and deletes, have a
+/// body of `Some(())`
+#[derive(Clone, Debug, Error, PartialEq)]
 pub struct SfResponse<T> {
     pub headers: HeaderMap,
     pub status: StatusCode,
     pub body: Option<T>,
 }
 
-impl<T> fmt::Display for SfResponse<T> {
+impl<T> SfResponse<T> {
+    /// Consumes the response, returning the body or [`error::Error::EmptyBody`] if the response
+    /// did not contain one
+    #[allow(clippy::result_large_err)]
+    pub fn into_body(self) -> SfResult<T> {
+        self.body.ok_or(SfError::EmptyBody {
+            status: self.status,
+            path: None,
+        })
+    }
+
+    pub fn body_ref(&self) -> Option<&T> {
+        self.body.as_ref()
+    }
+
+    /// The `Location` header, which refers to the status of requests that were accepted for
+    /// asynchronous processing
+    pub fn location(&self) -> Option<&str> {
+        self.headers
+            .get(LOCATION)
+            .and_then(|value| value.to_str().ok())
+    }
+}
+
+impl<T> fmt::Display for SfResponse<T>
+where
+    T: 'static,
+{
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Received response with {} status", self.status)
+        write!(f, "Received response with {} status", self.status)?;
+
+        // Include the errors of failed requests, as they are otherwise only visible by
+        // inspecting the error
+        let body = self.body.as_ref().map(|body| body as &dyn Any);
+
+        if let Some(errors) = body.and_then(|body| body.downcast_ref::<Vec<SfApiError>>()) {
+            for (i, error) in errors.iter().enumerate() {
+                let separator = if i == 0 { ":" } else { ";" };
+                write!(f, "{} {}: {}", separator, error.error_code, error.message)?;
+            }
+        } else if let Some(error) = body.and_then(|body| body.downcast_ref::<SfLoginError>()) {
+            write!(f, ": {}: {}", error.error, error.error_description)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ApiVersion {
+    pub version: String,
+    pub label: String,
+    pub url: String,
+}
+
+impl ApiVersion {
+    /// The numeric value of the version, for comparing versions
+    pub fn as_f64(&self) -> Option<f64> {
+        self.version.parse().ok()
     }
 }
 
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct SfApiError {
     #[serde(rename = "errorCode")]
     pub error_code: String,
     pub message: String,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct QueryResponse<T> {
     #[serde(rename = "totalSize")]
     pub total_size: i32,
@@ -315,21 +1387,148 @@ pub struct QueryResponse<T> {
     pub records: Vec<QueryRecord<T>>,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct QueryPlanResponse {
+    pub plans: Vec<QueryPlan>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct QueryPlan {
+    pub cardinality: u64,
+    pub fields: Vec<String>,
+    /// Such as `Index`, `TableScan`, or `Sharing`
+    pub leading_operation_type: String,
+    pub notes: Vec<QueryPlanNote>,
+    /// Plans with a relative cost above 1 are not selective
+    pub relative_cost: f64,
+    pub sobject_cardinality: u64,
+    pub sobject_type: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct QueryPlanNote {
+    pub description: String,
+    pub fields: Vec<String>,
+    pub table_enum_or_id: String,
+}
+
+/// A record returned by a query.
+///
+/// Parent fields selected through a relationship, such as `Account.Name`, are returned as a
+/// nested record with its own `attributes`, or as `null` when the relationship is not set. These
+/// are modeled with an optional nested `QueryRecord`, and child relationship subqueries with a
+/// nested [`QueryResponse`]:
+///
+/// ```
+/// use serde::Deserialize;
+/// use sf_client::{QueryRecord, QueryResponse};
+///
+/// #[derive(Deserialize)]
+/// #[serde(rename_all = "PascalCase")]
+/// struct Account {
+///     name: String,
+/// }
+///
+/// #[derive(Deserialize)]
+/// #[serde(rename_all = "PascalCase")]
+/// struct Case {
+///     subject: String,
+/// }
+///
+/// // SELECT Id, Account.Name, (SELECT Subject FROM Cases) FROM Contact
+/// #[derive(Deserialize)]
+/// #[serde(rename_all = "PascalCase")]
+/// struct Contact {
+///     id: String,
+///     account: Option<QueryRecord<Account>>,
+///     cases: Option<QueryResponse<Case>>,
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct QueryRecord<T> {
     pub attributes: QueryRecordAttributes,
     #[serde(flatten)]
     pub object: T,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct QueryRecordAttributes {
     #[serde(rename = "type")]
     pub type_: String,
+    // Aggregate results do not reference a record and are returned without a url
+    #[serde(default)]
     pub url: String,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+impl QueryRecordAttributes {
+    /// The id of the record, taken from the last segment of its url
+    pub fn record_id(&self) -> Option<&str> {
+        self.url
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .filter(|id| !id.is_empty())
+    }
+}
+
+/// A record wrapped with its `attributes`, as used by the sObject Tree and Collections APIs when
+/// sending records and by query results when receiving them.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SObjectEnvelope<T> {
+    pub attributes: SObjectAttributes,
+    #[serde(flatten)]
+    pub record: T,
+}
+
+impl<T> SObjectEnvelope<T> {
+    pub fn new(type_: String, record: T) -> Self {
+        Self {
+            attributes: SObjectAttributes {
+                type_,
+                reference_id: None,
+                url: None,
+            },
+            record,
+        }
+    }
+
+    pub fn reference_id(mut self, reference_id: String) -> Self {
+        self.attributes.reference_id = Some(reference_id);
+        self
+    }
+}
+
+/// A record wrapped with the `attributes.type` that the Collections APIs require of each record
+/// when creating records of several objects in one request
+pub type WithType<T> = SObjectEnvelope<T>;
+
+impl<T> From<QueryRecord<T>> for SObjectEnvelope<T> {
+    fn from(record: QueryRecord<T>) -> Self {
+        Self {
+            attributes: SObjectAttributes {
+                type_: record.attributes.type_,
+                reference_id: None,
+                url: Some(record.attributes.url).filter(|url| !url.is_empty()),
+            },
+            record: record.object,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SObjectAttributes {
+    #[serde(rename = "type")]
+    pub type_: String,
+    #[serde(rename = "referenceId", skip_serializing_if = "Option::is_none")]
+    pub reference_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ObjectDescriptionsResponse {
     pub encoding: String,
     #[serde(rename = "maxBatchSize")]
@@ -337,40 +1536,171 @@ pub struct ObjectDescriptionsResponse {
     pub sobjects: Vec<ObjectDescription>,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ObjectDescriptionResponse {
     #[serde(rename = "objectDescribe")]
     pub object_describe: ObjectDescription,
+    #[serde(rename = "recentItems", default)]
+    pub recent_items: Vec<Value>,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct ObjectDescription {
     pub name: String,
     pub label: String,
+    #[serde(rename = "keyPrefix", default)]
+    pub key_prefix: Option<String>,
+    #[serde(default)]
+    pub custom: bool,
+    #[serde(default)]
+    pub queryable: bool,
+    #[serde(default)]
+    pub createable: bool,
+    #[serde(default)]
+    pub updateable: bool,
+    #[serde(default)]
+    pub deletable: bool,
+    #[serde(default)]
+    pub searchable: bool,
+}
+
+/// Fields and headers that are set on a record when it is created, see
+/// [`SfClient::create_object_with_options`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CreateOptions {
+    pub record_type_id: Option<String>,
+    pub owner_id: Option<String>,
+    pub assignment_rule: Option<AssignmentRule>,
+}
+
+impl CreateOptions {
+    pub fn record_type_id(mut self, record_type_id: String) -> Self {
+        self.record_type_id = Some(record_type_id);
+        self
+    }
+
+    pub fn owner_id(mut self, owner_id: String) -> Self {
+        self.owner_id = Some(owner_id);
+        self
+    }
+
+    pub fn assignment_rule(mut self, assignment_rule: AssignmentRule) -> Self {
+        self.assignment_rule = Some(assignment_rule);
+        self
+    }
+}
+
+/// The assignment rule that is run when a Case or Lead is created
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AssignmentRule {
+    /// Runs the active assignment rule of the object
+    Active,
+    /// Runs the assignment rule with the given id
+    Id(String),
+    /// Skips assignment rules, even if the object has an active rule
+    Disabled,
+}
+
+impl AssignmentRule {
+    #[allow(clippy::result_large_err)]
+    fn header_value(&self) -> SfResult<HeaderValue> {
+        match self {
+            Self::Active => Ok(HeaderValue::from_static("TRUE")),
+            Self::Id(id) => HeaderValue::from_str(id).map_err(|_| {
+                SfError::InvalidInput(format!("{} is not a valid assignment rule id", id))
+            }),
+            Self::Disabled => Ok(HeaderValue::from_static("FALSE")),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct CreateObjectResponse {
     pub id: Option<String>,
     pub errors: Vec<SfApiError>,
     pub success: bool,
 }
 
+/// An error for a single record of an sObject Collections request
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionError {
+    pub status_code: String,
+    pub message: String,
+    #[serde(default)]
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CollectionResult {
+    id: Option<String>,
+    success: bool,
+    #[serde(default)]
+    created: bool,
+    #[serde(default)]
+    errors: Vec<CollectionError>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UpsertOutcome {
+    Created(String),
+    Updated(String),
+    Failed(Vec<CollectionError>),
+}
+
+impl From<CollectionResult> for UpsertOutcome {
+    fn from(result: CollectionResult) -> Self {
+        match (result.success, result.id) {
+            (true, Some(id)) if result.created => Self::Created(id),
+            (true, Some(id)) => Self::Updated(id),
+            _ => Self::Failed(result.errors),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ExternalId {
     pub field: String,
     pub value: String,
 }
 
+// Standard fields that can be used to upsert in place of an external id field
+const STANDARD_EXTERNAL_ID_FIELDS: &[&str] = &["Id"];
+
 impl ExternalId {
-    pub fn new(field: String, value: String) -> Self {
-        Self { field, value }
+    /// Creates an external id, returning [`SfError::InvalidInput`] if the value is empty or the
+    /// field is neither a custom field nor a standard field that can be upserted by
+    #[allow(clippy::result_large_err)]
+    pub fn new(field: String, value: String) -> SfResult<Self> {
+        let valid_field = !field.is_empty()
+            && field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && (field.to_ascii_lowercase().ends_with("__c")
+                || STANDARD_EXTERNAL_ID_FIELDS
+                    .iter()
+                    .any(|standard| standard.eq_ignore_ascii_case(&field)));
+
+        if !valid_field {
+            return Err(SfError::InvalidInput(format!(
+                "{:?} is not a valid external id field",
+                field
+            )));
+        }
+
+        if value.is_empty() {
+            return Err(SfError::InvalidInput(format!(
+                "External id value for {} must not be empty",
+                field
+            )));
+        }
+
+        Ok(Self { field, value })
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use wiremock::{
-        matchers::{method, path},
+        matchers::{body_json, header, method, path, query_param},
         Mock, MockServer, ResponseTemplate,
     };
 
@@ -387,7 +1717,7 @@ mod tests {
 
     use super::*;
 
-    async fn get_client(server: &MockServer) -> SfClient {
+    pub async fn get_client(server: &MockServer) -> SfClient {
         let key = private_key();
         let client_id = "123";
         let aud = AuthorizationServer::Test;
@@ -395,7 +1725,7 @@ mod tests {
 
         let claims = LoginClaims::new(client_id.to_string(), aud, sub.to_string());
 
-        let authenticator = JwtAuthenticator::new(&server.uri(), claims, key);
+        let authenticator = JwtAuthenticator::new(&server.uri(), claims, key).unwrap();
 
         let client = SfClient::new("12345.0".to_string(), authenticator)
             .await
@@ -412,7 +1742,9 @@ mod tests {
             object_describe: ObjectDescription {
                 name: "Lead".to_string(),
                 label: "Lead".to_string(),
+                ..Default::default()
             },
+            recent_items: vec![],
         };
         Mock::given(method("GET"))
             .and(path("/services/data/v12345.0/sobjects/Lead"))
@@ -437,6 +1769,7 @@ mod tests {
             sobjects: vec![ObjectDescription {
                 name: "Lead".to_string(),
                 label: "Lead".to_string(),
+                ..Default::default()
             }],
         };
         Mock::given(method("GET"))
@@ -539,7 +1872,10 @@ mod tests {
 
         Mock::given(method("GET"))
             .and(path("/services/data/v12345.0/sobjects/Lead/123"))
-            .respond_with(ResponseTemplate::new(200).set_body_string(&expected_body))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(expected_body.as_bytes(), "application/json"),
+            )
             .mount(&server)
             .await;
 
@@ -561,4 +1897,1993 @@ mod tests {
             assert_eq!(expected_body, body);
         }
     }
+
+    #[tokio::test]
+    async fn test_query_aggregate_typed() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let query = "SELECT COUNT(Id) total, StageName FROM Opportunity GROUP BY StageName";
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/query/"))
+            .and(query_param("q", query))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "totalSize": 2,
+                "done": true,
+                "records": [
+                    { "attributes": { "type": "AggregateResult" }, "total": 3, "StageName": "Prospecting" },
+                    { "attributes": { "type": "AggregateResult" }, "total": 5, "StageName": "Closed Won" }
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct StageCount {
+            total: i64,
+            #[serde(rename = "StageName")]
+            stage_name: String,
+        }
+
+        let response = client.query::<StageCount>(query).await.unwrap();
+        let body = response.body.unwrap();
+
+        assert_eq!(2, body.total_size);
+        assert_eq!("AggregateResult", body.records[0].attributes.type_);
+        assert_eq!("", body.records[0].attributes.url);
+        assert_eq!(
+            StageCount {
+                total: 5,
+                stage_name: "Closed Won".to_string(),
+            },
+            body.records[1].object
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_aggregate() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let query = "SELECT COUNT(Id), MAX(Amount) largest FROM Opportunity GROUP BY StageName";
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/query/"))
+            .and(query_param("q", query))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "totalSize": 1,
+                "done": true,
+                "records": [
+                    { "attributes": { "type": "AggregateResult" }, "expr0": 3, "largest": 1500.5 }
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let response = client.query_aggregate(query).await.unwrap();
+        let record = &response.body.unwrap().records[0];
+
+        assert_eq!(Some(&serde_json::json!(3)), record.object.get("expr0"));
+        assert_eq!(
+            Some(&serde_json::json!(1500.5)),
+            record.object.get("largest")
+        );
+        assert!(!record.object.contains_key("attributes"));
+    }
+
+    #[tokio::test]
+    async fn test_update_object_no_content() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("PATCH"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/123"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let response = client
+            .update_object("Lead", "123", serde_json::json!({ "Company": "Test" }))
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::NO_CONTENT, response.status);
+        assert_eq!(Some(()), response.body);
+    }
+
+    #[tokio::test]
+    async fn test_delete_object_err() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let expected_response = vec![SfApiError {
+            error_code: "NOT_FOUND".to_string(),
+            message: "The requested resource does not exist".to_string(),
+        }];
+        Mock::given(method("DELETE"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/123"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(&expected_response))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let err = client.delete_object("Lead", "123").await.unwrap_err();
+
+        assert!(matches!(err, Error::ApiFailure(_)));
+
+        if let Error::ApiFailure(err) = err {
+            assert_eq!(StatusCode::NOT_FOUND, err.status);
+            assert_eq!(expected_response, err.body.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_object_exists() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("HEAD"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/123"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        Mock::given(method("HEAD"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/456"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        Mock::given(method("HEAD"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/789"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        assert!(client.object_exists("Lead", "123").await.unwrap());
+        assert!(!client.object_exists("Lead", "456").await.unwrap());
+        assert!(matches!(
+            client.object_exists("Lead", "789").await,
+            Err(Error::ApiFailure(response)) if response.status == StatusCode::UNAUTHORIZED
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_delete_object_idempotent() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("DELETE"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/123"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/456"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!([
+                { "errorCode": "NOT_FOUND", "message": "The requested resource does not exist" }
+            ])))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/789"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!([
+                { "errorCode": "ENTITY_IS_LOCKED", "message": "Locked" }
+            ])))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        assert!(client
+            .delete_object_idempotent("Lead", "123")
+            .await
+            .unwrap());
+        assert!(!client
+            .delete_object_idempotent("Lead", "456")
+            .await
+            .unwrap());
+        assert!(matches!(
+            client.delete_object_idempotent("Lead", "789").await,
+            Err(Error::ApiFailure(_))
+        ));
+    }
+
+    #[test]
+    fn test_record_id() {
+        for (url, id) in [
+            (
+                "/services/data/v58.0/sobjects/Account/001000000000001",
+                Some("001000000000001"),
+            ),
+            (
+                "/services/data/v58.0/sobjects/Account/001000000000001/",
+                Some("001000000000001"),
+            ),
+            ("", None),
+            ("/", None),
+        ] {
+            let attributes = QueryRecordAttributes {
+                type_: "Account".to_string(),
+                url: url.to_string(),
+            };
+            assert_eq!(id, attributes.record_id(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_envelope_serializes_attributes() {
+        #[derive(Debug, Deserialize, PartialEq, Serialize)]
+        struct Account {
+            #[serde(rename = "Name")]
+            name: String,
+        }
+
+        let envelope = SObjectEnvelope::new(
+            "Account".to_string(),
+            Account {
+                name: "Acme".to_string(),
+            },
+        )
+        .reference_id("ref1".to_string());
+
+        assert_eq!(
+            serde_json::json!({
+                "attributes": { "type": "Account", "referenceId": "ref1" },
+                "Name": "Acme"
+            }),
+            serde_json::to_value(&envelope).unwrap()
+        );
+
+        let received: SObjectEnvelope<Account> = serde_json::from_value(serde_json::json!({
+            "attributes": {
+                "type": "Account",
+                "url": "/services/data/v58.0/sobjects/Account/001000000000001AAA"
+            },
+            "Name": "Acme"
+        }))
+        .unwrap();
+
+        assert_eq!("Account", received.attributes.type_);
+        assert_eq!(None, received.attributes.reference_id);
+        assert_eq!(
+            Some("/services/data/v58.0/sobjects/Account/001000000000001AAA"),
+            received.attributes.url.as_deref()
+        );
+        assert_eq!("Acme", received.record.name);
+    }
+
+    #[test]
+    fn test_with_type_mixed_objects() {
+        let records = vec![
+            WithType::new("Account".to_string(), serde_json::json!({ "Name": "Acme" })),
+            WithType::new(
+                "Contact".to_string(),
+                serde_json::json!({ "LastName": "Smith" }),
+            ),
+        ];
+
+        assert_eq!(
+            serde_json::json!([
+                { "attributes": { "type": "Account" }, "Name": "Acme" },
+                { "attributes": { "type": "Contact" }, "LastName": "Smith" }
+            ]),
+            serde_json::to_value(&records).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upsert_objects() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("PATCH"))
+            .and(path(
+                "/services/data/v12345.0/composite/sobjects/Account/External_Id__c",
+            ))
+            .and(body_json(serde_json::json!({
+                "allOrNone": false,
+                "records": [
+                    { "attributes": { "type": "Account" }, "External_Id__c": "a", "Name": "Acme" },
+                    { "attributes": { "type": "Account" }, "External_Id__c": "b", "Name": "Globex" },
+                    { "attributes": { "type": "Account" }, "External_Id__c": "c", "Name": "" }
+                ]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "id": "001000000000001", "success": true, "errors": [], "created": true },
+                { "id": "001000000000002", "success": true, "errors": [], "created": false },
+                {
+                    "success": false,
+                    "errors": [{
+                        "statusCode": "REQUIRED_FIELD_MISSING",
+                        "message": "Required fields are missing: [Name]",
+                        "fields": ["Name"]
+                    }]
+                }
+            ])))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let records = ["Acme", "Globex", ""]
+            .iter()
+            .zip(["a", "b", "c"])
+            .map(|(name, id)| serde_json::json!({ "External_Id__c": id, "Name": name }))
+            .collect::<Vec<_>>();
+
+        let outcomes = client
+            .upsert_objects("Account", "External_Id__c", &records, false)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                UpsertOutcome::Created("001000000000001".to_string()),
+                UpsertOutcome::Updated("001000000000002".to_string()),
+                UpsertOutcome::Failed(vec![CollectionError {
+                    status_code: "REQUIRED_FIELD_MISSING".to_string(),
+                    message: "Required fields are missing: [Name]".to_string(),
+                    fields: vec!["Name".to_string()],
+                }]),
+            ],
+            outcomes
+        );
+
+        let too_many = vec![serde_json::json!({}); 201];
+        assert!(matches!(
+            client
+                .upsert_objects("Account", "External_Id__c", &too_many, false)
+                .await,
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_upsert_objects_splits_large_batches() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        // Accepts at most two records per request
+        Mock::given(method("PATCH"))
+            .and(path(
+                "/services/data/v12345.0/composite/sobjects/Account/External_Id__c",
+            ))
+            .respond_with(|request: &wiremock::Request| {
+                let body: Value = serde_json::from_slice(&request.body).unwrap();
+                let records = body["records"].as_array().unwrap();
+
+                if records.len() > 2 {
+                    ResponseTemplate::new(413)
+                } else {
+                    ResponseTemplate::new(200).set_body_json(
+                        records
+                            .iter()
+                            .map(|record| {
+                                serde_json::json!({
+                                    "id": record["External_Id__c"],
+                                    "success": true,
+                                    "errors": [],
+                                    "created": true
+                                })
+                            })
+                            .collect::<Vec<_>>(),
+                    )
+                }
+            })
+            .expect(6)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let records = (0..5)
+            .map(|id| serde_json::json!({ "External_Id__c": id.to_string() }))
+            .collect::<Vec<_>>();
+
+        let outcomes = client
+            .upsert_objects("Account", "External_Id__c", &records, false)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            (0..5)
+                .map(|id| UpsertOutcome::Created(id.to_string()))
+                .collect::<Vec<_>>(),
+            outcomes
+        );
+        assert!(matches!(
+            client
+                .upsert_objects("Account", "External_Id__c", &records, true)
+                .await,
+            Err(Error::PayloadTooLarge { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_objects() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/composite/sobjects/Lead"))
+            .and(body_json(serde_json::json!({
+                "ids": ["123", "456"],
+                "fields": ["Id", "Company"]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "attributes": { "type": "Lead" }, "Id": "123", "Company": "Acme" },
+                null
+            ])))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename_all = "PascalCase")]
+        struct Lead {
+            id: String,
+            company: String,
+        }
+
+        let response = client
+            .get_objects::<Lead>("Lead", &["123", "456"], &["Id", "Company"])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                Some(Lead {
+                    id: "123".to_string(),
+                    company: "Acme".to_string(),
+                }),
+                None
+            ],
+            response.body.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_objects_enforces_limits() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let client = get_client(&server).await;
+
+        let ids = vec!["123"; MAX_COLLECTION_RETRIEVE + 1];
+        let err = client
+            .get_objects::<Value>("Lead", &ids, &["Id"])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput(_)));
+
+        let err = client
+            .get_objects::<Value>("Lead", &[], &["Id"])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput(_)));
+
+        let err = client
+            .get_objects::<Value>("Lead", &["123"], &[])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput(_)));
+    }
+
+    #[tokio::test]
+    async fn test_describe_object_basic() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Lead"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "objectDescribe": { "name": "Lead", "label": "Lead", "custom": false },
+                "recentItems": [
+                    { "attributes": { "type": "Lead", "url": "/services/data/v12345.0/sobjects/Lead/123" }, "Id": "123" }
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let body = client
+            .describe_object_basic("Lead")
+            .await
+            .unwrap()
+            .body
+            .unwrap();
+
+        assert_eq!("Lead", body.object_describe.name);
+        assert_eq!(1, body.recent_items.len());
+    }
+
+    #[tokio::test]
+    async fn test_with_locale() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/describe"))
+            .and(header("Accept-Language", "de"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "name": "Lead",
+                "label": "Interessent"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut client = get_client(&server).await;
+        client.with_locale("de").unwrap();
+
+        let body = client
+            .describe_object_full("Lead")
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+        assert_eq!("Interessent", body.label);
+
+        assert!(client.with_locale("de\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_correlation_id() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/limits"))
+            .and(header("X-Correlation-Id", "request-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut client = get_client(&server).await;
+        client
+            .with_correlation_id_header("X-Correlation-Id".to_string())
+            .unwrap();
+
+        client
+            .correlation_id("request-1")
+            .unwrap()
+            .limits()
+            .await
+            .unwrap();
+
+        // Requests of the original client are not tagged
+        client.limits().await.unwrap_err();
+
+        assert!(client
+            .with_correlation_id_header("X Correlation".to_string())
+            .is_err());
+        assert!(client.correlation_id("request\n1").is_err());
+    }
+
+    #[cfg(feature = "keep-alive")]
+    #[tokio::test]
+    async fn test_keep_alive_records_usage() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Sforce-Limit-Info", "api-usage=42/100")
+                    .set_body_json(serde_json::json!({})),
+            )
+            .mount(&server)
+            .await;
+
+        let mut client = get_client(&server).await;
+        client.start_keep_alive(tokio::time::interval(std::time::Duration::from_millis(10)));
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while client.api_usage().is_none() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(42, client.api_usage().unwrap().used);
+    }
+
+    #[tokio::test]
+    async fn test_correlation_id_shares_state() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/limits"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Sforce-Limit-Info", "api-usage=91/100")
+                    .set_body_json(serde_json::json!({
+                        "DailyApiRequests": { "Max": 100, "Remaining": 9 }
+                    })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut client = get_client(&server).await;
+        client.with_usage_guard(90).unwrap();
+
+        let request_client = client.correlation_id("request-1").unwrap();
+        request_client.limit("DailyApiRequests").await.unwrap();
+
+        // Usage recorded by the request client counts towards the guard of the parent, and the
+        // limits it fetched are served from the shared cache
+        assert_eq!(91, client.api_usage().unwrap().used);
+        assert!(client.limit("DailyApiRequests").await.unwrap().is_some());
+        assert!(matches!(
+            client.limits().await,
+            Err(SfError::UsageBudgetExceeded { used: 91, max: 100 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_describe_object_full() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/describe"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "name": "Lead",
+                "label": "Lead",
+                "labelPlural": "Leads",
+                "keyPrefix": "00Q",
+                "queryable": true,
+                "updateable": true,
+                "fields": [
+                    {
+                        "name": "Id",
+                        "label": "Lead ID",
+                        "type": "id",
+                        "length": 18,
+                        "updateable": false,
+                        "picklistValues": []
+                    },
+                    {
+                        "name": "LeadSource",
+                        "label": "Lead Source",
+                        "type": "picklist",
+                        "updateable": true,
+                        "picklistValues": [
+                            { "active": true, "defaultValue": false, "label": "Web", "value": "Web", "validFor": null }
+                        ]
+                    }
+                ],
+                "someUnmodeledProperty": { "nested": true }
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let body = client
+            .describe_object_full("Lead")
+            .await
+            .unwrap()
+            .body
+            .unwrap();
+
+        assert_eq!("Leads", body.label_plural);
+        assert_eq!(Some("00Q"), body.key_prefix.as_deref());
+        assert_eq!(2, body.fields.len());
+        assert_eq!("id", body.fields[0].type_);
+        assert!(!body.fields[0].updateable);
+        assert_eq!("Web", body.fields[1].picklist_values[0].value);
+    }
+
+    #[tokio::test]
+    async fn test_record_urls() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let client = get_client(&server).await;
+
+        assert_eq!(server.uri(), client.instance_url());
+        assert_eq!(
+            format!("{}/00Q000000000001", server.uri()),
+            client.record_url("00Q000000000001")
+        );
+        assert_eq!(
+            format!("{}/lightning/r/Lead/00Q000000000001/view", server.uri()),
+            client.lightning_record_url("Lead", "00Q000000000001")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_is_sandbox() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let mut client = get_client(&server).await;
+
+        for (url, sandbox) in [
+            ("https://acme--dev.sandbox.my.salesforce.com", true),
+            ("https://acme--dev.my.salesforce.com", true),
+            ("https://cs42.salesforce.com", true),
+            ("https://acme.my.salesforce.com", false),
+            ("https://na139.salesforce.com", false),
+            ("https://cspace.my.salesforce.com", false),
+            ("not a url", false),
+        ] {
+            client.instance_url = url.to_string();
+            assert_eq!(sandbox, client.is_sandbox(), "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_stream() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/query/"))
+            .and(query_param(
+                "q",
+                "SELECT Id FROM Lead WHERE SystemModstamp > 2023-01-02T03:04:05Z ORDER BY SystemModstamp",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "totalSize": 2,
+                "done": false,
+                "nextRecordsUrl": "/services/data/v12345.0/query/01g-1",
+                "records": [
+                    { "attributes": { "type": "Lead", "url": "/services/data/v12345.0/sobjects/Lead/1" }, "Id": "1" }
+                ]
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/query/01g-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "totalSize": 2,
+                "done": true,
+                "records": [
+                    { "attributes": { "type": "Lead", "url": "/services/data/v12345.0/sobjects/Lead/2" }, "Id": "2" }
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let since = DateTime::parse_from_rfc3339("2023-01-02T03:04:05.678Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let records: Vec<Value> = client
+            .changed_since("Lead", &["Id"], since)
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                serde_json::json!({ "Id": "1" }),
+                serde_json::json!({ "Id": "2" })
+            ],
+            records
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_empty() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/query/"))
+            .and(query_param("q", "SELECT Id FROM Lead WHERE Id = null"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "totalSize": 0,
+                "done": true,
+                "records": []
+            })))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        let response = client
+            .query::<Value>("SELECT Id FROM Lead WHERE Id = null")
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+        assert_eq!(0, response.total_size);
+        assert!(response.done);
+        assert!(response.records.is_empty());
+        assert_eq!(None, response.next_records_url);
+
+        let records: Vec<Value> = client
+            .query_stream("SELECT Id FROM Lead WHERE Id = null")
+            .try_collect()
+            .await
+            .unwrap();
+        assert!(records.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_with_access_token() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/123"))
+            .and(header("Authorization", "Bearer tenant_token"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "Id": "123" })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/123"))
+            .and(header("Authorization", "Bearer access_token"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "Id": "123" })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        client
+            .with_access_token("tenant_token".to_string())
+            .get_object::<Value>("Lead", "123")
+            .await
+            .unwrap();
+        client.get_object::<Value>("Lead", "123").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_query_relationships() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename_all = "PascalCase")]
+        struct Account {
+            name: String,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename_all = "PascalCase")]
+        struct Case {
+            subject: String,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename_all = "PascalCase")]
+        struct Contact {
+            id: String,
+            account: Option<QueryRecord<Account>>,
+            cases: Option<QueryResponse<Case>>,
+        }
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/query/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "totalSize": 2,
+                "done": true,
+                "records": [
+                    {
+                        "attributes": { "type": "Contact", "url": "/services/data/v12345.0/sobjects/Contact/1" },
+                        "Id": "1",
+                        "Account": {
+                            "attributes": { "type": "Account", "url": "/services/data/v12345.0/sobjects/Account/2" },
+                            "Name": "Acme"
+                        },
+                        "Cases": {
+                            "totalSize": 1,
+                            "done": true,
+                            "records": [{
+                                "attributes": { "type": "Case", "url": "/services/data/v12345.0/sobjects/Case/3" },
+                                "Subject": "Broken widget"
+                            }]
+                        }
+                    },
+                    {
+                        "attributes": { "type": "Contact", "url": "/services/data/v12345.0/sobjects/Contact/4" },
+                        "Id": "4",
+                        "Account": null,
+                        "Cases": null
+                    }
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let records = client
+            .query::<Contact>("SELECT Id, Account.Name, (SELECT Subject FROM Cases) FROM Contact")
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap()
+            .records;
+
+        let contact = &records[0].object;
+        assert_eq!("1", contact.id);
+        assert_eq!("Acme", contact.account.as_ref().unwrap().object.name);
+        assert_eq!(
+            "Broken widget",
+            contact.cases.as_ref().unwrap().records[0].object.subject
+        );
+
+        assert_eq!(None, records[1].object.account);
+        assert_eq!(None, records[1].object.cases);
+    }
+
+    #[tokio::test]
+    async fn test_query_batch_size() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/query/"))
+            .and(header("Sforce-Query-Options", "batchSize=2000"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "totalSize": 0,
+                "done": true,
+                "records": []
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut client = get_client(&server).await;
+
+        assert!(matches!(
+            client.with_query_batch_size(100),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            client.with_query_batch_size(2001),
+            Err(Error::InvalidInput(_))
+        ));
+
+        client.with_query_batch_size(2000).unwrap();
+        client.query::<Value>("SELECT Id FROM Lead").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_raw() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/custom/resource"))
+            .and(header("Authorization", "Bearer access_token"))
+            .and(body_json(serde_json::json!({ "key": "value" })))
+            .respond_with(ResponseTemplate::new(418).set_body_string("teapot"))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let response = client
+            .send_raw(
+                Method::POST,
+                "custom/resource",
+                Some(serde_json::json!({ "key": "value" })),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::IM_A_TEAPOT, response.status());
+        assert_eq!("teapot", response.text().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_build_query_url() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let client = get_client(&server).await;
+
+        assert_eq!(
+            format!(
+                "{}/services/data/v12345.0/query/?q=SELECT%20Id%20FROM%20Lead%20WHERE%20Email%20%3D%20%27a%2Bb%40c.com%27",
+                server.uri()
+            ),
+            client.build_query_url("SELECT Id FROM Lead WHERE Email = 'a+b@c.com'")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_explain() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/query/"))
+            .and(query_param("explain", "SELECT Id FROM Lead WHERE Email = 'a@b.com'"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "plans": [
+                    {
+                        "cardinality": 1,
+                        "fields": ["Email"],
+                        "leadingOperationType": "Index",
+                        "notes": [],
+                        "relativeCost": 0.0003,
+                        "sobjectCardinality": 12000,
+                        "sobjectType": "Lead"
+                    },
+                    {
+                        "cardinality": 1,
+                        "fields": [],
+                        "leadingOperationType": "TableScan",
+                        "notes": [{
+                            "description": "Not considering filter for optimization because unindexed",
+                            "fields": ["IsDeleted"],
+                            "tableEnumOrId": "Lead"
+                        }],
+                        "relativeCost": 2.8,
+                        "sobjectCardinality": 12000,
+                        "sobjectType": "Lead"
+                    }
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let plans = client
+            .query_explain("SELECT Id FROM Lead WHERE Email = 'a@b.com'")
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap()
+            .plans;
+
+        assert_eq!(2, plans.len());
+        assert_eq!("Index", plans[0].leading_operation_type);
+        assert_eq!(vec!["Email".to_string()], plans[0].fields);
+        assert_eq!("Lead", plans[1].notes[0].table_enum_or_id);
+    }
+
+    #[tokio::test]
+    async fn test_query_post() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let query = "SELECT Id FROM Lead WHERE Id IN ('123', '456')";
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/composite"))
+            .and(body_json(serde_json::json!({
+                "allOrNone": false,
+                "collateSubrequests": false,
+                "compositeRequest": [{
+                    "method": "GET",
+                    "url": format!("/services/data/v12345.0/query/?q={}", urlencoding::encode(query)),
+                    "referenceId": "query"
+                }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "compositeResponse": [{
+                    "body": {
+                        "totalSize": 1,
+                        "done": true,
+                        "records": [
+                            { "attributes": { "type": "Lead", "url": "/services/data/v12345.0/sobjects/Lead/123" }, "Id": "123" }
+                        ]
+                    },
+                    "httpHeaders": {},
+                    "httpStatusCode": 200,
+                    "referenceId": "query"
+                }]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let response = client.query_post::<Value>(query).await.unwrap();
+        let body = response.body.unwrap();
+
+        assert_eq!(StatusCode::OK, response.status);
+        assert_eq!(1, body.total_size);
+        assert_eq!(
+            Some(&serde_json::json!("123")),
+            body.records[0].object.get("Id")
+        );
+    }
+
+    #[test]
+    fn test_into_body() {
+        let response = SfResponse {
+            headers: HeaderMap::new(),
+            status: StatusCode::OK,
+            body: Some("body".to_string()),
+        };
+        assert_eq!(Some(&"body".to_string()), response.body_ref());
+        assert_eq!("body", response.into_body().unwrap());
+
+        let response = SfResponse::<String> {
+            headers: HeaderMap::new(),
+            status: StatusCode::NO_CONTENT,
+            body: None,
+        };
+        assert_eq!(None, response.body_ref());
+        assert!(matches!(
+            response.into_body(),
+            Err(Error::EmptyBody {
+                status: StatusCode::NO_CONTENT,
+                path: None,
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_object_empty_body() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/123"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let err = client.get_object::<Value>("Lead", "123").await.unwrap_err();
+
+        match err {
+            Error::EmptyBody { status, path } => {
+                assert_eq!(StatusCode::OK, status);
+                assert_eq!(
+                    Some("/services/data/v12345.0/sobjects/Lead/123"),
+                    path.as_deref()
+                );
+            }
+            err => panic!("Unexpected error {:?}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upsert_object_no_content() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("PATCH"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/Ext__c/123"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let response = client
+            .upsert_object(
+                "Lead",
+                &ExternalId::new("Ext__c".to_string(), "123".to_string()).unwrap(),
+                serde_json::json!({ "Company": "Test" }),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::NO_CONTENT, response.status);
+        assert!(response.body.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_encodes_path_segments() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("PATCH"))
+            .and(path(
+                "/services/data/v12345.0/sobjects/Lead/Ext__c/ACME%2FWest%20%231",
+            ))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/a%3Fb%2Fc"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/a%3Fb%2Fc"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        client
+            .upsert_object(
+                "Lead",
+                &ExternalId::new("Ext__c".to_string(), "ACME/West #1".to_string()).unwrap(),
+                serde_json::json!({ "Company": "Test" }),
+            )
+            .await
+            .unwrap();
+        client.get_object::<Value>("Lead", "a?b/c").await.unwrap();
+        client.delete_object("Lead", "a?b/c").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_error_without_body() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/123"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let err = client.get_object::<Value>("Lead", "123").await.unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::ApiFailure(SfResponse {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                body: None,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_describe_global_filtered() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "encoding": "UTF-8",
+                "sobjects": [
+                    { "name": "Lead", "label": "Lead", "keyPrefix": "00Q", "custom": false, "queryable": true },
+                    { "name": "Widget__c", "label": "Widget", "keyPrefix": "a01", "custom": true, "queryable": true },
+                    { "name": "Widget__Share", "label": "Widget Share", "keyPrefix": null, "custom": true, "queryable": false }
+                ],
+                "maxBatchSize": 200,
+                "unknownProperty": { "ignored": [1, 2] }
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let body = client
+            .describe_global_filtered(|object| object.custom && object.queryable)
+            .await
+            .unwrap()
+            .into_body()
+            .unwrap();
+
+        assert_eq!(1, body.sobjects.len());
+        assert_eq!("Widget__c", body.sobjects[0].name);
+        assert_eq!(Some("a01"), body.sobjects[0].key_prefix.as_deref());
+        assert_eq!(200, body.max_batch_size);
+    }
+
+    #[tokio::test]
+    async fn test_service_urls() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let client = get_client(&server).await;
+
+        assert_eq!(
+            format!("{}/services/data/v12345.0/sobjects/Lead", server.uri()),
+            client.service_url(Service::Data, "sobjects/Lead")
+        );
+        assert_eq!(
+            "/services/async/12345.0/job",
+            client.service_path(Service::Async, "job")
+        );
+        assert_eq!(
+            "/services/Soap/u/12345.0/",
+            client.service_path(Service::Soap, "")
+        );
+        assert_eq!("/services/data/v12345.0/query/", client.data_path("query/"));
+    }
+
+    #[tokio::test]
+    async fn test_service_urls_normalized() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let mut client = get_client(&server).await;
+        client.version = "v58.0".to_string();
+
+        for instance_url in [
+            "https://acme.my.salesforce.com",
+            "https://acme.my.salesforce.com/",
+        ] {
+            client.instance_url = instance_url.to_string();
+
+            for path in ["sobjects/Lead", "/sobjects/Lead"] {
+                assert_eq!(
+                    "https://acme.my.salesforce.com/services/data/v58.0/sobjects/Lead",
+                    client.url(path)
+                );
+            }
+            assert_eq!(
+                "https://acme.my.salesforce.com/00Q000000000001",
+                client.record_url("00Q000000000001")
+            );
+            assert_eq!(
+                "https://acme.my.salesforce.com/services/data/v58.0/sobjects/Lead/a%2Fb",
+                client.url(&client.record_path("Lead", "a/b"))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_object_updateable() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/describe"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "name": "Lead",
+                "fields": [
+                    { "name": "Id", "updateable": false },
+                    { "name": "Company", "updateable": true }
+                ]
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("PATCH"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/123"))
+            .and(body_json(serde_json::json!({ "Company": "Acme" })))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        client
+            .update_object_updateable(
+                "Lead",
+                "123",
+                serde_json::json!({
+                    "attributes": { "type": "Lead" },
+                    "Id": "123",
+                    "Company": "Acme"
+                }),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_client_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SfClient>();
+    }
+
+    #[tokio::test]
+    async fn test_client_shared_across_tasks() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/123"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "Id": "123" })),
+            )
+            .expect(4)
+            .mount(&server)
+            .await;
+
+        let client = std::sync::Arc::new(get_client(&server).await);
+
+        let tasks = (0..4)
+            .map(|_| {
+                let client = client.clone();
+                tokio::spawn(async move { client.get_object::<Value>("Lead", "123").await })
+            })
+            .collect::<Vec<_>>();
+
+        for task in tasks {
+            assert!(task.await.unwrap().is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_composite_accepted() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/composite"))
+            .and(header("Prefer", "respond-async"))
+            .respond_with(ResponseTemplate::new(202).insert_header("Location", "/status/123"))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Prefer", "respond-async".parse().unwrap());
+
+        let response = client
+            .composite_with_headers(&CompositeRequest::new(vec![]), headers)
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::ACCEPTED, response.status);
+        assert_eq!(Some("/status/123"), response.location());
+        assert!(response.body.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_composite_all_or_none_rollback() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/composite"))
+            .and(body_json(serde_json::json!({
+                "allOrNone": true,
+                "collateSubrequests": false,
+                "compositeRequest": [
+                    {
+                        "method": "POST",
+                        "url": "/services/data/v12345.0/sobjects/Account",
+                        "referenceId": "account",
+                        "body": { "Name": "Acme" }
+                    },
+                    {
+                        "method": "POST",
+                        "url": "/services/data/v12345.0/sobjects/Contact",
+                        "referenceId": "contact",
+                        "body": { "AccountId": "@{account.id}" }
+                    }
+                ]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "compositeResponse": [
+                    {
+                        "body": [{
+                            "errorCode": "PROCESSING_HALTED",
+                            "message": "The transaction was rolled back since another operation in the same transaction failed."
+                        }],
+                        "httpHeaders": {},
+                        "httpStatusCode": 400,
+                        "referenceId": "account"
+                    },
+                    {
+                        "body": [{
+                            "errorCode": "REQUIRED_FIELD_MISSING",
+                            "message": "Required fields are missing: [LastName]"
+                        }],
+                        "httpHeaders": {},
+                        "httpStatusCode": 400,
+                        "referenceId": "contact"
+                    }
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        let request = CompositeRequest::new(vec![
+            CompositeSubrequest::new(
+                Method::POST,
+                client.data_path("sobjects/Account"),
+                "account".to_string(),
+            )
+            .body(serde_json::json!({ "Name": "Acme" })),
+            CompositeSubrequest::new(
+                Method::POST,
+                client.data_path("sobjects/Contact"),
+                "contact".to_string(),
+            )
+            .body(serde_json::json!({ "AccountId": "@{account.id}" })),
+        ])
+        .all_or_none(true);
+
+        let mut response = client
+            .composite(&request)
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        let account = response
+            .take("account")
+            .unwrap()
+            .into_response::<CreateObjectResponse>()
+            .unwrap_err();
+        match account {
+            Error::ApiFailure(response) => {
+                assert_eq!("PROCESSING_HALTED", response.body.unwrap()[0].error_code)
+            }
+            err => panic!("Unexpected error {:?}", err),
+        }
+
+        assert!(response
+            .take("contact")
+            .unwrap()
+            .into_response::<CreateObjectResponse>()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resources() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "sobjects": "/services/data/v12345.0/sobjects",
+                "query": "/services/data/v12345.0/query",
+                "graphql": "/services/data/v12345.0/graphql"
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let resources = client
+            .resources()
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        assert_eq!(3, resources.len());
+        assert_eq!(
+            Some("/services/data/v12345.0/graphql"),
+            resources.get("graphql").map(String::as_str)
+        );
+        assert!(!resources.contains_key("jobs"));
+    }
+
+    #[tokio::test]
+    async fn test_latest_version() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "version": "9.0", "label": "Winter '07", "url": "/services/data/v9.0" },
+                { "version": "58.0", "label": "Summer '23", "url": "/services/data/v58.0" },
+                { "version": "57.0", "label": "Spring '23", "url": "/services/data/v57.0" }
+            ])))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        let versions = client.versions().await.unwrap().into_body().unwrap();
+        assert_eq!(3, versions.len());
+
+        let latest = client.latest_version().await.unwrap().unwrap();
+        assert_eq!("58.0", latest.version);
+        assert_eq!("/services/data/v58.0", latest.url);
+    }
+
+    #[tokio::test]
+    async fn test_update_and_return() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/composite"))
+            .and(body_json(serde_json::json!({
+                "allOrNone": true,
+                "collateSubrequests": false,
+                "compositeRequest": [
+                    {
+                        "method": "PATCH",
+                        "url": "/services/data/v12345.0/sobjects/Lead/123",
+                        "referenceId": "update",
+                        "body": { "Company": "Acme" }
+                    },
+                    {
+                        "method": "GET",
+                        "url": "/services/data/v12345.0/sobjects/Lead/123",
+                        "referenceId": "fetch"
+                    }
+                ]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "compositeResponse": [
+                    {
+                        "body": null,
+                        "httpHeaders": {},
+                        "httpStatusCode": 204,
+                        "referenceId": "update"
+                    },
+                    {
+                        "body": { "Id": "123", "Company": "Acme" },
+                        "httpHeaders": {},
+                        "httpStatusCode": 200,
+                        "referenceId": "fetch"
+                    }
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let record = client
+            .update_and_return::<_, Value>("Lead", "123", serde_json::json!({ "Company": "Acme" }))
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        assert_eq!(
+            serde_json::json!({ "Id": "123", "Company": "Acme" }),
+            record
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_and_return_update_failure() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/composite"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "compositeResponse": [
+                    {
+                        "body": [{ "errorCode": "INVALID_FIELD", "message": "No such column" }],
+                        "httpHeaders": {},
+                        "httpStatusCode": 400,
+                        "referenceId": "update"
+                    },
+                    {
+                        "body": [{ "errorCode": "PROCESSING_HALTED", "message": "Halted" }],
+                        "httpHeaders": {},
+                        "httpStatusCode": 400,
+                        "referenceId": "fetch"
+                    }
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let err = client
+            .update_and_return::<_, Value>("Lead", "123", serde_json::json!({ "Bad": "Acme" }))
+            .await
+            .unwrap_err();
+
+        match err {
+            Error::ApiFailure(response) => {
+                assert_eq!("INVALID_FIELD", response.body.unwrap()[0].error_code)
+            }
+            err => panic!("Unexpected error {:?}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_object_if() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let guard = |size: u32| {
+            serde_json::json!({
+                "body": {
+                    "totalSize": size,
+                    "done": true,
+                    "records": (0..size).map(|_| serde_json::json!({
+                        "attributes": { "type": "Product2", "url": "/services/data/v12345.0/sobjects/Product2/123" },
+                        "Id": "123"
+                    })).collect::<Vec<_>>()
+                },
+                "httpHeaders": {},
+                "httpStatusCode": 200,
+                "referenceId": "guard"
+            })
+        };
+
+        let query = "SELECT Id FROM Product2 WHERE Id = '123' AND Quantity__c = 5";
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/composite"))
+            .and(body_json(serde_json::json!({
+                "allOrNone": true,
+                "collateSubrequests": false,
+                "compositeRequest": [
+                    {
+                        "method": "GET",
+                        "url": format!("/services/data/v12345.0/query/?q={}", urlencoding::encode(query)),
+                        "referenceId": "guard"
+                    },
+                    {
+                        "method": "PATCH",
+                        "url": "/services/data/v12345.0/sobjects/Product2/@{guard.records[0].Id}",
+                        "referenceId": "update",
+                        "body": { "Quantity__c": 4 }
+                    }
+                ]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "compositeResponse": [
+                    guard(1),
+                    {
+                        "body": null,
+                        "httpHeaders": {},
+                        "httpStatusCode": 204,
+                        "referenceId": "update"
+                    }
+                ]
+            })))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        // A guard that matches nothing rolls back the whole request
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/composite"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "compositeResponse": [
+                    {
+                        "body": [{
+                            "errorCode": "PROCESSING_HALTED",
+                            "message": "The transaction was rolled back since another operation in the same transaction failed."
+                        }],
+                        "httpHeaders": {},
+                        "httpStatusCode": 400,
+                        "referenceId": "guard"
+                    },
+                    {
+                        "body": [{
+                            "errorCode": "PROCESSING_HALTED",
+                            "message": "Invalid reference specified. No value for guard.records[0].Id found in guard. Provided referenceId ensure you use an existing referenceId."
+                        }],
+                        "httpHeaders": {},
+                        "httpStatusCode": 400,
+                        "referenceId": "update"
+                    }
+                ]
+            })))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/composite"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "compositeResponse": [
+                    {
+                        "body": [{
+                            "errorCode": "PROCESSING_HALTED",
+                            "message": "The transaction was rolled back since another operation in the same transaction failed."
+                        }],
+                        "httpHeaders": {},
+                        "httpStatusCode": 400,
+                        "referenceId": "guard"
+                    },
+                    {
+                        "body": [{
+                            "errorCode": "FIELD_CUSTOM_VALIDATION_EXCEPTION",
+                            "message": "Quantity can not decrease"
+                        }],
+                        "httpHeaders": {},
+                        "httpStatusCode": 400,
+                        "referenceId": "update"
+                    }
+                ]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let body = serde_json::json!({ "Quantity__c": 4 });
+
+        assert!(client
+            .update_object_if(
+                "Product2",
+                "123",
+                "Quantity__c",
+                &serde_json::json!(5),
+                &body
+            )
+            .await
+            .unwrap());
+        assert!(!client
+            .update_object_if(
+                "Product2",
+                "123",
+                "Quantity__c",
+                &serde_json::json!(5),
+                &body
+            )
+            .await
+            .unwrap());
+
+        // Other failures of the update are still errors
+        match client
+            .update_object_if(
+                "Product2",
+                "123",
+                "Quantity__c",
+                &serde_json::json!(5),
+                &body,
+            )
+            .await
+            .unwrap_err()
+        {
+            Error::ApiFailure(response) => assert_eq!(
+                "FIELD_CUSTOM_VALIDATION_EXCEPTION",
+                response.body.unwrap()[0].error_code
+            ),
+            err => panic!("Unexpected error {:?}", err),
+        }
+
+        // Names are checked before they are placed into the guard query
+        for (object, field) in [
+            ("Product2", "Quantity__c = 5 OR Id"),
+            ("Product2 WHERE Id != null", "Quantity__c"),
+        ] {
+            assert!(matches!(
+                client
+                    .update_object_if(object, "123", field, &serde_json::json!(5), &body)
+                    .await,
+                Err(Error::InvalidInput(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_object_strict() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/sobjects/Lead"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({
+                "id": null,
+                "success": false,
+                "errors": [{ "errorCode": "DUPLICATES_DETECTED", "message": "Duplicate" }]
+            })))
+            .mount(&server)
+            .await;
+
+        let mut client = get_client(&server).await;
+
+        let response = client
+            .create_object("Lead", serde_json::json!({}))
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+        assert!(!response.success);
+
+        client.with_strict_create(true);
+
+        match client
+            .create_object("Lead", serde_json::json!({}))
+            .await
+            .unwrap_err()
+        {
+            Error::ApiFailure(response) => {
+                assert_eq!(StatusCode::CREATED, response.status);
+                assert_eq!("DUPLICATES_DETECTED", response.body.unwrap()[0].error_code);
+            }
+            err => panic!("Unexpected error {:?}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_object_with_options() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/sobjects/Lead"))
+            .and(header("Sforce-Auto-Assign", "FALSE"))
+            .and(body_json(serde_json::json!({
+                "Company": "Acme",
+                "RecordTypeId": "012000000000001",
+                "OwnerId": "005000000000001"
+            })))
+            .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({
+                "id": "00Q000000000001",
+                "success": true,
+                "errors": []
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let options = CreateOptions::default()
+            .record_type_id("012000000000001".to_string())
+            .owner_id("005000000000001".to_string())
+            .assignment_rule(AssignmentRule::Disabled);
+
+        let response = client
+            .create_object_with_options(
+                "Lead",
+                serde_json::json!({ "Company": "Acme", "OwnerId": "005000000000002" }),
+                &options,
+            )
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        assert_eq!(Some("00Q000000000001".to_string()), response.id);
+    }
+
+    #[test]
+    fn test_response_display() {
+        let response = SfResponse {
+            headers: HeaderMap::new(),
+            status: StatusCode::BAD_REQUEST,
+            body: Some(vec![
+                SfApiError {
+                    error_code: "INVALID_FIELD".to_string(),
+                    message: "No such column 'Foo'".to_string(),
+                },
+                SfApiError {
+                    error_code: "MALFORMED_QUERY".to_string(),
+                    message: "Unexpected token".to_string(),
+                },
+            ]),
+        };
+
+        assert_eq!(
+            "API request failed Received response with 400 Bad Request status: INVALID_FIELD: No such column 'Foo'; MALFORMED_QUERY: Unexpected token",
+            Error::ApiFailure(response).to_string()
+        );
+
+        let response = SfResponse::<Vec<SfApiError>> {
+            headers: HeaderMap::new(),
+            status: StatusCode::NOT_FOUND,
+            body: None,
+        };
+
+        assert_eq!(
+            "Received response with 404 Not Found status",
+            response.to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_debug_redacts_bearer() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        let client = get_client(&server).await;
+        let debug = format!("{:?}", client);
+
+        assert!(!debug.contains("access_token"));
+        assert!(debug.contains("[redacted]"));
+        assert!(debug.contains(&server.uri()));
+    }
+
+    #[test]
+    #[allow(clippy::result_large_err)]
+    fn test_external_id_validation() {
+        let external_id =
+            |field: &str, value: &str| ExternalId::new(field.to_string(), value.to_string());
+
+        assert!(external_id("Ext__c", "123").is_ok());
+        assert!(external_id("acme__Ext__C", "123").is_ok());
+        assert!(external_id("Id", "001000000000001").is_ok());
+        assert!(external_id("", "123").is_err());
+        assert!(external_id("Ext__c", "").is_err());
+        assert!(external_id("Name", "Acme").is_err());
+        assert!(external_id("Ext__c/x", "123").is_err());
+    }
+
+    #[test]
+    fn test_response_clone_eq() {
+        let response = SfResponse {
+            headers: HeaderMap::new(),
+            status: StatusCode::OK,
+            body: Some(vec![SfApiError {
+                error_code: "NOT_FOUND".to_string(),
+                message: "Not found".to_string(),
+            }]),
+        };
+
+        assert_eq!(response, response.clone());
+    }
 }
@@ -0,0 +1,379 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::{Error, SfResult},
+    SfClient, SfResponse,
+};
+
+// How long fetched limits are reused by SfClient::limit before being fetched again
+const LIMITS_CACHE_TTL: Duration = Duration::from_secs(10);
+
+// Reports the daily API usage of the org on every response, such as `api-usage=25/15000`
+const SFORCE_LIMIT_INFO: &str = "Sforce-Limit-Info";
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Limit {
+    #[serde(rename = "Max")]
+    pub max: i64,
+    #[serde(rename = "Remaining")]
+    pub remaining: i64,
+    // Some limits are further broken down, such as DailyApiRequests by connected app
+    #[serde(flatten)]
+    pub components: HashMap<String, Limit>,
+}
+
+/// The daily API usage of the org as of a response
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ApiUsage {
+    pub used: u64,
+    pub max: u64,
+    pub observed_at: DateTime<Utc>,
+}
+
+impl ApiUsage {
+    fn parse(value: &str, observed_at: DateTime<Utc>) -> Option<Self> {
+        let usage = value
+            .split(',')
+            .find_map(|part| part.trim().strip_prefix("api-usage="))?;
+        let (used, max) = usage.split_once('/')?;
+
+        Some(Self {
+            used: used.trim().parse().ok()?,
+            max: max.trim().parse().ok()?,
+            observed_at,
+        })
+    }
+
+    fn exceeds(&self, threshold_percent: u8, now: DateTime<Utc>) -> bool {
+        self.observed_at.date_naive() == now.date_naive()
+            && self.used * 100 > self.max * u64::from(threshold_percent)
+    }
+}
+
+/// The number of records of an object, as last calculated by Salesforce
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct RecordCount {
+    pub name: String,
+    pub count: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct RecordCountResponse {
+    #[serde(rename = "sObjects")]
+    pub objects: Vec<RecordCount>,
+}
+
+/// A snapshot of the limits that are most often monitored, along with every other limit
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct OrgHealth {
+    pub api_requests: Limit,
+    pub data_storage: Limit,
+    pub file_storage: Limit,
+    pub bulk_api_batches: Option<Limit>,
+    pub async_apex_executions: Option<Limit>,
+    pub limits: HashMap<String, Limit>,
+    /// Record counts by object, only present when requested
+    pub record_counts: HashMap<String, u64>,
+}
+
+impl OrgHealth {
+    #[allow(clippy::result_large_err)]
+    fn from_limits(limits: HashMap<String, Limit>) -> SfResult<Self> {
+        let required = |name: &str| {
+            limits
+                .get(name)
+                .cloned()
+                .ok_or_else(|| Error::MalformedResponse {
+                    error: format!("Missing limit {}", name),
+                    body: serde_json::to_string(&limits).unwrap_or_default(),
+                })
+        };
+
+        Ok(Self {
+            api_requests: required("DailyApiRequests")?,
+            data_storage: required("DataStorageMB")?,
+            file_storage: required("FileStorageMB")?,
+            bulk_api_batches: limits.get("DailyBulkApiBatches").cloned(),
+            async_apex_executions: limits.get("DailyAsyncApexExecutions").cloned(),
+            record_counts: HashMap::new(),
+            limits,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct CachedLimits {
+    fetched_at: Instant,
+    limits: HashMap<String, Limit>,
+}
+
+impl SfClient {
+    /// Fetches the current usage of every limit of the org, keyed by the name of the limit
+    pub async fn limits(&self) -> SfResult<SfResponse<HashMap<String, Limit>>> {
+        self.get("limits").await
+    }
+
+    /// Fetches a single limit by name, such as `DailyApiRequests`. Limits are cached for a short
+    /// period so that frequent polling does not repeatedly fetch every limit
+    pub async fn limit(&self, name: &str) -> SfResult<Option<Limit>> {
+        if let Some(cached) = self.limits_cache.lock().unwrap().as_ref() {
+            if cached.fetched_at.elapsed() < LIMITS_CACHE_TTL {
+                return Ok(cached.limits.get(name).cloned());
+            }
+        }
+
+        let limits = self.limits().await?.into_body()?;
+        let limit = limits.get(name).cloned();
+
+        *self.limits_cache.lock().unwrap() = Some(CachedLimits {
+            fetched_at: Instant::now(),
+            limits,
+        });
+
+        Ok(limit)
+    }
+
+    /// Fetches the number of records of each of the given objects. Counts are calculated
+    /// periodically by Salesforce, so may not include recent changes
+    pub async fn record_counts(
+        &self,
+        objects: &[&str],
+    ) -> SfResult<SfResponse<RecordCountResponse>> {
+        self.get(&format!(
+            "limits/recordCount?sObjects={}",
+            objects.join(",")
+        ))
+        .await
+    }
+
+    /// Fetches the limits of the org, with the most monitored limits promoted to fields
+    pub async fn org_health(&self) -> SfResult<OrgHealth> {
+        OrgHealth::from_limits(self.limits().await?.into_body()?)
+    }
+
+    /// Fetches the limits of the org along with the record counts of the given objects
+    pub async fn org_health_with_counts(&self, objects: &[&str]) -> SfResult<OrgHealth> {
+        let (limits, counts) = tokio::try_join!(self.limits(), self.record_counts(objects))?;
+
+        let mut health = OrgHealth::from_limits(limits.into_body()?)?;
+        health.record_counts = counts
+            .into_body()?
+            .objects
+            .into_iter()
+            .map(|count| (count.name, count.count))
+            .collect();
+
+        Ok(health)
+    }
+
+    /// The API usage reported by the latest response, if any
+    pub fn api_usage(&self) -> Option<ApiUsage> {
+        *self.api_usage.lock().unwrap()
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn check_usage(&self) -> SfResult<()> {
+        let (Some(threshold), Some(usage)) = (self.usage_guard, self.api_usage()) else {
+            return Ok(());
+        };
+
+        if usage.exceeds(threshold, Utc::now()) {
+            Err(Error::UsageBudgetExceeded {
+                used: usage.used,
+                max: usage.max,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn record_usage(&self, headers: &HeaderMap) {
+        let usage = headers
+            .get(SFORCE_LIMIT_INFO)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| ApiUsage::parse(value, Utc::now()));
+
+        if let Some(usage) = usage {
+            *self.api_usage.lock().unwrap() = Some(usage);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::{authenticator::jwt::tests::add_token_mock, error::Error, tests::get_client};
+
+    use super::{ApiUsage, Limit};
+
+    #[test]
+    fn test_api_usage() {
+        let now = Utc.with_ymd_and_hms(2023, 1, 2, 3, 4, 5).unwrap();
+        let usage =
+            ApiUsage::parse("api-usage=91/100, per-app-api-usage=2/50(appName=etl)", now).unwrap();
+
+        assert_eq!(91, usage.used);
+        assert_eq!(100, usage.max);
+        assert!(usage.exceeds(90, now));
+        assert!(!usage.exceeds(95, now));
+        // Usage observed on a previous day no longer applies
+        assert!(!usage.exceeds(90, Utc.with_ymd_and_hms(2023, 1, 3, 0, 0, 0).unwrap()));
+        assert!(ApiUsage::parse("per-app-api-usage=2/50", now).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_usage_guard() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/limits"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Sforce-Limit-Info", "api-usage=91/100")
+                    .set_body_json(serde_json::json!({})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut client = get_client(&server).await;
+        assert!(matches!(
+            client.with_usage_guard(101),
+            Err(Error::InvalidInput(_))
+        ));
+        client.with_usage_guard(90).unwrap();
+
+        client.limits().await.unwrap();
+        assert_eq!(91, client.api_usage().unwrap().used);
+
+        assert!(matches!(
+            client.limits().await.unwrap_err(),
+            Error::UsageBudgetExceeded { used: 91, max: 100 }
+        ));
+
+        // Requests that read the response themselves are refused as well
+        assert!(matches!(
+            client.object_exists("Lead", "00Q000000000001").await,
+            Err(Error::UsageBudgetExceeded { .. })
+        ));
+        assert!(matches!(
+            client
+                .send_raw::<()>(reqwest::Method::GET, "limits", None)
+                .await,
+            Err(Error::UsageBudgetExceeded { .. })
+        ));
+        assert!(matches!(
+            client.undelete(&["00Q000000000001"]).await,
+            Err(Error::UsageBudgetExceeded { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_limit() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/limits"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "DailyApiRequests": {
+                    "Max": 15000,
+                    "Remaining": 14998,
+                    "Ant Migration Tool": { "Max": 0, "Remaining": 0 }
+                },
+                "DataStorageMB": { "Max": 5, "Remaining": 5 }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        let limit = client.limit("DailyApiRequests").await.unwrap().unwrap();
+        assert_eq!(15000, limit.max);
+        assert_eq!(14998, limit.remaining);
+        assert_eq!(0, limit.components["Ant Migration Tool"].max);
+
+        // Served from the cache
+        let limit = client.limit("DataStorageMB").await.unwrap().unwrap();
+        assert_eq!(5, limit.remaining);
+        assert!(client.limit("Unknown").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_org_health() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/limits"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "DailyApiRequests": { "Max": 15000, "Remaining": 14998 },
+                "DataStorageMB": { "Max": 5, "Remaining": 4 },
+                "FileStorageMB": { "Max": 20, "Remaining": 18 },
+                "DailyBulkApiBatches": { "Max": 15000, "Remaining": 15000 },
+                "HourlyODataCallout": { "Max": 10000, "Remaining": 9999 }
+            })))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/limits/recordCount"))
+            .and(query_param("sObjects", "Account,Contact"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "sObjects": [
+                    { "count": 3, "name": "Account" },
+                    { "count": 10, "name": "Contact" }
+                ]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        let health = client.org_health().await.unwrap();
+        assert_eq!(14998, health.api_requests.remaining);
+        assert_eq!(4, health.data_storage.remaining);
+        assert_eq!(18, health.file_storage.remaining);
+        assert_eq!(15000, health.bulk_api_batches.unwrap().max);
+        assert_eq!(None, health.async_apex_executions);
+        assert_eq!(9999, health.limits["HourlyODataCallout"].remaining);
+        assert!(health.record_counts.is_empty());
+
+        let health = client
+            .org_health_with_counts(&["Account", "Contact"])
+            .await
+            .unwrap();
+        assert_eq!(
+            Limit {
+                max: 5,
+                remaining: 4,
+                ..Default::default()
+            },
+            health.data_storage
+        );
+        assert_eq!(3, health.record_counts["Account"]);
+        assert_eq!(10, health.record_counts["Contact"]);
+    }
+}
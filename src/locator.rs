@@ -0,0 +1,211 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use futures::TryStreamExt;
+use serde::de::DeserializeOwned;
+
+use crate::{error::SfResult, util::join_url, QueryResponse, SfClient};
+
+/// The position of a page of query results, from either a REST query or a Bulk API 2.0 query job.
+/// Pages are fetched with [`SfClient::next_page`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Locator {
+    /// The `nextRecordsUrl` of a REST query response
+    Query(String),
+    /// The `Sforce-Locator` of a page of query job results. A locator of `None` is the first page
+    BulkQuery {
+        job_id: String,
+        locator: Option<String>,
+    },
+}
+
+impl Locator {
+    /// The first page of results of a completed query job
+    pub fn bulk_query(job_id: &str) -> Self {
+        Self::BulkQuery {
+            job_id: job_id.to_string(),
+            locator: None,
+        }
+    }
+}
+
+/// A page of records along with the locator of the page that follows it, if any
+#[derive(Clone, Debug, PartialEq)]
+pub struct Page<T> {
+    pub records: Vec<T>,
+    pub next: Option<Locator>,
+}
+
+impl<T> QueryResponse<T> {
+    /// The locator of the next page of results, if the query has more results
+    pub fn locator(&self) -> Option<Locator> {
+        self.next_records_url.clone().map(Locator::Query)
+    }
+}
+
+impl<T> From<QueryResponse<T>> for Page<T> {
+    fn from(response: QueryResponse<T>) -> Self {
+        Self {
+            next: response.locator(),
+            records: response
+                .records
+                .into_iter()
+                .map(|record| record.object)
+                .collect(),
+        }
+    }
+}
+
+impl SfClient {
+    /// Fetches the page of records at a locator. REST query records are deserialized from JSON,
+    /// while query job records are deserialized from CSV rows
+    pub async fn next_page<T>(&self, locator: &Locator) -> SfResult<Page<T>>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        match locator {
+            Locator::Query(path) => Ok(self
+                .get_query_page(&join_url(&self.instance_url, path))
+                .await?
+                .into_body()?
+                .into()),
+            Locator::BulkQuery { job_id, locator } => {
+                let url = self.url(&format!("jobs/query/{}/results", job_id));
+                let (records, next) = self
+                    .query_job_results_page(&url, locator.as_deref())
+                    .await?;
+
+                Ok(Page {
+                    records: records.try_collect().await?,
+                    next: next.map(|locator| Locator::BulkQuery {
+                        job_id: job_id.clone(),
+                        locator: Some(locator),
+                    }),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use wiremock::{
+        matchers::{method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{authenticator::jwt::tests::add_token_mock, tests::get_client, SfResponse};
+
+    use super::Locator;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Lead {
+        #[serde(rename = "Id")]
+        id: String,
+    }
+
+    #[tokio::test]
+    async fn test_next_page_query() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/query/"))
+            .and(query_param("q", "SELECT Id FROM Lead"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "totalSize": 2,
+                "done": false,
+                "nextRecordsUrl": "/services/data/v12345.0/query/01g-1",
+                "records": [{ "attributes": { "type": "Lead", "url": "" }, "Id": "001" }]
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/query/01g-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "totalSize": 2,
+                "done": true,
+                "records": [{ "attributes": { "type": "Lead", "url": "" }, "Id": "002" }]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let first = client
+            .query::<Lead>("SELECT Id FROM Lead")
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        let locator = first.locator().unwrap();
+        assert_eq!(
+            Locator::Query("/services/data/v12345.0/query/01g-1".to_string()),
+            locator
+        );
+
+        let page = client.next_page::<Lead>(&locator).await.unwrap();
+        assert_eq!(
+            vec![Lead {
+                id: "002".to_string()
+            }],
+            page.records
+        );
+        assert_eq!(None, page.next);
+    }
+
+    #[tokio::test]
+    async fn test_next_page_bulk_query() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path(
+                "/services/data/v12345.0/jobs/query/750000000000002/results",
+            ))
+            .and(query_param("locator", "MTAwMDA"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Sforce-Locator", "null")
+                    .set_body_raw("Id\n002\n", "text/csv"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(
+                "/services/data/v12345.0/jobs/query/750000000000002/results",
+            ))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Sforce-Locator", "MTAwMDA")
+                    .set_body_raw("Id\n001\n", "text/csv"),
+            )
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        let mut locator = Some(Locator::bulk_query("750000000000002"));
+        let mut records = Vec::new();
+        while let Some(current) = locator {
+            let page = client.next_page::<Lead>(&current).await.unwrap();
+            records.extend(page.records);
+            locator = page.next;
+        }
+
+        assert_eq!(
+            vec![
+                Lead {
+                    id: "001".to_string()
+                },
+                Lead {
+                    id: "002".to_string()
+                }
+            ],
+            records
+        );
+    }
+}
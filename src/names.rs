@@ -0,0 +1,147 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use crate::error::{Error, SfResult};
+
+// Suffixes that mark the kind of a custom or namespaced entity, in lowercase
+const CUSTOM_SUFFIXES: &[&str] = &[
+    "c",
+    "mdt",
+    "e",
+    "x",
+    "b",
+    "share",
+    "history",
+    "feed",
+    "kav",
+    "ka",
+    "chn",
+    "p",
+    "changeevent",
+    "tag",
+    "dlm",
+];
+
+/// Normalizes the name of an object for use in a resource path. Surrounding whitespace is
+/// removed and the casing of a custom object suffix is normalized, so `acme__Widget__C` becomes
+/// `acme__Widget__c`. Other suffixes, such as `__Share` or `__ChangeEvent`, are kept as they are.
+///
+/// Names that look like a custom object that is missing its suffix, such as `acme__Widget` or
+/// `Widget_c`, are rejected with [`Error::InvalidInput`] as they would otherwise result in a 404
+#[allow(clippy::result_large_err)]
+pub fn normalize_object_name(name: &str) -> SfResult<String> {
+    let name = name.trim();
+
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(Error::InvalidInput(format!(
+            "{:?} is not a valid object name",
+            name
+        )));
+    }
+
+    match name.rsplit_once("__") {
+        Some((base, suffix)) => {
+            let lowercase = suffix.to_ascii_lowercase();
+
+            if lowercase == "c" && !base.is_empty() {
+                Ok(format!("{}__c", base))
+            } else if CUSTOM_SUFFIXES.contains(&lowercase.as_str()) && !base.is_empty() {
+                Ok(name.to_string())
+            } else {
+                Err(Error::InvalidInput(format!(
+                    "{} looks like a namespaced object without a suffix, did you mean {}__c?",
+                    name, name
+                )))
+            }
+        }
+        None => match name.strip_suffix("_c").or(name.strip_suffix("_C")) {
+            Some(base) => Err(Error::InvalidInput(format!(
+                "{} looks like a custom object with a malformed suffix, did you mean {}__c?",
+                name, base
+            ))),
+            None => Ok(name.to_string()),
+        },
+    }
+}
+
+/// Checks that a field name, or a path through relationships such as `Owner.Email`, consists of
+/// API names only, so that it can be placed into a SOQL query as it is
+#[allow(clippy::result_large_err)]
+pub(crate) fn validate_field_name(name: &str) -> SfResult<&str> {
+    let valid = name.split('.').all(|part| {
+        part.starts_with(|c: char| c.is_ascii_alphabetic())
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    });
+
+    if valid {
+        Ok(name)
+    } else {
+        Err(Error::InvalidInput(format!(
+            "{:?} is not a valid field name",
+            name
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+
+    use super::{normalize_object_name, validate_field_name};
+
+    #[test]
+    fn test_normalize_object_name() {
+        for (name, expected) in [
+            ("Account", "Account"),
+            (" Lead ", "Lead"),
+            ("Widget__c", "Widget__c"),
+            ("acme__Widget__C", "acme__Widget__c"),
+            ("acme__Setting__mdt", "acme__Setting__mdt"),
+            ("Widget__Share", "Widget__Share"),
+            ("Widget__ChangeEvent", "Widget__ChangeEvent"),
+            ("AccountChangeEvent", "AccountChangeEvent"),
+            ("Widget__History", "Widget__History"),
+            ("Widget__Tag", "Widget__Tag"),
+            ("acme__Order__e", "acme__Order__e"),
+            ("Sales__dlm", "Sales__dlm"),
+        ] {
+            assert_eq!(expected, normalize_object_name(name).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_normalize_object_name_missing_suffix() {
+        for name in [
+            "acme__Widget",
+            "Widget_c",
+            "__c",
+            "",
+            "Lead Contact",
+            "Lead/1",
+        ] {
+            assert!(
+                matches!(normalize_object_name(name), Err(Error::InvalidInput(_))),
+                "{} should be rejected",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_field_name() {
+        for name in ["Name", "acme__Quantity__c", "Owner.Email"] {
+            assert_eq!(name, validate_field_name(name).unwrap());
+        }
+
+        for name in ["", "Name = 'x' OR Id", "Owner.", "1Name", "_Name", "Name;"] {
+            assert!(
+                matches!(validate_field_name(name), Err(Error::InvalidInput(_))),
+                "{} should be rejected",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,256 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::SfResult, SfApiError, SfClient, SfResponse};
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub enum ApprovalActionType {
+    Submit,
+    Approve,
+    Reject,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalRequest {
+    pub action_type: ApprovalActionType,
+    /// The record to submit, or the work item to approve or reject
+    pub context_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comments: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub next_approver_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub process_definition_name_or_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_entry_criteria: Option<bool>,
+}
+
+impl ApprovalRequest {
+    pub fn new(action_type: ApprovalActionType, context_id: String) -> Self {
+        Self {
+            action_type,
+            context_id,
+            comments: None,
+            next_approver_ids: vec![],
+            process_definition_name_or_id: None,
+            skip_entry_criteria: None,
+        }
+    }
+
+    pub fn comments(mut self, comments: String) -> Self {
+        self.comments = Some(comments);
+        self
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ApprovalRequests<'a> {
+    requests: &'a [ApprovalRequest],
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ApprovalResult {
+    pub actor_ids: Vec<String>,
+    pub entity_id: Option<String>,
+    pub errors: Option<Vec<SfApiError>>,
+    pub instance_id: Option<String>,
+    pub instance_status: Option<String>,
+    pub new_workitem_ids: Vec<String>,
+    pub success: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProcessRulesRequest<'a> {
+    context_ids: &'a [&'a str],
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct ProcessRulesResult {
+    pub errors: Option<Vec<SfApiError>>,
+    pub success: bool,
+}
+
+impl SfClient {
+    /// Evaluates the workflow rules of records, such as after a data load that bypassed
+    /// automation. The records must all be of the same object
+    pub async fn trigger_process_rules(
+        &self,
+        context_ids: &[&str],
+    ) -> SfResult<SfResponse<ProcessRulesResult>> {
+        self.post(
+            "process/rules/",
+            ProcessRulesRequest { context_ids },
+            &[StatusCode::OK],
+        )
+        .await
+    }
+
+    /// Submits, approves, or rejects records in approval processes. A result is returned for
+    /// each request
+    pub async fn process_approvals(
+        &self,
+        requests: &[ApprovalRequest],
+    ) -> SfResult<SfResponse<Vec<ApprovalResult>>> {
+        self.post(
+            "process/approvals/",
+            ApprovalRequests { requests },
+            &[StatusCode::OK],
+        )
+        .await
+    }
+
+    pub async fn submit_for_approval(
+        &self,
+        record_id: &str,
+        comments: Option<&str>,
+    ) -> SfResult<SfResponse<Vec<ApprovalResult>>> {
+        self.approval_action(ApprovalActionType::Submit, record_id, comments)
+            .await
+    }
+
+    /// Approves a pending approval work item, as returned by [`ApprovalResult::new_workitem_ids`]
+    pub async fn approve(
+        &self,
+        workitem_id: &str,
+        comments: Option<&str>,
+    ) -> SfResult<SfResponse<Vec<ApprovalResult>>> {
+        self.approval_action(ApprovalActionType::Approve, workitem_id, comments)
+            .await
+    }
+
+    /// Rejects a pending approval work item, as returned by [`ApprovalResult::new_workitem_ids`]
+    pub async fn reject(
+        &self,
+        workitem_id: &str,
+        comments: Option<&str>,
+    ) -> SfResult<SfResponse<Vec<ApprovalResult>>> {
+        self.approval_action(ApprovalActionType::Reject, workitem_id, comments)
+            .await
+    }
+
+    async fn approval_action(
+        &self,
+        action_type: ApprovalActionType,
+        context_id: &str,
+        comments: Option<&str>,
+    ) -> SfResult<SfResponse<Vec<ApprovalResult>>> {
+        let mut request = ApprovalRequest::new(action_type, context_id.to_string());
+        request.comments = comments.map(|comments| comments.to_string());
+
+        self.process_approvals(&[request]).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{body_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{authenticator::jwt::tests::add_token_mock, tests::get_client, SfResponse};
+
+    #[tokio::test]
+    async fn test_submit_and_approve() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/process/approvals/"))
+            .and(body_json(serde_json::json!({
+                "requests": [{
+                    "actionType": "Submit",
+                    "contextId": "001000000000001",
+                    "comments": "New account"
+                }]
+            })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!([{
+                    "actorIds": ["005000000000001"],
+                    "entityId": "001000000000001",
+                    "errors": null,
+                    "instanceId": "04g000000000001",
+                    "instanceStatus": "Pending",
+                    "newWorkitemIds": ["04i000000000001"],
+                    "success": true
+                }])),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/process/approvals/"))
+            .and(body_json(serde_json::json!({
+                "requests": [{
+                    "actionType": "Approve",
+                    "contextId": "04i000000000001"
+                }]
+            })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!([{
+                    "actorIds": [],
+                    "entityId": "001000000000001",
+                    "errors": null,
+                    "instanceId": "04g000000000001",
+                    "instanceStatus": "Approved",
+                    "newWorkitemIds": [],
+                    "success": true
+                }])),
+            )
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+
+        let submitted = client
+            .submit_for_approval("001000000000001", Some("New account"))
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+        assert_eq!(Some("Pending"), submitted[0].instance_status.as_deref());
+
+        let approved = client
+            .approve(&submitted[0].new_workitem_ids[0], None)
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+        assert_eq!(Some("Approved"), approved[0].instance_status.as_deref());
+    }
+
+    #[tokio::test]
+    async fn test_trigger_process_rules() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/process/rules/"))
+            .and(body_json(serde_json::json!({
+                "contextIds": ["001000000000001", "001000000000002"]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "errors": null,
+                "success": true
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let result = client
+            .trigger_process_rules(&["001000000000001", "001000000000002"])
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        assert!(result.success);
+        assert_eq!(None, result.errors);
+    }
+}
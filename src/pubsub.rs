@@ -0,0 +1,625 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+//! A client of the Pub/Sub API, which delivers Change Data Capture and platform events over gRPC.
+//! Event payloads are Avro encoded and are returned as they were received, along with the id of
+//! the schema that [`PubSubClient::get_schema`] fetches to decode them
+
+use futures::{channel::mpsc, stream, Stream, TryStreamExt};
+use std::fmt;
+use tonic::{
+    client::Grpc,
+    codec::ProstCodec,
+    codegen::http::uri::PathAndQuery,
+    metadata::MetadataValue,
+    transport::{Channel, ClientTlsConfig, Endpoint},
+    Request, Status,
+};
+
+use crate::{
+    error::{Error, SfResult},
+    util::Redacted,
+    Authenticator,
+};
+
+/// The global endpoint of the Pub/Sub API
+pub const DEFAULT_ENDPOINT: &str = "https://api.pubsub.salesforce.com:7443";
+
+const SUBSCRIBE: &str = "/eventbus.v1.PubSub/Subscribe";
+const GET_SCHEMA: &str = "/eventbus.v1.PubSub/GetSchema";
+
+// Messages of the Pub/Sub API, as defined by pubsub_api.proto. Only the fields used by the
+// client are modeled
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct FetchRequest {
+    #[prost(string, tag = "1")]
+    topic_name: String,
+    #[prost(int32, tag = "2")]
+    replay_preset: i32,
+    #[prost(bytes = "vec", tag = "3")]
+    replay_id: Vec<u8>,
+    #[prost(int32, tag = "4")]
+    num_requested: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct FetchResponse {
+    #[prost(message, repeated, tag = "1")]
+    events: Vec<ConsumerEvent>,
+    #[prost(bytes = "vec", tag = "2")]
+    latest_replay_id: Vec<u8>,
+    #[prost(string, tag = "3")]
+    rpc_id: String,
+    #[prost(int32, tag = "4")]
+    pending_num_requested: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ConsumerEvent {
+    #[prost(message, optional, tag = "1")]
+    event: Option<ProducerEvent>,
+    #[prost(bytes = "vec", tag = "2")]
+    replay_id: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ProducerEvent {
+    #[prost(string, tag = "1")]
+    id: String,
+    #[prost(string, tag = "2")]
+    schema_id: String,
+    #[prost(bytes = "vec", tag = "3")]
+    payload: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct SchemaRequest {
+    #[prost(string, tag = "1")]
+    schema_id: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct SchemaInfo {
+    #[prost(string, tag = "1")]
+    schema_json: String,
+    #[prost(string, tag = "2")]
+    schema_id: String,
+}
+
+/// Where a subscription starts from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplayFrom {
+    /// Only events published after subscribing
+    Latest,
+    /// The oldest retained events
+    Earliest,
+    /// The events after the event with the given replay id
+    ReplayId(Vec<u8>),
+}
+
+impl ReplayFrom {
+    fn fetch_request(&self, topic: &str, num_requested: i32) -> FetchRequest {
+        let (replay_preset, replay_id) = match self {
+            Self::Latest => (0, Vec::new()),
+            Self::Earliest => (1, Vec::new()),
+            Self::ReplayId(replay_id) => (2, replay_id.clone()),
+        };
+
+        FetchRequest {
+            topic_name: topic.to_string(),
+            replay_preset,
+            replay_id,
+            num_requested,
+        }
+    }
+}
+
+/// An event received from a subscription
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PubSubEvent {
+    pub id: String,
+    pub schema_id: String,
+    /// The id to resume a subscription after this event with [`ReplayFrom::ReplayId`]
+    pub replay_id: Vec<u8>,
+    /// The Avro encoded event
+    pub payload: Vec<u8>,
+}
+
+impl From<ConsumerEvent> for PubSubEvent {
+    fn from(event: ConsumerEvent) -> Self {
+        let producer = event.event.unwrap_or_default();
+
+        Self {
+            id: producer.id,
+            schema_id: producer.schema_id,
+            replay_id: event.replay_id,
+            payload: producer.payload,
+        }
+    }
+}
+
+// The id of the org is the first segment after `/id/` of the identity url of a token
+fn org_id(identity_url: &str) -> Option<&str> {
+    identity_url
+        .split_once("/id/")
+        .and_then(|(_, ids)| ids.split('/').next())
+        .filter(|org_id| !org_id.is_empty())
+}
+
+#[allow(clippy::result_large_err)]
+fn metadata(value: &str) -> SfResult<MetadataValue<tonic::metadata::Ascii>> {
+    value
+        .parse()
+        .map_err(|_| Error::InvalidInput(format!("{:?} is not a valid header value", value)))
+}
+
+pub struct PubSubClient {
+    grpc: Grpc<Channel>,
+    access_token: String,
+    instance_url: String,
+    org_id: String,
+}
+
+impl fmt::Debug for PubSubClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PubSubClient")
+            .field("instance_url", &self.instance_url)
+            .field("org_id", &self.org_id)
+            .field("access_token", &Redacted)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PubSubClient {
+    /// Connects to the global Pub/Sub API endpoint with a token from the authenticator
+    pub async fn connect(authenticator: &(impl Authenticator + Sync)) -> SfResult<Self> {
+        Self::connect_to(DEFAULT_ENDPOINT, authenticator).await
+    }
+
+    /// Connects to the given Pub/Sub API endpoint with a token from the authenticator. The
+    /// token is not refreshed, so the client must be recreated once the token expires
+    pub async fn connect_to(
+        endpoint: &str,
+        authenticator: &(impl Authenticator + Sync),
+    ) -> SfResult<Self> {
+        let token = authenticator.get_token().await?;
+
+        // Tokens of authenticators that do not request them, such as sessions, have no identity
+        let org_id = match org_id(&token.id) {
+            Some(org_id) => org_id.to_string(),
+            None => {
+                authenticator
+                    .user_info_with_token(&token)
+                    .await?
+                    .organization_id
+            }
+        };
+
+        let mut endpoint = Endpoint::from_shared(endpoint.to_string())?;
+        if endpoint.uri().scheme_str() == Some("https") {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new())?;
+        }
+
+        tracing::debug!(uri = ?endpoint.uri(), "Connecting to the Pub/Sub API");
+
+        Ok(Self {
+            grpc: Grpc::new(endpoint.connect().await?),
+            access_token: token.access_token,
+            instance_url: token.instance_url,
+            org_id,
+        })
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn request<T>(&self, message: T) -> SfResult<Request<T>> {
+        let mut request = Request::new(message);
+        let metadata_map = request.metadata_mut();
+        metadata_map.insert("accesstoken", metadata(&self.access_token)?);
+        metadata_map.insert("instanceurl", metadata(&self.instance_url)?);
+        metadata_map.insert("tenantid", metadata(&self.org_id)?);
+
+        Ok(request)
+    }
+
+    async fn ready(&self) -> SfResult<Grpc<Channel>> {
+        let mut grpc = self.grpc.clone();
+        grpc.ready()
+            .await
+            .map_err(|err| Status::unavailable(format!("Service was not ready: {}", err)))?;
+
+        Ok(grpc)
+    }
+
+    /// Fetches the Avro schema of events, as JSON
+    pub async fn get_schema(&self, schema_id: &str) -> SfResult<String> {
+        let request = self.request(SchemaRequest {
+            schema_id: schema_id.to_string(),
+        })?;
+
+        let response = self
+            .ready()
+            .await?
+            .unary::<_, SchemaInfo, _>(
+                request,
+                PathAndQuery::from_static(GET_SCHEMA),
+                ProstCodec::default(),
+            )
+            .await?;
+
+        Ok(response.into_inner().schema_json)
+    }
+
+    /// Subscribes to a topic, such as `/event/Order_Placed__e` or `/data/AccountChangeEvent`.
+    /// Events are requested from the server in batches of the given size, with the next batch
+    /// requested once every event of the previous batch has been delivered. Returns
+    /// [`Error::InvalidInput`] if the batch size is not positive
+    #[allow(clippy::result_large_err)]
+    pub async fn subscribe(
+        &self,
+        topic: &str,
+        replay_from: ReplayFrom,
+        batch_size: i32,
+    ) -> SfResult<impl Stream<Item = SfResult<PubSubEvent>>> {
+        if batch_size <= 0 {
+            return Err(Error::InvalidInput(format!(
+                "Expected a positive batch size but received {}",
+                batch_size
+            )));
+        }
+
+        let (sender, receiver) = mpsc::unbounded();
+        sender
+            .unbounded_send(replay_from.fetch_request(topic, batch_size))
+            .map_err(|_| Error::Cancelled)?;
+
+        tracing::debug!(topic, ?replay_from, "Subscribing to topic");
+
+        let responses = self
+            .ready()
+            .await?
+            .streaming::<_, FetchRequest, FetchResponse, _>(
+                self.request(receiver)?,
+                PathAndQuery::from_static(SUBSCRIBE),
+                ProstCodec::default(),
+            )
+            .await?
+            .into_inner();
+
+        // Subsequent requests only request more events, the replay position is kept by the server
+        let next_request = FetchRequest {
+            topic_name: topic.to_string(),
+            num_requested: batch_size,
+            ..Default::default()
+        };
+
+        Ok(
+            stream::try_unfold((responses, sender), move |(mut responses, sender)| {
+                let next_request = next_request.clone();
+
+                async move {
+                    let Some(response) = responses.message().await? else {
+                        return Ok::<_, Error>(None);
+                    };
+
+                    if response.pending_num_requested == 0 {
+                        // The request stream is only closed once the subscription has ended
+                        let _ = sender.unbounded_send(next_request);
+                    }
+
+                    let events = response
+                        .events
+                        .into_iter()
+                        .map(|event| Ok(PubSubEvent::from(event)));
+
+                    Ok(Some((stream::iter(events), (responses, sender))))
+                }
+            })
+            .try_flatten(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{channel::mpsc, stream, TryStreamExt};
+    use prost::Message;
+    use std::{
+        convert::Infallible,
+        sync::{Arc, Mutex},
+    };
+    use tonic::{
+        body::BoxBody,
+        codec::{ProstCodec, Streaming},
+        codegen::{http, BoxFuture, BoxStream, Context, Poll, Service},
+        server::NamedService,
+        transport::{Body, Server},
+        Status,
+    };
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        authenticator::{
+            jwt::{tests::private_key, JwtAuthenticator, LoginClaims},
+            AuthorizationServer, SfAccessToken,
+        },
+        error::Error,
+    };
+
+    use super::{
+        org_id, ConsumerEvent, FetchRequest, FetchResponse, ProducerEvent, PubSubClient,
+        PubSubEvent, ReplayFrom, SUBSCRIBE,
+    };
+
+    // Serves Subscribe of the Pub/Sub API, responding to the first request with two events and
+    // none pending, and to the second with one event before ending the subscription
+    #[derive(Clone, Default)]
+    struct PubSubServer {
+        metadata: Arc<Mutex<Vec<String>>>,
+        requests: Arc<Mutex<Vec<FetchRequest>>>,
+    }
+
+    impl NamedService for PubSubServer {
+        const NAME: &'static str = "eventbus.v1.PubSub";
+    }
+
+    impl Service<http::Request<Body>> for PubSubServer {
+        type Response = http::Response<BoxBody>;
+        type Error = Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<Body>) -> Self::Future {
+            assert_eq!(SUBSCRIBE, request.uri().path());
+
+            let server = self.clone();
+            Box::pin(async move {
+                let mut grpc =
+                    tonic::server::Grpc::new(ProstCodec::<FetchResponse, FetchRequest>::default());
+                Ok(grpc.streaming(server, request).await)
+            })
+        }
+    }
+
+    impl Service<tonic::Request<Streaming<FetchRequest>>> for PubSubServer {
+        type Response = tonic::Response<BoxStream<FetchResponse>>;
+        type Error = Status;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: tonic::Request<Streaming<FetchRequest>>) -> Self::Future {
+            *self.metadata.lock().unwrap() = ["accesstoken", "instanceurl", "tenantid"]
+                .iter()
+                .map(|key| {
+                    request
+                        .metadata()
+                        .get(*key)
+                        .unwrap()
+                        .to_str()
+                        .unwrap()
+                        .to_string()
+                })
+                .collect();
+
+            let requests = self.requests.clone();
+            let mut fetches = request.into_inner();
+            let (sender, receiver) = mpsc::unbounded();
+
+            tokio::spawn(async move {
+                for (ids, pending_num_requested) in [(&["1", "2"][..], 0), (&["3"][..], 1)] {
+                    let Some(fetch) = fetches.message().await.unwrap() else {
+                        return;
+                    };
+                    requests.lock().unwrap().push(fetch);
+
+                    let events = ids
+                        .iter()
+                        .map(|id| ConsumerEvent {
+                            event: Some(ProducerEvent {
+                                id: id.to_string(),
+                                schema_id: "schema-1".to_string(),
+                                payload: Vec::new(),
+                            }),
+                            replay_id: id.as_bytes().to_vec(),
+                        })
+                        .collect();
+
+                    let _ = sender.unbounded_send(Ok(FetchResponse {
+                        events,
+                        pending_num_requested,
+                        ..Default::default()
+                    }));
+                }
+            });
+
+            Box::pin(async move { Ok(tonic::Response::new(Box::pin(receiver) as BoxStream<_>)) })
+        }
+    }
+
+    async fn connect(server: &PubSubServer) -> PubSubClient {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = stream::unfold(listener, |listener| async move {
+            let stream = listener.accept().await.map(|(stream, _)| stream);
+            Some((stream, listener))
+        });
+        tokio::spawn(
+            Server::builder()
+                .add_service(server.clone())
+                .serve_with_incoming(incoming),
+        );
+
+        let auth_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/services/oauth2/token"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(SfAccessToken {
+                    access_token: "access_token".to_string(),
+                    scope: "api".to_string(),
+                    instance_url: "https://acme.my.salesforce.com".to_string(),
+                    id: "https://login.salesforce.com/id/00D000000000001AAA/005000000000001AAA"
+                        .to_string(),
+                    token_type: "Bearer".to_string(),
+                }),
+            )
+            .mount(&auth_server)
+            .await;
+
+        let claims = LoginClaims::new(
+            "sf-client-id".to_string(),
+            AuthorizationServer::Test,
+            "test@company".to_string(),
+        );
+        let authenticator =
+            JwtAuthenticator::new(&auth_server.uri(), claims, private_key()).unwrap();
+
+        PubSubClient::connect_to(&format!("http://{}", addr), &authenticator)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let server = PubSubServer::default();
+        let client = connect(&server).await;
+
+        let events = client
+            .subscribe("/event/Order__e", ReplayFrom::Earliest, 2)
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        // The stream ends along with the subscription
+        assert_eq!(
+            vec!["1", "2", "3"],
+            events
+                .iter()
+                .map(|event| event.id.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(b"3".to_vec(), events[2].replay_id);
+
+        assert_eq!(
+            vec![
+                "access_token",
+                "https://acme.my.salesforce.com",
+                "00D000000000001AAA"
+            ],
+            *server.metadata.lock().unwrap()
+        );
+
+        // More events are only requested once none are pending
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(2, requests.len());
+        assert_eq!(
+            ReplayFrom::Earliest.fetch_request("/event/Order__e", 2),
+            requests[0]
+        );
+        assert_eq!(
+            FetchRequest {
+                topic_name: "/event/Order__e".to_string(),
+                num_requested: 2,
+                ..Default::default()
+            },
+            requests[1]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_invalid_batch_size() {
+        let server = PubSubServer::default();
+        let client = connect(&server).await;
+
+        for batch_size in [0, -1] {
+            assert!(matches!(
+                client
+                    .subscribe("/event/Order__e", ReplayFrom::Latest, batch_size)
+                    .await
+                    .err()
+                    .unwrap(),
+                Error::InvalidInput(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_org_id() {
+        assert_eq!(
+            Some("00D000000000001AAA"),
+            org_id("https://login.salesforce.com/id/00D000000000001AAA/005000000000001AAA")
+        );
+        assert_eq!(None, org_id(""));
+        assert_eq!(None, org_id("https://login.salesforce.com/id/"));
+    }
+
+    #[test]
+    fn test_fetch_request() {
+        let request = ReplayFrom::ReplayId(vec![1, 2]).fetch_request("/event/Order__e", 100);
+        assert_eq!(2, request.replay_preset);
+
+        // Fields are encoded with the tags of the Pub/Sub API
+        assert_eq!(
+            [
+                &[0x0a, 15][..],
+                b"/event/Order__e",
+                &[0x10, 2, 0x1a, 2, 1, 2, 0x20, 100]
+            ]
+            .concat(),
+            request.encode_to_vec()
+        );
+
+        let request = ReplayFrom::Latest.fetch_request("/event/Order__e", 1);
+        assert_eq!(
+            request,
+            FetchRequest::decode(request.encode_to_vec().as_slice()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_events() {
+        let response = FetchResponse {
+            events: vec![ConsumerEvent {
+                event: Some(ProducerEvent {
+                    id: "event-1".to_string(),
+                    schema_id: "schema-1".to_string(),
+                    payload: vec![0x02, 0x61],
+                }),
+                replay_id: vec![0, 0, 1],
+            }],
+            latest_replay_id: vec![0, 0, 1],
+            rpc_id: "rpc-1".to_string(),
+            pending_num_requested: 99,
+        };
+
+        let response = FetchResponse::decode(response.encode_to_vec().as_slice()).unwrap();
+        let events = response
+            .events
+            .into_iter()
+            .map(PubSubEvent::from)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![PubSubEvent {
+                id: "event-1".to_string(),
+                schema_id: "schema-1".to_string(),
+                replay_id: vec![0, 0, 1],
+                payload: vec![0x02, 0x61],
+            }],
+            events
+        );
+    }
+}
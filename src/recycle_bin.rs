@@ -0,0 +1,272 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use quick_xml::escape::escape;
+use reqwest::{header::CONTENT_TYPE, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    error::{Error, SfResult},
+    util::join_url,
+    QueryResponse, Service, SfApiError, SfClient, SfResponse,
+};
+
+/// The result of restoring a single record
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct UndeleteResult {
+    pub id: Option<String>,
+    pub success: bool,
+    #[serde(default)]
+    pub errors: Vec<UndeleteError>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UndeleteError {
+    pub status_code: String,
+    pub message: String,
+    #[serde(default)]
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Envelope {
+    #[serde(rename = "Body")]
+    body: Body,
+}
+
+#[derive(Debug, Deserialize)]
+struct Body {
+    #[serde(rename = "undeleteResponse")]
+    response: Option<UndeleteResponse>,
+    #[serde(rename = "Fault")]
+    fault: Option<Fault>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UndeleteResponse {
+    #[serde(default)]
+    result: Vec<UndeleteResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Fault {
+    faultcode: String,
+    faultstring: String,
+}
+
+impl SfClient {
+    /// Lists the records of an object that are in the recycle bin
+    pub async fn query_deleted<T>(
+        &self,
+        object: &str,
+        fields: &[&str],
+    ) -> SfResult<SfResponse<QueryResponse<T>>>
+    where
+        T: DeserializeOwned + 'static,
+    {
+        self.query_all(&format!(
+            "SELECT {} FROM {} WHERE IsDeleted = true",
+            fields.join(", "),
+            object
+        ))
+        .await
+    }
+
+    /// Restores records from the recycle bin. The REST API has no equivalent of undelete, so this
+    /// is sent to the SOAP API using the same access token. A result is returned for each id
+    pub async fn undelete(&self, ids: &[&str]) -> SfResult<Vec<UndeleteResult>> {
+        // The endpoint is the root of the service itself, which has no trailing slash
+        let url = join_url(&self.instance_url, &Service::Soap.root(&self.version));
+        tracing::trace!(?url, "SOAP undelete request");
+
+        let ids = ids
+            .iter()
+            .map(|id| format!("<urn:ids>{}</urn:ids>", escape(id)))
+            .collect::<String>();
+        let envelope = format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="utf-8"?>"#,
+                r#"<soapenv:Envelope xmlns:soapenv="http://schemas.xmlsoap.org/soap/envelope/" xmlns:urn="urn:partner.soap.sforce.com">"#,
+                "<soapenv:Header><urn:SessionHeader><urn:sessionId>{}</urn:sessionId></urn:SessionHeader></soapenv:Header>",
+                "<soapenv:Body><urn:undelete>{}</urn:undelete></soapenv:Body>",
+                "</soapenv:Envelope>"
+            ),
+            escape(self.bearer.as_str()),
+            ids
+        );
+
+        self.send_with(
+            self.inner
+                .post(&url)
+                .header(CONTENT_TYPE, "text/xml; charset=UTF-8")
+                .header("SOAPAction", "undelete")
+                .body(envelope),
+            undelete_results,
+        )
+        .await
+    }
+}
+
+async fn undelete_results(response: Response) -> SfResult<Vec<UndeleteResult>> {
+    let status = response.status();
+    let headers = response.headers().clone();
+    let text = response.text().await?;
+
+    let envelope: Envelope =
+        quick_xml::de::from_str(&text).map_err(|err| Error::MalformedResponse {
+            error: err.to_string(),
+            body: text.clone(),
+        })?;
+
+    match (envelope.body.response, envelope.body.fault) {
+        (Some(response), _) if status == StatusCode::OK => Ok(response.result),
+        (_, Some(fault)) => Err(Error::ApiFailure(SfResponse {
+            headers,
+            status,
+            body: Some(vec![SfApiError {
+                error_code: fault
+                    .faultcode
+                    .rsplit(':')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                message: fault.faultstring,
+            }]),
+        })),
+        _ => Err(Error::UnknownApiFailure(SfResponse {
+            headers,
+            status,
+            body: Some(text),
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{body_string_contains, header, method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        authenticator::jwt::tests::add_token_mock, error::Error, tests::get_client, SfResponse,
+    };
+
+    #[tokio::test]
+    async fn test_query_deleted() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/queryAll/"))
+            .and(query_param(
+                "q",
+                "SELECT Id, Name FROM Account WHERE IsDeleted = true",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "totalSize": 1,
+                "done": true,
+                "records": [{
+                    "attributes": { "type": "Account", "url": "/services/data/v12345.0/sobjects/Account/001" },
+                    "Id": "001",
+                    "Name": "Acme"
+                }]
+            })))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let response = client
+            .query_deleted::<serde_json::Value>("Account", &["Id", "Name"])
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        assert_eq!(1, response.total_size);
+        assert_eq!("Acme", response.records[0].object["Name"]);
+    }
+
+    #[tokio::test]
+    async fn test_undelete() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/Soap/u/12345.0"))
+            .and(header("SOAPAction", "undelete"))
+            .and(body_string_contains(
+                "<urn:sessionId>access_token</urn:sessionId>",
+            ))
+            .and(body_string_contains(
+                "<urn:undelete><urn:ids>001</urn:ids><urn:ids>002</urn:ids></urn:undelete>",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<soapenv:Envelope xmlns:soapenv="http://schemas.xmlsoap.org/soap/envelope/" xmlns="urn:partner.soap.sforce.com">
+  <soapenv:Body>
+    <undeleteResponse>
+      <result>
+        <id>001</id>
+        <success>true</success>
+      </result>
+      <result>
+        <errors>
+          <message>entity is not in the recycle bin</message>
+          <statusCode>UNDELETE_FAILED</statusCode>
+        </errors>
+        <id>002</id>
+        <success>false</success>
+      </result>
+    </undeleteResponse>
+  </soapenv:Body>
+</soapenv:Envelope>"#,
+            ))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let results = client.undelete(&["001", "002"]).await.unwrap();
+
+        assert_eq!(2, results.len());
+        assert!(results[0].success);
+        assert!(results[0].errors.is_empty());
+        assert!(!results[1].success);
+        assert_eq!("UNDELETE_FAILED", results[1].errors[0].status_code);
+    }
+
+    #[tokio::test]
+    async fn test_undelete_fault() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/Soap/u/12345.0"))
+            .respond_with(ResponseTemplate::new(500).set_body_string(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<soapenv:Envelope xmlns:soapenv="http://schemas.xmlsoap.org/soap/envelope/" xmlns:sf="urn:fault.partner.soap.sforce.com">
+  <soapenv:Body>
+    <soapenv:Fault>
+      <faultcode>sf:INVALID_SESSION_ID</faultcode>
+      <faultstring>INVALID_SESSION_ID: Invalid Session ID found in SessionHeader</faultstring>
+    </soapenv:Fault>
+  </soapenv:Body>
+</soapenv:Envelope>"#,
+            ))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let err = client.undelete(&["001"]).await.unwrap_err();
+
+        match err {
+            Error::ApiFailure(response) => {
+                assert_eq!("INVALID_SESSION_ID", response.body.unwrap()[0].error_code)
+            }
+            err => panic!("Unexpected error {:?}", err),
+        }
+    }
+}
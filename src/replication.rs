@@ -0,0 +1,192 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+//! Records of an object that were updated or deleted within a time range, for replicating
+//! changes to another system
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::SfResult, SfClient, SfResponse};
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatedRecords {
+    pub ids: Vec<String>,
+    #[serde(with = "crate::serde::datetime")]
+    pub latest_date_covered: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletedRecords {
+    pub deleted_records: Vec<DeletedRecord>,
+    #[serde(with = "crate::serde::datetime")]
+    pub earliest_date_available: DateTime<Utc>,
+    #[serde(with = "crate::serde::datetime")]
+    pub latest_date_covered: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletedRecord {
+    pub id: String,
+    #[serde(with = "crate::serde::datetime")]
+    pub deleted_date: DateTime<Utc>,
+}
+
+// Salesforce rejects other representations of the range, and interprets datetimes without an
+// offset in the timezone of the org
+fn range_query(start: &DateTime<Utc>, end: &DateTime<Utc>) -> String {
+    let format = |datetime: &DateTime<Utc>| {
+        urlencoding::encode(&datetime.format("%Y-%m-%dT%H:%M:%S+00:00").to_string()).into_owned()
+    };
+
+    format!("start={}&end={}", format(start), format(end))
+}
+
+impl SfClient {
+    /// Lists the ids of the records of an object that were updated between `start` and `end`.
+    /// The range is truncated to the minute and may not be more than 30 days
+    pub async fn get_updated(
+        &self,
+        object: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> SfResult<SfResponse<UpdatedRecords>> {
+        self.get(&self.object_path(&format!(
+            "{}/updated/?{}",
+            object,
+            range_query(&start, &end)
+        )))
+        .await
+    }
+
+    /// Lists the records of an object that were deleted between `start` and `end`, as long as
+    /// they are still in the recycle bin
+    pub async fn get_deleted(
+        &self,
+        object: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> SfResult<SfResponse<DeletedRecords>> {
+        self.get(&self.object_path(&format!(
+            "{}/deleted/?{}",
+            object,
+            range_query(&start, &end)
+        )))
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, TimeZone, Utc};
+    use wiremock::{
+        matchers::{method, path, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{authenticator::jwt::tests::add_token_mock, tests::get_client, SfResponse};
+
+    use super::range_query;
+
+    #[test]
+    fn test_range_query() {
+        // Datetimes in other timezones are converted to UTC
+        let start = FixedOffset::west_opt(8 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2023, 1, 1, 19, 4, 5)
+            .unwrap()
+            .with_timezone(&Utc);
+        let end = Utc.with_ymd_and_hms(2023, 1, 3, 0, 0, 0).unwrap();
+
+        assert_eq!(
+            "start=2023-01-02T03%3A04%3A05%2B00%3A00&end=2023-01-03T00%3A00%3A00%2B00%3A00",
+            range_query(&start, &end)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_updated() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Account/updated/"))
+            .and(query_param("start", "2023-01-02T03:04:05+00:00"))
+            .and(query_param("end", "2023-01-03T00:00:00+00:00"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "ids": ["001000000000001AAA"],
+                "latestDateCovered": "2023-01-02T23:59:00.000+0000"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let updated = client
+            .get_updated(
+                "Account",
+                Utc.with_ymd_and_hms(2023, 1, 2, 3, 4, 5).unwrap(),
+                Utc.with_ymd_and_hms(2023, 1, 3, 0, 0, 0).unwrap(),
+            )
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        assert_eq!(vec!["001000000000001AAA".to_string()], updated.ids);
+        assert_eq!(
+            Utc.with_ymd_and_hms(2023, 1, 2, 23, 59, 0).unwrap(),
+            updated.latest_date_covered
+        );
+
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(
+            Some("start=2023-01-02T03%3A04%3A05%2B00%3A00&end=2023-01-03T00%3A00%3A00%2B00%3A00"),
+            requests.last().unwrap().url.query()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_deleted() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Account/deleted/"))
+            .and(query_param("start", "2023-01-02T00:00:00+00:00"))
+            .and(query_param("end", "2023-01-03T00:00:00+00:00"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "deletedRecords": [{
+                    "id": "001000000000002AAA",
+                    "deletedDate": "2023-01-02T12:00:00.000+0000"
+                }],
+                "earliestDateAvailable": "2022-12-01T00:00:00.000+0000",
+                "latestDateCovered": "2023-01-02T23:59:00.000+0000"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let deleted = client
+            .get_deleted(
+                "Account",
+                Utc.with_ymd_and_hms(2023, 1, 2, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2023, 1, 3, 0, 0, 0).unwrap(),
+            )
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        assert_eq!("001000000000002AAA", deleted.deleted_records[0].id);
+        assert_eq!(
+            Utc.with_ymd_and_hms(2023, 1, 2, 12, 0, 0).unwrap(),
+            deleted.deleted_records[0].deleted_date
+        );
+    }
+}
@@ -0,0 +1,192 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// Retries requests that failed with an API error code that indicates a transient condition, such
+/// as `UNABLE_TO_LOCK_ROW`. Retries back off exponentially from the initial backoff up to the
+/// maximum backoff. Requests are not retried by default
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub retryable_codes: Vec<String>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            retryable_codes: vec![
+                "REQUEST_LIMIT_EXCEEDED".to_string(),
+                "SERVER_UNAVAILABLE".to_string(),
+                "UNABLE_TO_LOCK_ROW".to_string(),
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The default retryable codes, retried up to the given number of times
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..Default::default()
+        }
+    }
+
+    pub fn backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub(crate) fn should_retry(&self, attempt: u32, err: &Error) -> bool {
+        if attempt >= self.max_retries {
+            return false;
+        }
+
+        match err {
+            Error::ApiFailure(response) | Error::QueryTimeout(response) => response
+                .body
+                .iter()
+                .flatten()
+                .any(|error| self.retryable_codes.contains(&error.error_code)),
+            _ => false,
+        }
+    }
+
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        authenticator::jwt::tests::add_token_mock, error::Error, tests::get_client, SfResponse,
+    };
+
+    use super::RetryPolicy;
+
+    fn error_body(code: &str) -> serde_json::Value {
+        serde_json::json!([{ "errorCode": code, "message": "Failed" }])
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::new(10).backoff(Duration::from_secs(1), Duration::from_secs(5));
+
+        assert_eq!(Duration::from_secs(1), policy.delay(0));
+        assert_eq!(Duration::from_secs(2), policy.delay(1));
+        assert_eq!(Duration::from_secs(4), policy.delay(2));
+        assert_eq!(Duration::from_secs(5), policy.delay(3));
+        assert_eq!(Duration::from_secs(5), policy.delay(40));
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_codes() {
+        for (status, code) in [
+            (403, "REQUEST_LIMIT_EXCEEDED"),
+            (503, "SERVER_UNAVAILABLE"),
+            (400, "UNABLE_TO_LOCK_ROW"),
+        ] {
+            let server = MockServer::start().await;
+            add_token_mock(&server).await;
+
+            Mock::given(method("PATCH"))
+                .and(path("/services/data/v12345.0/sobjects/Lead/123"))
+                .respond_with(ResponseTemplate::new(status).set_body_json(error_body(code)))
+                .up_to_n_times(2)
+                .expect(2)
+                .mount(&server)
+                .await;
+            Mock::given(method("PATCH"))
+                .and(path("/services/data/v12345.0/sobjects/Lead/123"))
+                .respond_with(ResponseTemplate::new(204))
+                .expect(1)
+                .mount(&server)
+                .await;
+
+            let mut client = get_client(&server).await;
+            client.with_retry_policy(
+                RetryPolicy::new(2).backoff(Duration::from_millis(1), Duration::from_millis(5)),
+            );
+
+            let response = client
+                .update_object("Lead", "123", serde_json::json!({ "Company": "Acme" }))
+                .await;
+
+            assert!(response.is_ok(), "{} should be retried", code);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v12345.0/sobjects/Lead/123"))
+            .respond_with(
+                ResponseTemplate::new(400).set_body_json(error_body("UNABLE_TO_LOCK_ROW")),
+            )
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let mut client = get_client(&server).await;
+        client.with_retry_policy(
+            RetryPolicy::new(2).backoff(Duration::from_millis(1), Duration::from_millis(5)),
+        );
+
+        let err = client
+            .get_object::<serde_json::Value>("Lead", "123")
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, Error::ApiFailure(_)));
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_other_codes() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/services/data/v12345.0/sobjects/Lead"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(error_body("DUPLICATE_VALUE")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut client = get_client(&server).await;
+        client.with_retry_policy(
+            RetryPolicy::new(2).backoff(Duration::from_millis(1), Duration::from_millis(5)),
+        );
+
+        let err = client
+            .create_object("Lead", serde_json::json!({ "Company": "Acme" }))
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap_err();
+
+        assert!(matches!(err, Error::ApiFailure(_)));
+    }
+}
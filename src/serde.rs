@@ -0,0 +1,428 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+//! Helpers for use with `#[serde(with = "...")]` on record fields whose format differs from the
+//! chrono defaults or whose picklist values may change, and for partial updates
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A field of a partial update. Salesforce leaves fields that are omitted from an update
+/// unchanged, while fields sent as `null` are cleared.
+///
+/// An `Option` field with `skip_serializing_if = "Option::is_none"` can only leave a field
+/// unchanged, and one without it always clears the field when `None`. `FieldValue` distinguishes
+/// the two when paired with [`FieldValue::is_leave`], and serializing a `Leave` that is not
+/// skipped is an error:
+///
+/// ```
+/// use serde::Serialize;
+/// use sf_client::serde::FieldValue;
+///
+/// #[derive(Default, Serialize)]
+/// #[serde(rename_all = "PascalCase")]
+/// struct LeadUpdate {
+///     #[serde(skip_serializing_if = "FieldValue::is_leave")]
+///     company: FieldValue<String>,
+///     #[serde(skip_serializing_if = "FieldValue::is_leave")]
+///     phone: FieldValue<String>,
+/// }
+///
+/// let update = LeadUpdate {
+///     phone: FieldValue::SetNull,
+///     ..Default::default()
+/// };
+/// assert_eq!(r#"{"Phone":null}"#, serde_json::to_string(&update).unwrap());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum FieldValue<T> {
+    Set(T),
+    SetNull,
+    #[default]
+    Leave,
+}
+
+impl<T> FieldValue<T> {
+    pub fn is_leave(&self) -> bool {
+        matches!(self, Self::Leave)
+    }
+}
+
+impl<T> From<Option<T>> for FieldValue<T> {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(value) => Self::Set(value),
+            None => Self::SetNull,
+        }
+    }
+}
+
+// A field that is left unchanged must be skipped, as any value sent for it would change the field
+impl<T> Serialize for FieldValue<T>
+where
+    T: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Self::Set(value) => value.serialize(serializer),
+            Self::SetNull => serializer.serialize_none(),
+            Self::Leave => Err(serde::ser::Error::custom(
+                "FieldValue::Leave must be skipped with `skip_serializing_if = \"FieldValue::is_leave\"`",
+            )),
+        }
+    }
+}
+
+// Missing fields are only deserialized as `Leave` when the field is marked `#[serde(default)]`
+impl<'de, T> Deserialize<'de> for FieldValue<T>
+where
+    T: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Option::deserialize(deserializer)?.into())
+    }
+}
+
+/// An enum of picklist values with a variant that holds any value it does not know of. Admins can
+/// add picklist values at any time, and a derived enum fails to deserialize the whole record when
+/// one is returned. Fields with [`lenient`] or [`lenient_option`] keep the new value instead.
+///
+/// Fields with [`lenient_option`] also need `#[serde(default)]`. Serde only treats a missing field
+/// as `None` when it deserializes the field itself, so without it records that omit the field fail
+/// to deserialize:
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use sf_client::serde::UnknownVariant;
+///
+/// #[derive(Debug, Deserialize, PartialEq, Serialize)]
+/// enum Stage {
+///     Prospecting,
+///     #[serde(rename = "Closed Won")]
+///     ClosedWon,
+///     #[serde(skip)]
+///     Unknown(String),
+/// }
+///
+/// impl UnknownVariant for Stage {
+///     fn unknown(value: String) -> Self {
+///         Self::Unknown(value)
+///     }
+///
+///     fn as_unknown(&self) -> Option<&str> {
+///         match self {
+///             Self::Unknown(value) => Some(value),
+///             _ => None,
+///         }
+///     }
+/// }
+///
+/// #[derive(Debug, Deserialize, Serialize)]
+/// struct Opportunity {
+///     #[serde(rename = "StageName", with = "sf_client::serde::lenient")]
+///     stage: Stage,
+///     #[serde(
+///         rename = "PreviousStage__c",
+///         default,
+///         with = "sf_client::serde::lenient_option"
+///     )]
+///     previous_stage: Option<Stage>,
+/// }
+///
+/// let opportunity: Opportunity = serde_json::from_str(r#"{"StageName":"Negotiation"}"#).unwrap();
+/// assert_eq!(Stage::Unknown("Negotiation".to_string()), opportunity.stage);
+/// assert_eq!(None, opportunity.previous_stage);
+/// assert_eq!(
+///     r#"{"StageName":"Negotiation","PreviousStage__c":null}"#,
+///     serde_json::to_string(&opportunity).unwrap()
+/// );
+/// ```
+pub trait UnknownVariant: Sized {
+    fn unknown(value: String) -> Self;
+
+    /// The value held by the unknown variant, or `None` for known variants
+    fn as_unknown(&self) -> Option<&str>;
+}
+
+/// Picklist fields as an enum that implements [`UnknownVariant`]
+pub mod lenient {
+    use serde::{
+        de::{value::Error, DeserializeOwned, IntoDeserializer},
+        Deserialize, Deserializer, Serialize, Serializer,
+    };
+
+    use super::UnknownVariant;
+
+    pub fn serialize<S, T>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize + UnknownVariant,
+    {
+        match value.as_unknown() {
+            Some(unknown) => serializer.serialize_str(unknown),
+            None => value.serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: DeserializeOwned + UnknownVariant,
+    {
+        let value = String::deserialize(deserializer)?;
+
+        Ok(
+            T::deserialize(IntoDeserializer::<Error>::into_deserializer(value.as_str()))
+                .unwrap_or_else(|_| T::unknown(value)),
+        )
+    }
+}
+
+/// Nullable picklist fields as an optional enum that implements [`UnknownVariant`]. The field must
+/// also have `#[serde(default)]` to deserialize records that omit it
+pub mod lenient_option {
+    use serde::{
+        de::{DeserializeOwned, IntoDeserializer},
+        Deserialize, Deserializer, Serialize, Serializer,
+    };
+
+    use super::UnknownVariant;
+
+    pub fn serialize<S, T>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize + UnknownVariant,
+    {
+        match value {
+            Some(value) => super::lenient::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: DeserializeOwned + UnknownVariant,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|value| super::lenient::deserialize(value.into_deserializer()))
+            .transpose()
+    }
+}
+
+/// Datetime fields, such as `2023-01-02T03:04:05.000+0000`, as a `DateTime<Utc>`
+pub mod datetime {
+    use chrono::{DateTime, Utc};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3f%z";
+
+    pub fn serialize<S>(value: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(&value.format(FORMAT))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+
+        // Fall back to RFC 3339 for values written with a `Z` or `+00:00` offset
+        DateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S%.f%z")
+            .or_else(|_| DateTime::parse_from_rfc3339(&value))
+            .map(|datetime| datetime.with_timezone(&Utc))
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Date fields, such as `2023-01-02`, as a `NaiveDate`
+pub mod date {
+    use chrono::NaiveDate;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%Y-%m-%d";
+
+    pub fn serialize<S>(value: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(&value.format(FORMAT))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveDate, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = String::deserialize(deserializer)?;
+        NaiveDate::parse_from_str(&value, FORMAT).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use super::{FieldValue, UnknownVariant};
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Record {
+        #[serde(rename = "CreatedDate", with = "super::datetime")]
+        created_date: DateTime<Utc>,
+        #[serde(rename = "CloseDate", with = "super::date")]
+        close_date: NaiveDate,
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let record: Record = serde_json::from_value(json!({
+            "CreatedDate": "2023-01-02T03:04:05.000+0000",
+            "CloseDate": "2023-01-02"
+        }))
+        .unwrap();
+
+        assert_eq!(
+            Record {
+                created_date: Utc.with_ymd_and_hms(2023, 1, 2, 3, 4, 5).unwrap(),
+                close_date: NaiveDate::from_ymd_opt(2023, 1, 2).unwrap(),
+            },
+            record
+        );
+        assert_eq!(
+            json!({
+                "CreatedDate": "2023-01-02T03:04:05.000+0000",
+                "CloseDate": "2023-01-02"
+            }),
+            serde_json::to_value(&record).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_datetime_offsets() {
+        let expected = Utc.with_ymd_and_hms(2023, 1, 2, 3, 4, 5).unwrap();
+
+        for value in [
+            "2023-01-02T03:04:05.000+0000",
+            "2023-01-01T19:04:05.000-0800",
+            "2023-01-02T03:04:05Z",
+            "2023-01-02T03:04:05.000+00:00",
+        ] {
+            let record: Record = serde_json::from_value(json!({
+                "CreatedDate": value,
+                "CloseDate": "2023-01-02"
+            }))
+            .unwrap();
+
+            assert_eq!(expected, record.created_date, "{}", value);
+        }
+    }
+
+    #[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+    struct LeadUpdate {
+        #[serde(
+            rename = "Company",
+            default,
+            skip_serializing_if = "FieldValue::is_leave"
+        )]
+        company: FieldValue<String>,
+        #[serde(
+            rename = "Phone",
+            default,
+            skip_serializing_if = "FieldValue::is_leave"
+        )]
+        phone: FieldValue<String>,
+        #[serde(
+            rename = "Email",
+            default,
+            skip_serializing_if = "FieldValue::is_leave"
+        )]
+        email: FieldValue<String>,
+    }
+
+    #[test]
+    fn test_field_value() {
+        let update = LeadUpdate {
+            company: FieldValue::Set("Acme".to_string()),
+            phone: FieldValue::SetNull,
+            email: FieldValue::Leave,
+        };
+        let value = json!({ "Company": "Acme", "Phone": null });
+
+        assert_eq!(value, serde_json::to_value(&update).unwrap());
+        assert_eq!(update, serde_json::from_value(value).unwrap());
+
+        // A field that is not skipped is rejected rather than cleared
+        assert!(serde_json::to_value(FieldValue::<String>::Leave).is_err());
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    enum Status {
+        Open,
+        #[serde(rename = "Closed - Converted")]
+        Converted,
+        #[serde(skip)]
+        Unknown(String),
+    }
+
+    impl UnknownVariant for Status {
+        fn unknown(value: String) -> Self {
+            Self::Unknown(value)
+        }
+
+        fn as_unknown(&self) -> Option<&str> {
+            match self {
+                Self::Unknown(value) => Some(value),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Lead {
+        #[serde(rename = "Status", with = "super::lenient")]
+        status: Status,
+        #[serde(rename = "PreviousStatus__c", default, with = "super::lenient_option")]
+        previous_status: Option<Status>,
+    }
+
+    #[test]
+    fn test_lenient() {
+        for (value, status) in [
+            ("Open", Status::Open),
+            ("Closed - Converted", Status::Converted),
+            ("Nurturing", Status::Unknown("Nurturing".to_string())),
+        ] {
+            let record = json!({ "Status": value, "PreviousStatus__c": value });
+            let lead: Lead = serde_json::from_value(record.clone()).unwrap();
+
+            assert_eq!(status, lead.status);
+            assert_eq!(Some(&status), lead.previous_status.as_ref());
+            assert_eq!(record, serde_json::to_value(&lead).unwrap());
+        }
+
+        let lead: Lead =
+            serde_json::from_value(json!({ "Status": "Open", "PreviousStatus__c": null })).unwrap();
+        assert_eq!(None, lead.previous_status);
+
+        let lead: Lead = serde_json::from_value(json!({ "Status": "Open" })).unwrap();
+        assert_eq!(None, lead.previous_status);
+
+        // Values that are not strings are still rejected
+        assert!(
+            serde_json::from_value::<Lead>(json!({ "Status": 1, "PreviousStatus__c": null }))
+                .is_err()
+        );
+    }
+}
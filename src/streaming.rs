@@ -0,0 +1,548 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+//! A client of the Streaming API, which delivers PushTopic and platform event messages through
+//! CometD long polling
+
+use futures::{stream, Stream, TryStreamExt};
+use reqwest::{
+    header::{COOKIE, SET_COOKIE},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use crate::{
+    error::{Error, SfResult},
+    util::{handle_response, join_url},
+    SfClient,
+};
+
+const HANDSHAKE: &str = "/meta/handshake";
+const SUBSCRIBE: &str = "/meta/subscribe";
+const CONNECT: &str = "/meta/connect";
+
+// Polls that fail to reach the server are retried this many times in a row before the
+// subscription fails
+const MAX_POLL_RETRIES: u32 = 3;
+
+// The least time to wait before retrying a poll that failed to reach the server, for when the
+// server has advised polling again immediately
+const MIN_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Where a subscription starts from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayFrom {
+    /// Only messages published after subscribing
+    Latest,
+    /// Every message that is retained, which is those of the past 72 hours
+    Earliest,
+    /// The messages after the message with the given replay id
+    ReplayId(i64),
+}
+
+impl ReplayFrom {
+    fn replay_id(&self) -> i64 {
+        match self {
+            Self::Latest => -1,
+            Self::Earliest => -2,
+            Self::ReplayId(replay_id) => *replay_id,
+        }
+    }
+}
+
+/// A message received on a channel. Platform events and Change Data Capture events have their
+/// fields in `payload`, while PushTopic messages have the fields of the record in `sobject`
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct StreamingMessage {
+    pub channel: String,
+    pub data: Value,
+}
+
+impl StreamingMessage {
+    /// The id to resume a subscription after this message with [`ReplayFrom::ReplayId`]
+    pub fn replay_id(&self) -> Option<i64> {
+        self.data["event"]["replayId"].as_i64()
+    }
+
+    /// The event or record of the message
+    pub fn payload(&self) -> Option<&Value> {
+        self.data
+            .get("payload")
+            .or_else(|| self.data.get("sobject"))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct BayeuxMessage {
+    channel: String,
+    client_id: Option<String>,
+    successful: Option<bool>,
+    error: Option<String>,
+    advice: Option<Advice>,
+    data: Option<Value>,
+}
+
+impl BayeuxMessage {
+    #[allow(clippy::result_large_err)]
+    fn check(self, channel: &str) -> SfResult<Self> {
+        if self.successful == Some(true) {
+            Ok(self)
+        } else {
+            Err(self.failure(channel))
+        }
+    }
+
+    fn failure(&self, channel: &str) -> Error {
+        Error::Streaming(format!(
+            "{} failed: {}",
+            channel,
+            self.error.as_deref().unwrap_or("unknown error")
+        ))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Advice {
+    reconnect: Option<String>,
+    interval: Option<u64>,
+}
+
+/// Subscribes to channels of the Streaming API with the access token of a client. The token is
+/// captured when the streaming client is created and is never refreshed, so subscriptions fail
+/// once it expires and must be recreated from a client with a new token
+#[derive(Debug)]
+pub struct StreamingClient {
+    client: SfClient,
+    url: String,
+}
+
+impl StreamingClient {
+    pub fn new(client: &SfClient) -> Self {
+        Self {
+            client: client.with_access_token(client.bearer.clone()),
+            url: join_url(
+                &client.instance_url,
+                &format!("cometd/{}", client.version.trim_start_matches('v')),
+            ),
+        }
+    }
+
+    /// Subscribes to a channel, such as `/event/Order_Placed__e` or `/topic/NewLeads`. The
+    /// subscription is renewed when the server drops it, resuming after the last message
+    /// received, and polls that fail to reach the server are retried a few times after the
+    /// interval advised by the server. The stream ends when the server closes the connection and
+    /// advises against reconnecting, and fails with [`Error::Streaming`] when a poll fails and the
+    /// server advises against reconnecting
+    pub async fn subscribe(
+        self,
+        channel: &str,
+        replay_from: ReplayFrom,
+    ) -> SfResult<impl Stream<Item = SfResult<StreamingMessage>>> {
+        let mut session = Session {
+            client: self,
+            channel: channel.to_string(),
+            replay_from,
+            client_id: String::new(),
+            cookies: None,
+            interval: Duration::ZERO,
+            failures: 0,
+        };
+        session.start().await?;
+
+        // Messages received along with a failed poll are yielded before its error
+        Ok(stream::try_unfold(Ok(Some(session)), |session| async move {
+            let Some(mut session) = session? else {
+                return Ok::<_, Error>(None);
+            };
+
+            let (messages, reconnect) = session.poll().await?;
+            let session = match reconnect {
+                Reconnect::Poll => Ok(Some(session)),
+                Reconnect::Handshake => {
+                    session.start().await?;
+                    Ok(Some(session))
+                }
+                Reconnect::Close => Ok(None),
+                Reconnect::Fail(err) => Err(err),
+            };
+
+            Ok(Some((stream::iter(messages.into_iter().map(Ok)), session)))
+        })
+        .try_flatten())
+    }
+}
+
+// What to do after a poll, following the advice of the server
+enum Reconnect {
+    Poll,
+    Handshake,
+    Close,
+    Fail(Error),
+}
+
+struct Session {
+    client: StreamingClient,
+    channel: String,
+    replay_from: ReplayFrom,
+    client_id: String,
+    // Requests must be routed to the server that handled the handshake by its cookies
+    cookies: Option<String>,
+    interval: Duration,
+    // Polls in a row that failed to reach the server
+    failures: u32,
+}
+
+impl Session {
+    async fn post(&mut self, message: Value) -> SfResult<Vec<BayeuxMessage>> {
+        let client = &self.client.client;
+        let mut request = client
+            .inner
+            .post(&self.client.url)
+            .bearer_auth(&client.bearer)
+            .json(&[message]);
+        if let Some(cookies) = &self.cookies {
+            request = request.header(COOKIE, cookies);
+        }
+
+        let (cookies, messages) = client
+            .send_with(request, |response| async move {
+                let cookies = response
+                    .headers()
+                    .get_all(SET_COOKIE)
+                    .iter()
+                    .filter_map(|value| value.to_str().ok())
+                    .filter_map(|value| value.split(';').next())
+                    .map(str::to_string)
+                    .collect::<Vec<_>>();
+
+                let messages = handle_response(response, &[StatusCode::OK])
+                    .await?
+                    .into_body()?;
+
+                Ok((cookies, messages))
+            })
+            .await?;
+
+        if !cookies.is_empty() {
+            self.cookies = Some(cookies.join("; "));
+        }
+
+        Ok(messages)
+    }
+
+    // Performs the handshake and subscribes to the channel, resuming after the last message
+    async fn start(&mut self) -> SfResult<()> {
+        self.cookies = None;
+
+        let handshake = self
+            .post(json!({
+                "channel": HANDSHAKE,
+                "version": "1.0",
+                "supportedConnectionTypes": ["long-polling"]
+            }))
+            .await?
+            .into_iter()
+            .find(|message| message.channel == HANDSHAKE)
+            .ok_or_else(|| Error::MissingSubresponse(HANDSHAKE.to_string()))?
+            .check(HANDSHAKE)?;
+
+        self.client_id = handshake
+            .client_id
+            .ok_or_else(|| Error::Streaming("Handshake did not return a client id".to_string()))?;
+
+        tracing::debug!(channel = self.channel, replay_from = ?self.replay_from, "Subscribing to channel");
+
+        self.post(json!({
+            "channel": SUBSCRIBE,
+            "clientId": self.client_id,
+            "subscription": self.channel,
+            "ext": { "replay": { &self.channel: self.replay_from.replay_id() } }
+        }))
+        .await?
+        .into_iter()
+        .find(|message| message.channel == SUBSCRIBE)
+        .ok_or_else(|| Error::MissingSubresponse(SUBSCRIBE.to_string()))?
+        .check(SUBSCRIBE)?;
+
+        Ok(())
+    }
+
+    // Waits for messages, returning them along with the reconnect advice of the server
+    async fn poll(&mut self) -> SfResult<(Vec<StreamingMessage>, Reconnect)> {
+        let interval = match self.failures {
+            0 => self.interval,
+            _ => self.interval.max(MIN_RETRY_INTERVAL),
+        };
+        if !interval.is_zero() {
+            tokio::time::sleep(interval).await;
+        }
+
+        let result = self
+            .post(json!({
+                "channel": CONNECT,
+                "clientId": self.client_id,
+                "connectionType": "long-polling"
+            }))
+            .await;
+
+        let responses = match result {
+            Ok(responses) => {
+                self.failures = 0;
+                responses
+            }
+            Err(err @ (Error::Client(_) | Error::Connect(_) | Error::Timeout(_)))
+                if self.failures < MAX_POLL_RETRIES =>
+            {
+                self.failures += 1;
+                tracing::warn!(channel = self.channel, failures = self.failures, error = %err, "Retrying failed poll");
+
+                return Ok((Vec::new(), Reconnect::Poll));
+            }
+            Err(err) => return Err(err),
+        };
+
+        let mut messages = Vec::new();
+        let mut reconnect = Reconnect::Poll;
+
+        for mut response in responses {
+            if response.channel == CONNECT {
+                let advice = response.advice.take().unwrap_or_default();
+                if let Some(interval) = advice.interval {
+                    self.interval = Duration::from_millis(interval);
+                }
+
+                // Connections that fail without advice, such as for an expired client id,
+                // require a new handshake, while those the server will not accept again fail
+                reconnect = match (response.successful, advice.reconnect.as_deref()) {
+                    (Some(false), None | Some("handshake")) => Reconnect::Handshake,
+                    (Some(false), Some("none")) => Reconnect::Fail(response.failure(CONNECT)),
+                    (_, Some("handshake")) => Reconnect::Handshake,
+                    (_, Some("none")) => Reconnect::Close,
+                    _ => Reconnect::Poll,
+                };
+            } else if let Some(data) = response.data {
+                let message = StreamingMessage {
+                    channel: response.channel,
+                    data,
+                };
+                if let Some(replay_id) = message.replay_id() {
+                    self.replay_from = ReplayFrom::ReplayId(replay_id);
+                }
+
+                messages.push(message);
+            }
+        }
+
+        Ok((messages, reconnect))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+    use serde_json::json;
+    use std::time::Duration;
+    use wiremock::{
+        matchers::{body_partial_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{authenticator::jwt::tests::add_token_mock, tests::get_client};
+
+    use super::{ReplayFrom, StreamingClient};
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/cometd/12345.0"))
+            .and(body_partial_json(json!([{ "channel": "/meta/handshake" }])))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Set-Cookie", "BAYEUX_BROWSER=abc; Path=/")
+                    .set_body_json(json!([{
+                        "channel": "/meta/handshake",
+                        "clientId": "client-1",
+                        "successful": true
+                    }])),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/cometd/12345.0"))
+            .and(header("Cookie", "BAYEUX_BROWSER=abc"))
+            .and(body_partial_json(json!([{
+                "channel": "/meta/subscribe",
+                "clientId": "client-1",
+                "subscription": "/event/Order__e",
+                "ext": { "replay": { "/event/Order__e": -2 } }
+            }])))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([{
+                "channel": "/meta/subscribe",
+                "subscription": "/event/Order__e",
+                "successful": true
+            }])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/cometd/12345.0"))
+            .and(body_partial_json(json!([{ "channel": "/meta/connect" }])))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                {
+                    "channel": "/event/Order__e",
+                    "data": {
+                        "schema": "schema-1",
+                        "payload": { "OrderNumber__c": "1001" },
+                        "event": { "replayId": 7 }
+                    }
+                },
+                { "channel": "/meta/connect", "successful": true, "advice": { "interval": 0 } }
+            ])))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/cometd/12345.0"))
+            .and(body_partial_json(json!([{ "channel": "/meta/connect" }])))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([{
+                "channel": "/meta/connect",
+                "successful": false,
+                "error": "403::Unknown client",
+                "advice": { "reconnect": "none" }
+            }])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let mut messages = Box::pin(
+            StreamingClient::new(&client)
+                .subscribe("/event/Order__e", ReplayFrom::Earliest)
+                .await
+                .unwrap(),
+        );
+
+        let message = messages.try_next().await.unwrap().unwrap();
+        assert_eq!("/event/Order__e", message.channel);
+        assert_eq!(Some(7), message.replay_id());
+        assert_eq!("1001", message.payload().unwrap()["OrderNumber__c"]);
+
+        // A poll that fails without a retry is an error rather than the end of the stream
+        assert_eq!(
+            "Streaming API request failed /meta/connect failed: 403::Unknown client",
+            messages.try_next().await.unwrap_err().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_retries_failed_poll() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        for channel in ["/meta/handshake", "/meta/subscribe"] {
+            Mock::given(method("POST"))
+                .and(path("/cometd/12345.0"))
+                .and(body_partial_json(json!([{ "channel": channel }])))
+                .respond_with(ResponseTemplate::new(200).set_body_json(json!([{
+                    "channel": channel,
+                    "clientId": "client-1",
+                    "successful": true
+                }])))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        // The first poll outlasts the timeout of the client
+        Mock::given(method("POST"))
+            .and(path("/cometd/12345.0"))
+            .and(body_partial_json(json!([{ "channel": "/meta/connect" }])))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/cometd/12345.0"))
+            .and(body_partial_json(json!([{ "channel": "/meta/connect" }])))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                { "channel": "/event/Order__e", "data": { "payload": {} } },
+                { "channel": "/meta/connect", "successful": true, "advice": { "reconnect": "none" } }
+            ])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut client = get_client(&server).await;
+        client.inner = reqwest::Client::builder()
+            .timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+
+        let messages = StreamingClient::new(&client)
+            .subscribe("/event/Order__e", ReplayFrom::Latest)
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(1, messages.len());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_failure() {
+        let server = MockServer::start().await;
+        add_token_mock(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/cometd/12345.0"))
+            .and(body_partial_json(json!([{ "channel": "/meta/handshake" }])))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([{
+                "channel": "/meta/handshake",
+                "clientId": "client-1",
+                "successful": true
+            }])))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path("/cometd/12345.0"))
+            .and(body_partial_json(json!([{ "channel": "/meta/subscribe" }])))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([{
+                "channel": "/meta/subscribe",
+                "successful": false,
+                "error": "403::Unknown channel"
+            }])))
+            .mount(&server)
+            .await;
+
+        let client = get_client(&server).await;
+        let err = StreamingClient::new(&client)
+            .subscribe("/event/Unknown__e", ReplayFrom::Latest)
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            "Streaming API request failed /meta/subscribe failed: 403::Unknown channel",
+            err.to_string()
+        );
+    }
+}
@@ -0,0 +1,96 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+use reqwest::{Request, StatusCode};
+use std::time::Instant;
+
+const REQUESTS: &str = "sf_client_requests_total";
+const REQUEST_DURATION: &str = "sf_client_request_duration_seconds";
+const RETRIES: &str = "sf_client_retries_total";
+
+// Groups resources into a small number of categories so that metrics have bounded cardinality
+fn endpoint(path: &str) -> &'static str {
+    let mut segments = path.trim_start_matches('/').split('/');
+
+    match (segments.next(), segments.next(), segments.nth(1)) {
+        (Some("services"), Some("async"), _) => "bulk",
+        (Some("services"), Some("data"), Some("sobjects")) => "sobjects",
+        (Some("services"), Some("data"), Some("query" | "queryAll")) => "query",
+        (Some("services"), Some("data"), Some("composite")) => "composite",
+        (Some("services"), Some("data"), Some("jobs")) => "bulk",
+        _ => "other",
+    }
+}
+
+/// Records the count and latency of a single attempt of a request
+pub struct RequestTimer {
+    method: String,
+    endpoint: &'static str,
+    start: Instant,
+}
+
+impl RequestTimer {
+    pub fn start(request: &Request) -> Self {
+        Self {
+            method: request.method().to_string(),
+            endpoint: endpoint(request.url().path()),
+            start: Instant::now(),
+        }
+    }
+
+    /// Records the status of the response, or an error for requests that received no response
+    pub fn finish(self, status: Option<StatusCode>) {
+        let status = match status {
+            Some(status) => status.as_str().to_string(),
+            None => "error".to_string(),
+        };
+
+        metrics::increment_counter!(
+            REQUESTS,
+            "method" => self.method.clone(),
+            "endpoint" => self.endpoint,
+            "status" => status
+        );
+        metrics::histogram!(
+            REQUEST_DURATION,
+            self.start.elapsed(),
+            "method" => self.method,
+            "endpoint" => self.endpoint
+        );
+    }
+}
+
+pub fn record_retry(request: &Request) {
+    metrics::increment_counter!(
+        RETRIES,
+        "method" => request.method().to_string(),
+        "endpoint" => endpoint(request.url().path())
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::endpoint;
+
+    #[test]
+    fn test_endpoint() {
+        assert_eq!(
+            "sobjects",
+            endpoint("/services/data/v58.0/sobjects/Lead/123")
+        );
+        assert_eq!("query", endpoint("/services/data/v58.0/query/"));
+        assert_eq!("query", endpoint("/services/data/v58.0/queryAll/"));
+        assert_eq!("composite", endpoint("/services/data/v58.0/composite"));
+        assert_eq!(
+            "composite",
+            endpoint("/services/data/v58.0/composite/sobjects/Lead")
+        );
+        assert_eq!("bulk", endpoint("/services/data/v58.0/jobs/ingest/750"));
+        assert_eq!("bulk", endpoint("/services/async/58.0/job"));
+        assert_eq!("other", endpoint("/services/data/v58.0/limits"));
+        assert_eq!("other", endpoint("/services/data/"));
+    }
+}
@@ -0,0 +1,122 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+// Copyright 2023 Oxide Computer Company
+
+//! Helpers for testing code that uses [`crate::SfClient`] against a mock server
+
+use async_trait::async_trait;
+use std::fmt;
+
+use crate::{
+    authenticator::{SfAccessToken, SfUserInfo},
+    error::{Error, SfResult},
+    util::Redacted,
+    Authenticator,
+};
+
+/// An authenticator that returns a fixed token without making any requests. Point the instance
+/// url at a mock server to construct a client that sends all of its requests there
+#[derive(Clone)]
+pub struct MockAuthenticator {
+    token: SfAccessToken,
+    user_info: Option<SfUserInfo>,
+}
+
+impl fmt::Debug for MockAuthenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockAuthenticator")
+            .field("token", &Redacted)
+            .field("instance_url", &self.token.instance_url)
+            .field("user_info", &self.user_info)
+            .finish()
+    }
+}
+
+impl MockAuthenticator {
+    pub fn new(access_token: &str, instance_url: &str) -> Self {
+        Self {
+            token: SfAccessToken {
+                access_token: access_token.to_string(),
+                scope: String::new(),
+                instance_url: instance_url.to_string(),
+                id: String::new(),
+                token_type: "Bearer".to_string(),
+            },
+            user_info: None,
+        }
+    }
+
+    /// Sets the user info that is returned by [`Authenticator::user_info`]. Requesting user info
+    /// fails if none has been set
+    pub fn with_user_info(&mut self, user_info: SfUserInfo) -> &mut Self {
+        self.user_info = Some(user_info);
+        self
+    }
+}
+
+#[async_trait]
+impl Authenticator for MockAuthenticator {
+    async fn get_token(&self) -> SfResult<SfAccessToken> {
+        Ok(self.token.clone())
+    }
+
+    async fn user_info(&self) -> SfResult<SfUserInfo> {
+        self.user_info
+            .clone()
+            .ok_or_else(|| Error::InvalidInput("No user info was configured".to_string()))
+    }
+
+    fn auth_url(&self) -> Option<&str> {
+        Some(&self.token.instance_url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{Authenticator, SfClient, SfResponse};
+
+    use super::MockAuthenticator;
+
+    #[tokio::test]
+    async fn test_mock_authenticator() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/services/data/v58.0/sobjects/Lead/00Q000000000001"))
+            .and(header("Authorization", "Bearer mock_token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "Id": "00Q000000000001"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let authenticator = MockAuthenticator::new("mock_token", &server.uri());
+        assert!(authenticator.user_info().await.is_err());
+
+        let client = SfClient::new("58.0".to_string(), authenticator)
+            .await
+            .unwrap();
+        let lead = client
+            .get_object::<serde_json::Value>("Lead", "00Q000000000001")
+            .await
+            .and_then(SfResponse::into_body)
+            .unwrap();
+
+        assert_eq!("00Q000000000001", lead["Id"]);
+    }
+
+    #[test]
+    fn test_mock_authenticator_debug_redacts_token() {
+        let authenticator = MockAuthenticator::new("mock_token", "http://localhost");
+
+        assert!(!format!("{:?}", authenticator).contains("mock_token"));
+    }
+}
@@ -4,32 +4,551 @@
 
 // Copyright 2023 Oxide Computer Company
 
-use serde::de::DeserializeOwned;
-use std::any::{Any, TypeId};
+use chrono::{DateTime, Utc};
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE, RETRY_AFTER},
+    Response, StatusCode, Url,
+};
+use serde::{
+    de::{DeserializeOwned, DeserializeSeed},
+    Deserialize,
+};
+use serde_json::Value;
+use std::{
+    any::{Any, TypeId},
+    fmt,
+    time::Duration,
+};
 
-use crate::error::{Error, SfResult};
+use crate::{
+    error::{Error, SfResult},
+    SfApiError, SfResponse,
+};
+
+/// Stands in for secrets such as access tokens, passwords and private keys in the `Debug` output
+/// of the types that hold them. Those types implement `Debug` manually with this in place of each
+/// secret, so that secrets are never written to logs
+pub struct Redacted;
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
 
 pub fn is_unit<T: Any>() -> bool {
     TypeId::of::<T>() == TypeId::of::<()>()
 }
 
+// The unit value as a `T`, if `T` is the unit type
+fn unit<T: Any>() -> Option<T> {
+    let unit: Box<dyn Any> = Box::new(());
+    unit.downcast().ok().map(|unit| *unit)
+}
+
+#[allow(clippy::result_large_err)]
 pub fn deser_body<T>(body: &str) -> SfResult<T>
 where
     T: DeserializeOwned,
 {
+    // simd-json parses in place, so works on a copy of the body. Bodies that it fails to parse
+    // are parsed again by serde_json to report the error
+    #[cfg(feature = "simd-json")]
+    if let Ok(value) = simd_json::serde::from_slice(&mut body.as_bytes().to_vec()) {
+        return Ok(value);
+    }
+
     serde_json::from_str(body).map_err(|error| Error::UnexpectedBody {
         error,
         body: body.to_string(),
     })
 }
 
+// Deserializes a body with a seed, such as to filter it while it is parsed, parsing it with
+// simd-json when enabled like deser_body. The seed is cloned to parse the body a second time
+#[allow(clippy::result_large_err)]
+pub fn deser_body_seed<S, T>(body: &str, seed: S) -> SfResult<T>
+where
+    S: for<'de> DeserializeSeed<'de, Value = T> + Clone,
+{
+    #[cfg(feature = "simd-json")]
+    if let Ok(value) = simd_json::Deserializer::from_slice(&mut body.as_bytes().to_vec())
+        .and_then(|mut deserializer| seed.clone().deserialize(&mut deserializer))
+    {
+        return Ok(value);
+    }
+
+    let mut deserializer = serde_json::Deserializer::from_str(body);
+    seed.deserialize(&mut deserializer)
+        .and_then(|value| deserializer.end().map(|_| value))
+        .map_err(|error| Error::UnexpectedBody {
+            error,
+            body: body.to_string(),
+        })
+}
+
+// Most resources respond with an array of errors, but some such as composite and bulk
+// subresources respond with a single error object
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum ApiErrors {
+    Many(Vec<SfApiError>),
+    One(SfApiError),
+}
+
+impl From<ApiErrors> for Vec<SfApiError> {
+    fn from(errors: ApiErrors) -> Self {
+        match errors {
+            ApiErrors::Many(errors) => errors,
+            ApiErrors::One(error) => vec![error],
+        }
+    }
+}
+
+#[allow(clippy::result_large_err)]
+pub fn deser_errors(body: &str) -> SfResult<Vec<SfApiError>> {
+    deser_body::<ApiErrors>(body).map(Into::into)
+}
+
+// Retry-After may either be a number of seconds or an HTTP date
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => DateTime::parse_from_rfc2822(value).ok().map(|date| {
+            (date.with_timezone(&Utc) - Utc::now())
+                .to_std()
+                .unwrap_or_default()
+        }),
+    }
+}
+
+/// Appends a path, which may include a query, to a base url such as an instance url. Slashes
+/// between the two are normalized and characters that are not allowed in a url are encoded
+pub fn join_url(base: &str, path: &str) -> String {
+    let (path, query) = match path.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path, None),
+    };
+    let path = path.trim_start_matches('/');
+
+    let Ok(mut url) = Url::parse(base) else {
+        let mut url = format!("{}/{}", base.trim_end_matches('/'), path);
+        if let Some(query) = query {
+            url.push('?');
+            url.push_str(query);
+        }
+        return url;
+    };
+
+    let joined = format!("{}/{}", url.path().trim_end_matches('/'), path);
+    url.set_path(&joined);
+    url.set_query(query);
+    url.into()
+}
+
+// SOQL datetime literals are unquoted and must include a timezone
+pub fn soql_datetime(datetime: &DateTime<Utc>) -> String {
+    datetime.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+// Formats a JSON value as a SOQL literal for comparison in a WHERE clause
+#[allow(clippy::result_large_err)]
+pub fn soql_literal(value: &Value) -> SfResult<String> {
+    match value {
+        Value::Null => Ok("null".to_string()),
+        Value::Bool(value) => Ok(value.to_string()),
+        Value::Number(value) => Ok(value.to_string()),
+        Value::String(value) => Ok(format!(
+            "'{}'",
+            value.replace('\\', "\\\\").replace('\'', "\\'")
+        )),
+        value => Err(Error::InvalidInput(format!(
+            "{} can not be used as a SOQL literal",
+            value
+        ))),
+    }
+}
+
+// Maximum number of bytes of an unexpected body to retain in an error
+const BODY_SNIPPET_LEN: usize = 512;
+
+pub fn body_snippet(body: &str) -> String {
+    if body.len() <= BODY_SNIPPET_LEN {
+        body.to_string()
+    } else {
+        let mut end = BODY_SNIPPET_LEN;
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}...", &body[..end])
+    }
+}
+
+pub async fn handle_response<T>(
+    response: Response,
+    expected_statuses: &[StatusCode],
+) -> SfResult<SfResponse<T>>
+where
+    T: DeserializeOwned + 'static,
+{
+    let headers = response.headers().clone();
+    let status = response.status();
+    let path = response.url().path().to_string();
+
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return Err(Error::RateLimited {
+            retry_after: retry_after(&headers),
+        });
+    }
+
+    if status == StatusCode::PAYLOAD_TOO_LARGE {
+        return Err(Error::PayloadTooLarge { path });
+    }
+
+    let body = response.text().await?;
+
+    // Maintenance pages and intercepting proxies respond with HTML rather than JSON. Report these
+    // directly instead of as a failure to deserialize
+    if let Some(content_type) = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    {
+        if !body.is_empty() && !content_type.contains("json") {
+            return Err(Error::NonJsonResponse {
+                content_type: content_type.to_string(),
+                body_snippet: body_snippet(&body),
+            });
+        }
+    }
+
+    if expected_statuses.contains(&status) {
+        // Requests that expect no content have a body of `Some(())` whatever the response contains
+        let body = if is_unit::<T>() {
+            unit()
+        } else if !body.is_empty() {
+            Some(deser_body(&body)?)
+        } else if status == StatusCode::NO_CONTENT || status == StatusCode::ACCEPTED {
+            None
+        } else {
+            return Err(Error::EmptyBody {
+                status,
+                path: Some(path),
+            });
+        };
+
+        Ok(SfResponse {
+            headers,
+            status,
+            body,
+        })
+    } else {
+        Err(SfResponse {
+            headers,
+            status,
+            body: if body.is_empty() {
+                None
+            } else {
+                Some(deser_errors(&body)?)
+            },
+        })?
+    }
+}
+
+// Converts a response that was not successful into an error, for resources that are not handled
+// by handle_response such as CSV results or HEAD requests
+pub async fn failure(response: Response) -> Error {
+    let headers = response.headers().clone();
+    let status = response.status();
+
+    match handle_response::<()>(response, &[]).await {
+        Err(err) => err,
+        Ok(_) => Error::UnknownApiFailure(SfResponse {
+            headers,
+            status,
+            body: None,
+        }),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::util::is_unit;
+    use reqwest::StatusCode;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        error::Error,
+        util::{
+            body_snippet, deser_body, deser_body_seed, deser_errors, handle_response, is_unit,
+            join_url, retry_after, soql_literal,
+        },
+        SfApiError,
+    };
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+    use std::time::Duration;
 
     #[test]
     fn test_types() {
         assert!(is_unit::<()>());
         assert!(!is_unit::<String>());
     }
+
+    #[tokio::test]
+    async fn test_handle_response_rejects_unexpected_status() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/resource"))
+            .respond_with(
+                ResponseTemplate::new(201).set_body_json(serde_json::json!([{
+                    "errorCode": "UNEXPECTED",
+                    "message": "Unexpected"
+                }])),
+            )
+            .mount(&server)
+            .await;
+
+        let response = reqwest::get(format!("{}/resource", server.uri()))
+            .await
+            .unwrap();
+        let err = handle_response::<serde_json::Value>(response, &[StatusCode::OK])
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(err, Error::ApiFailure(ref response) if response.status == StatusCode::CREATED)
+        );
+    }
+
+    #[test]
+    fn test_soql_literal() {
+        assert_eq!("null", soql_literal(&serde_json::json!(null)).unwrap());
+        assert_eq!("true", soql_literal(&serde_json::json!(true)).unwrap());
+        assert_eq!("12.5", soql_literal(&serde_json::json!(12.5)).unwrap());
+        assert_eq!(
+            r"'O\'Brien \\ Sons'",
+            soql_literal(&serde_json::json!(r"O'Brien \ Sons")).unwrap()
+        );
+        assert!(matches!(
+            soql_literal(&serde_json::json!([1])),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_deser_body() {
+        let value: serde_json::Value = deser_body(r#"{ "Name": "Acme", "Count": 2 }"#).unwrap();
+        assert_eq!(serde_json::json!({ "Name": "Acme", "Count": 2 }), value);
+
+        assert!(matches!(
+            deser_body::<serde_json::Value>("{ \"Name\": "),
+            Err(Error::UnexpectedBody { .. })
+        ));
+    }
+
+    #[test]
+    fn test_deser_body_seed() {
+        let seed = std::marker::PhantomData::<serde_json::Value>;
+
+        let value = deser_body_seed(r#"{ "Name": "Acme" }"#, seed).unwrap();
+        assert_eq!(serde_json::json!({ "Name": "Acme" }), value);
+
+        assert!(matches!(
+            deser_body_seed(r#"{ "Name": "Acme" } {}"#, seed),
+            Err(Error::UnexpectedBody { .. })
+        ));
+    }
+
+    #[test]
+    fn test_deser_errors() {
+        let expected = vec![SfApiError {
+            error_code: "NOT_FOUND".to_string(),
+            message: "Not found".to_string(),
+        }];
+
+        assert_eq!(
+            expected,
+            deser_errors(r#"[{ "errorCode": "NOT_FOUND", "message": "Not found" }]"#).unwrap()
+        );
+        assert_eq!(
+            expected,
+            deser_errors(r#"{ "errorCode": "NOT_FOUND", "message": "Not found" }"#).unwrap()
+        );
+        assert!(matches!(
+            deser_errors(r#"{ "error": "invalid_grant" }"#),
+            Err(Error::UnexpectedBody { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_handle_response_single_error_object() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/resource"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "errorCode": "INVALIDJOB",
+                "message": "Job is not open"
+            })))
+            .mount(&server)
+            .await;
+
+        let response = reqwest::get(format!("{}/resource", server.uri()))
+            .await
+            .unwrap();
+        let err = handle_response::<serde_json::Value>(response, &[StatusCode::OK])
+            .await
+            .unwrap_err();
+
+        match err {
+            Error::ApiFailure(response) => {
+                assert_eq!("INVALIDJOB", response.body.unwrap()[0].error_code)
+            }
+            err => panic!("Unexpected error {:?}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_response_empty_unit_body() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/resource"))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+
+        let response = reqwest::get(format!("{}/resource", server.uri()))
+            .await
+            .unwrap();
+        let response = handle_response::<()>(response, &[StatusCode::NO_CONTENT])
+            .await
+            .unwrap();
+
+        assert_eq!(StatusCode::NO_CONTENT, response.status);
+        assert_eq!(Some(()), response.body);
+    }
+
+    #[test]
+    fn test_body_snippet_truncates_on_char_boundary() {
+        assert_eq!("short", body_snippet("short"));
+
+        let body = "é".repeat(400);
+        let snippet = body_snippet(&body);
+
+        assert!(snippet.ends_with("..."));
+        assert_eq!(512 + 3, snippet.len());
+    }
+
+    #[tokio::test]
+    async fn test_handle_response_html_body() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/resource"))
+            .respond_with(ResponseTemplate::new(503).set_body_raw(
+                "<html><body>Down for maintenance</body></html>",
+                "text/html",
+            ))
+            .mount(&server)
+            .await;
+
+        let response = reqwest::get(format!("{}/resource", server.uri()))
+            .await
+            .unwrap();
+        let err = handle_response::<serde_json::Value>(response, &[StatusCode::OK])
+            .await
+            .unwrap_err();
+
+        match err {
+            Error::NonJsonResponse {
+                content_type,
+                body_snippet,
+            } => {
+                assert_eq!("text/html", content_type);
+                assert_eq!(
+                    "<html><body>Down for maintenance</body></html>",
+                    body_snippet
+                );
+            }
+            err => panic!("Unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(None, retry_after(&headers));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(Some(Duration::from_secs(120)), retry_after(&headers));
+
+        // Dates in the past do not require waiting
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(Some(Duration::ZERO), retry_after(&headers));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(None, retry_after(&headers));
+    }
+
+    #[tokio::test]
+    async fn test_handle_response_rate_limited() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/resource"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "30"))
+            .mount(&server)
+            .await;
+
+        let response = reqwest::get(format!("{}/resource", server.uri()))
+            .await
+            .unwrap();
+        let err = handle_response::<serde_json::Value>(response, &[StatusCode::OK])
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::RateLimited {
+                retry_after: Some(duration)
+            } if duration == Duration::from_secs(30)
+        ));
+    }
+
+    #[test]
+    fn test_join_url() {
+        for base in [
+            "https://acme.my.salesforce.com",
+            "https://acme.my.salesforce.com/",
+        ] {
+            for path in ["services/data/", "/services/data/"] {
+                assert_eq!(
+                    "https://acme.my.salesforce.com/services/data/",
+                    join_url(base, path)
+                );
+            }
+        }
+
+        assert_eq!(
+            "https://acme.my.salesforce.com/",
+            join_url("https://acme.my.salesforce.com", "")
+        );
+        assert_eq!(
+            "https://acme.my.salesforce.com/site/services/data/v58.0/query/?q=SELECT+Id",
+            join_url(
+                "https://acme.my.salesforce.com/site/",
+                "/services/data/v58.0/query/?q=SELECT+Id"
+            )
+        );
+        // Reserved characters are encoded, while existing encodings are kept
+        assert_eq!(
+            "https://acme.my.salesforce.com/sobjects/Lead/a%20b%2Fc",
+            join_url("https://acme.my.salesforce.com", "sobjects/Lead/a b%2Fc")
+        );
+        // Bases that are not urls are joined as strings
+        assert_eq!("acme/services", join_url("acme//", "//services"));
+    }
 }
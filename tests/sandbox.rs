@@ -14,12 +14,12 @@ use sf_client::{
         AuthorizationServer,
     },
     error::Error,
-    ExternalId, SfClient,
+    ExternalId, SfClient, SfResponse,
 };
 use std::env::var;
 
 fn tvar(name: &str) -> String {
-    var(name).expect(&format!("Failed to find expected variable {}", name))
+    var(name).unwrap_or_else(|_| panic!("Failed to find expected variable {}", name))
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -68,7 +68,8 @@ async fn test_lead_crud() {
         AuthorizationServer::Test,
         tvar("SUBJECT"),
     );
-    let mut authenticator = JwtAuthenticator::new(&tvar("INSTANCE_DOMAIN"), claims, vec![]);
+    let mut authenticator =
+        JwtAuthenticator::new(&tvar("INSTANCE_DOMAIN"), claims, vec![]).unwrap();
     authenticator.load_rsa_pem("sf_test.key").unwrap();
 
     let client = SfClient::new(tvar("VERSION"), authenticator)
@@ -90,8 +91,7 @@ async fn test_lead_crud() {
             },
         )
         .await
-        .unwrap()
-        .body
+        .and_then(SfResponse::into_body)
         .unwrap()
         .id
         .unwrap();
@@ -117,8 +117,7 @@ async fn test_lead_crud() {
     let lead = client
         .get_object::<Lead>("Lead", &object)
         .await
-        .unwrap()
-        .body
+        .and_then(SfResponse::into_body)
         .unwrap();
 
     assert_eq!("First_2", lead.first_name);
@@ -136,7 +135,8 @@ async fn test_lead_crud() {
             &ExternalId::new(
                 "External_Service_Record_Id__c".to_string(),
                 "external_12345_2".to_string(),
-            ),
+            )
+            .unwrap(),
             &LeadUpsert {
                 first_name: "First_3".to_string(),
                 last_name: "Last_3".to_string(),